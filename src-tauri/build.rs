@@ -1,3 +1,5 @@
 fn main() {
+    // The headless CLI builds without Tauri
+    #[cfg(feature = "desktop")]
     tauri_build::build()
 }
@@ -0,0 +1,623 @@
+//! Matching an LLM's corrected transcript back to Whisper's words, so corrections keep real timings.
+//!
+//! The corrected text is aligned with the original words by character edit distance, where one corrected
+//! word may also stand for a run of fragments ("nego cios" → "negocios") or the reverse.
+//! Every difference becomes a `TranscriptChange`; applying all or some of them to the original segments
+//! gives the corrected transcript.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use crate::glossary::{edit_distance, normalized};
+use crate::models::{ChangeKind, Confidence, Segment, TranscriptChange, Word};
+
+/// Most fragments one corrected word may join, or pieces one original word may split into
+const MAX_FRAGMENTS: usize = 4;
+
+/// Extra cost, in characters, of each fragment joined; a word the LLM dropped next to one it respelled
+/// stays a deletion unless joining it explains the letters better
+const JOIN_COST: f64 = 0.5;
+
+/// How far, in words, the alignment may stray from the expected path; bounds time and memory on long transcripts
+const ALIGNMENT_BAND: usize = 100;
+
+/// Inserted words get at least this long each, borrowing from their neighbours when the gap is shorter
+const MIN_INSERTED_WORD_SECONDS: f64 = 0.1;
+
+/// How original words map onto corrected ones
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Step {
+    /// One original word, one corrected word
+    Pair,
+    /// Several original words, one corrected word
+    Merge(usize),
+    /// One original word, several corrected words
+    Split(usize),
+    Delete,
+    Insert,
+}
+
+/// Cheapest way found to align some original words with some corrected ones
+#[derive(Debug, Clone, Copy)]
+struct Cell {
+    cost: f64,
+    step: Step,
+}
+
+impl Cell {
+    const UNREACHED: Cell = Cell {
+        cost: f64::INFINITY,
+        step: Step::Pair,
+    };
+
+    /// Keeps the earlier candidate on ties, so a plain pair beats an equally good join
+    fn consider(&mut self, cost: f64, step: Step) {
+        if cost < self.cost {
+            *self = Cell { cost, step };
+        }
+    }
+}
+
+/// Cheapest alignment cost up to each pair of word counts, within the band around the expected path
+struct Table {
+    rows: Vec<(usize, Vec<Cell>)>,
+}
+
+impl Table {
+    fn cost(&self, i: usize, j: usize) -> f64 {
+        self.rows
+            .get(i)
+            .and_then(|(first, row)| row.get(j.checked_sub(*first)?))
+            .map_or(f64::INFINITY, |cell| cell.cost)
+    }
+
+    fn step(&self, i: usize, j: usize) -> Step {
+        let (first, row) = &self.rows[i];
+        row[j - first].step
+    }
+}
+
+/// Changes that turn `segments` into `corrected_text`
+pub fn align(segments: &[Segment], corrected_text: &str) -> Vec<TranscriptChange> {
+    let originals = flatten(segments);
+    let corrected: Vec<&str> = corrected_text.split_whitespace().collect();
+    if originals.is_empty() || corrected.is_empty() {
+        return Vec::new();
+    }
+
+    let original_keys: Vec<Vec<char>> = originals.iter().map(|(_, w)| normalized(&w.word)).collect();
+    let corrected_keys: Vec<Vec<char>> = corrected.iter().map(|w| normalized(w)).collect();
+    let steps = cheapest_steps(&original_keys, &corrected_keys);
+
+    let mut changes: Vec<TranscriptChange> = Vec::new();
+    let (mut i, mut j) = (0, 0);
+
+    for step in steps {
+        let (original_count, corrected_count) = match step {
+            Step::Pair => (1, 1),
+            Step::Merge(n) => (n, 1),
+            Step::Split(n) => (1, n),
+            Step::Delete => (1, 0),
+            Step::Insert => (0, 1),
+        };
+        let original_words: Vec<Word> = originals[i..i + original_count].iter().map(|(_, w)| (*w).clone()).collect();
+        let corrected_words = &corrected[j..j + corrected_count];
+        let id = changes.len();
+
+        match step {
+            Step::Pair if original_words[0].word.trim() == corrected_words[0] => {}
+            Step::Pair | Step::Merge(_) => changes.push(TranscriptChange {
+                id,
+                kind: if original_count == 1 { ChangeKind::Replace } else { ChangeKind::Merge },
+                segment_id: originals[i].0,
+                position: i,
+                corrected: vec![Word {
+                    id: original_words[0].id.clone(),
+                    word: corrected_words[0].to_string(),
+                    start: original_words[0].start,
+                    end: original_words[original_count - 1].end,
+                    confidence: combined_confidence(&original_words),
+                }],
+                original: original_words,
+            }),
+            Step::Split(_) => changes.push(TranscriptChange {
+                id,
+                kind: ChangeKind::Split,
+                segment_id: originals[i].0,
+                position: i,
+                corrected: split_word(&original_words[0], corrected_words),
+                original: original_words,
+            }),
+            Step::Delete => match changes.last_mut() {
+                Some(last) if last.kind == ChangeKind::Delete && last.position + last.original.len() == i => {
+                    last.original.extend(original_words);
+                }
+                _ => changes.push(TranscriptChange {
+                    id,
+                    kind: ChangeKind::Delete,
+                    segment_id: originals[i].0,
+                    position: i,
+                    original: original_words,
+                    corrected: Vec::new(),
+                }),
+            },
+            Step::Insert => {
+                let word = Word {
+                    id: String::new(),
+                    word: corrected_words[0].to_string(),
+                    start: 0.0,
+                    end: 0.0,
+                    confidence: None,
+                };
+                match changes.last_mut() {
+                    Some(last) if last.kind == ChangeKind::Insert && last.position == i => last.corrected.push(word),
+                    _ => changes.push(TranscriptChange {
+                        id,
+                        kind: ChangeKind::Insert,
+                        // Inserted words end the previous word's segment
+                        segment_id: originals[i.saturating_sub(1).min(originals.len() - 1)].0,
+                        position: i,
+                        original: Vec::new(),
+                        corrected: vec![word],
+                    }),
+                }
+            }
+        }
+
+        i += original_count;
+        j += corrected_count;
+    }
+
+    for change in changes.iter_mut().filter(|c| c.kind == ChangeKind::Insert) {
+        let previous = change.position.checked_sub(1).map(|p| originals[p].1);
+        let next = originals.get(change.position).map(|(_, w)| *w);
+        place_inserted(&mut change.corrected, previous, next);
+    }
+    renumber(&mut changes);
+
+    eprintln!(
+        "🔗 Aligned {} corrected words with {} original words: {} changes",
+        corrected.len(),
+        originals.len(),
+        changes.len()
+    );
+
+    changes
+}
+
+/// Number changes in order and give the words they add ids of their own
+pub fn renumber(changes: &mut [TranscriptChange]) {
+    for (index, change) in changes.iter_mut().enumerate() {
+        change.id = index;
+        for (k, word) in change.corrected.iter_mut().enumerate() {
+            // A split's first piece and replaced or merged words keep the original word's id
+            let added = change.kind == ChangeKind::Insert || (change.kind == ChangeKind::Split && k > 0);
+            if added {
+                word.id = format!("c{}.{}", index, k);
+            }
+        }
+    }
+}
+
+/// `segments` with the given changes applied; the rest of the transcript stays as Whisper produced it
+pub fn apply_changes(segments: &[Segment], changes: &[TranscriptChange]) -> Result<Vec<Segment>, String> {
+    let originals = flatten(segments);
+
+    let mut inserts: BTreeMap<usize, &TranscriptChange> = BTreeMap::new();
+    let mut edits: BTreeMap<usize, &TranscriptChange> = BTreeMap::new();
+    for change in changes {
+        let end = change.position + change.original.len();
+        let matches = end <= originals.len()
+            && change.original.iter().zip(&originals[change.position..end]).all(|(a, (_, b))| a.id == b.id);
+        if !matches {
+            return Err(format!("Change {} doesn't match the transcript", change.id));
+        }
+
+        let target = if change.original.is_empty() { &mut inserts } else { &mut edits };
+        if target.insert(change.position, change).is_some() {
+            return Err(format!("Change {} overlaps another change", change.id));
+        }
+    }
+
+    // An edit may not start inside another, nor an insert land inside one
+    let mut covered_until = 0;
+    for (&position, change) in &edits {
+        if position < covered_until {
+            return Err(format!("Change {} overlaps another change", change.id));
+        }
+        covered_until = position + change.original.len();
+        if let Some((_, insert)) = inserts.range(position + 1..covered_until).next() {
+            return Err(format!("Change {} overlaps another change", insert.id));
+        }
+    }
+
+    let mut words: BTreeMap<usize, Vec<Word>> = BTreeMap::new();
+    let mut changed: HashSet<usize> = HashSet::new();
+    let mut i = 0;
+    loop {
+        if let Some(change) = inserts.get(&i) {
+            words.entry(change.segment_id).or_default().extend(change.corrected.iter().cloned());
+            changed.insert(change.segment_id);
+        }
+        if i >= originals.len() {
+            break;
+        }
+
+        if let Some(change) = edits.get(&i) {
+            words.entry(change.segment_id).or_default().extend(change.corrected.iter().cloned());
+            changed.insert(change.segment_id);
+            changed.extend(originals[i..i + change.original.len()].iter().map(|(segment_id, _)| *segment_id));
+            i += change.original.len();
+        } else {
+            let (segment_id, word) = originals[i];
+            words.entry(segment_id).or_default().push(word.clone());
+            i += 1;
+        }
+    }
+
+    let mut result: Vec<Segment> = Vec::with_capacity(segments.len());
+    for segment in segments {
+        if !changed.contains(&segment.id) {
+            result.push(segment.clone());
+            continue;
+        }
+
+        let segment_words = words.remove(&segment.id).unwrap_or_default();
+        let (Some(first), Some(last)) = (segment_words.first(), segment_words.last()) else {
+            // Every word was deleted
+            continue;
+        };
+
+        // Keep Whisper's leading space
+        let leading = if segment.text.starts_with(' ') { " " } else { "" };
+        result.push(Segment {
+            start: segment.start.min(first.start),
+            end: segment.end.max(last.end),
+            text: format!(
+                "{}{}",
+                leading,
+                segment_words.iter().map(|w| w.word.as_str()).collect::<Vec<_>>().join(" ")
+            ),
+            words: segment_words,
+            ..segment.clone()
+        });
+    }
+
+    // A word joined across a segment boundary pushes the next segment back
+    for k in 1..result.len() {
+        let previous_end = result[k - 1].end;
+        let segment = &mut result[k];
+        if segment.start < previous_end {
+            let first_word = segment.words.first().map_or(segment.end, |w| w.start);
+            segment.start = previous_end.min(first_word);
+        }
+    }
+
+    Ok(result)
+}
+
+/// Every word of the transcript with the id of its segment
+fn flatten(segments: &[Segment]) -> Vec<(usize, &Word)> {
+    segments.iter().flat_map(|s| s.words.iter().map(move |w| (s.id, w))).collect()
+}
+
+/// Steps of the cheapest alignment of `original` with `corrected`, from the start
+fn cheapest_steps(original: &[Vec<char>], corrected: &[Vec<char>]) -> Vec<Step> {
+    let mut table = Table {
+        rows: Vec::with_capacity(original.len() + 1),
+    };
+
+    for (i, (first, last)) in band(original, corrected).into_iter().enumerate() {
+        table.rows.push((first, vec![Cell::UNREACHED; last - first + 1]));
+
+        for j in first..=last {
+            if i == 0 && j == 0 {
+                table.rows[0].1[0].cost = 0.0;
+                continue;
+            }
+
+            let mut best = Cell::UNREACHED;
+
+            if i > 0 && j > 0 {
+                let previous = table.cost(i - 1, j - 1);
+                if previous + length_gap(&original[i - 1..i], &corrected[j - 1..j]) < best.cost {
+                    best.consider(previous + distance(&original[i - 1], &corrected[j - 1]), Step::Pair);
+                }
+            }
+            if i > 0 {
+                best.consider(table.cost(i - 1, j) + word_cost(&original[i - 1]), Step::Delete);
+            }
+            if j > 0 {
+                best.consider(table.cost(i, j - 1) + word_cost(&corrected[j - 1]), Step::Insert);
+            }
+            for n in (2..=MAX_FRAGMENTS.min(i)).filter(|_| j > 0) {
+                let previous = table.cost(i - n, j - 1) + JOIN_COST * (n - 1) as f64;
+                if previous + length_gap(&original[i - n..i], &corrected[j - 1..j]) < best.cost {
+                    let joined: Vec<char> = original[i - n..i].concat();
+                    best.consider(previous + distance(&joined, &corrected[j - 1]), Step::Merge(n));
+                }
+            }
+            for n in (2..=MAX_FRAGMENTS.min(j)).filter(|_| i > 0) {
+                let previous = table.cost(i - 1, j - n) + JOIN_COST * (n - 1) as f64;
+                if previous + length_gap(&original[i - 1..i], &corrected[j - n..j]) < best.cost {
+                    let joined: Vec<char> = corrected[j - n..j].concat();
+                    best.consider(previous + distance(&original[i - 1], &joined), Step::Split(n));
+                }
+            }
+
+            table.rows[i].1[j - first] = best;
+        }
+    }
+
+    let mut steps = Vec::new();
+    let (mut i, mut j) = (original.len(), corrected.len());
+    while i > 0 || j > 0 {
+        let step = table.step(i, j);
+        match step {
+            Step::Pair => (i, j) = (i - 1, j - 1),
+            Step::Merge(n) => (i, j) = (i - n, j - 1),
+            Step::Split(n) => (i, j) = (i - 1, j - n),
+            Step::Delete => i -= 1,
+            Step::Insert => j -= 1,
+        }
+        steps.push(step);
+    }
+    steps.reverse();
+    steps
+}
+
+/// Corrected word counts considered against each count of original words: a band around a path through
+/// the words both sides have exactly once, so a reply that drops or adds a long stretch (a truncated one)
+/// still aligns. Each row overlaps the one before, so the end is always reachable.
+fn band(original: &[Vec<char>], corrected: &[Vec<char>]) -> Vec<(usize, usize)> {
+    let anchors = anchors(original, corrected);
+    let mut ranges: Vec<(usize, usize)> = Vec::with_capacity(original.len() + 1);
+    let mut segment = 0;
+
+    for i in 0..=original.len() {
+        while anchors[segment + 1].0 < i {
+            segment += 1;
+        }
+        let ((i0, j0), (i1, j1)) = (anchors[segment], anchors[segment + 1]);
+        let center = j0 + ((i - i0) * (j1 - j0)).checked_div(i1 - i0).unwrap_or(0);
+
+        let mut first = center.saturating_sub(ALIGNMENT_BAND);
+        let mut last = (center + ALIGNMENT_BAND).min(corrected.len());
+        if let Some(&(previous_first, previous_last)) = ranges.last() {
+            first = first.clamp(previous_first, previous_last);
+            last = last.max(first);
+        }
+        if i == original.len() {
+            last = corrected.len();
+        }
+        ranges.push((first, last));
+    }
+    ranges
+}
+
+/// Points the alignment surely passes through, as (original, corrected) word counts: the start, the end,
+/// and after each word that appears exactly once on both sides, keeping the longest run in order
+fn anchors(original: &[Vec<char>], corrected: &[Vec<char>]) -> Vec<(usize, usize)> {
+    // Occurrences in the original and in the corrected text, and the last position in the corrected text
+    let mut counts: HashMap<&[char], (usize, usize, usize)> = HashMap::new();
+    for word in original.iter().filter(|w| !w.is_empty()) {
+        counts.entry(word).or_default().0 += 1;
+    }
+    for (j, word) in corrected.iter().enumerate().filter(|(_, w)| !w.is_empty()) {
+        let entry = counts.entry(word).or_default();
+        entry.1 += 1;
+        entry.2 = j;
+    }
+    let matches: Vec<(usize, usize)> = original
+        .iter()
+        .enumerate()
+        .filter_map(|(i, word)| match counts.get(word.as_slice()) {
+            Some(&(1, 1, j)) => Some((i, j)),
+            _ => None,
+        })
+        .collect();
+
+    // Longest increasing run of corrected positions, by patience sorting
+    let mut tails: Vec<usize> = Vec::new();
+    let mut previous: Vec<Option<usize>> = vec![None; matches.len()];
+    for (k, &(_, j)) in matches.iter().enumerate() {
+        let pile = tails.partition_point(|&t| matches[t].1 < j);
+        previous[k] = pile.checked_sub(1).map(|p| tails[p]);
+        if pile == tails.len() {
+            tails.push(k);
+        } else {
+            tails[pile] = k;
+        }
+    }
+    let mut run = Vec::new();
+    let mut next = tails.last().copied();
+    while let Some(k) = next {
+        run.push((matches[k].0 + 1, matches[k].1 + 1));
+        next = previous[k];
+    }
+
+    let mut anchors = vec![(0, 0)];
+    anchors.extend(run.into_iter().rev());
+    anchors.push((original.len(), corrected.len()));
+    anchors
+}
+
+/// Characters to add, remove or change to turn one word into the other
+fn distance(a: &[char], b: &[char]) -> f64 {
+    edit_distance(a, b) as f64
+}
+
+/// Cost of deleting or inserting a whole word; punctuation-only words still count
+fn word_cost(word: &[char]) -> f64 {
+    word.len().max(1) as f64
+}
+
+/// Lower bound of `distance` between the joined words, to skip hopeless candidates cheaply
+fn length_gap(a: &[Vec<char>], b: &[Vec<char>]) -> f64 {
+    let len = |words: &[Vec<char>]| words.iter().map(Vec::len).sum::<usize>();
+    len(a).abs_diff(len(b)) as f64
+}
+
+/// Mean over the words' means and lowest minimum; None if any word has none
+fn combined_confidence(words: &[Word]) -> Option<Confidence> {
+    let confidences: Option<Vec<Confidence>> = words.iter().map(|w| w.confidence).collect();
+    confidences.filter(|c| !c.is_empty()).map(|c| Confidence {
+        min: c.iter().map(|c| c.min).fold(f64::INFINITY, f64::min),
+        mean: c.iter().map(|c| c.mean).sum::<f64>() / c.len() as f64,
+    })
+}
+
+/// One word's time shared among its pieces by length
+fn split_word(word: &Word, pieces: &[&str]) -> Vec<Word> {
+    let weights: Vec<f64> = pieces.iter().map(|p| normalized(p).len().max(1) as f64).collect();
+    let total: f64 = weights.iter().sum();
+    let duration = word.end - word.start;
+
+    let mut start = word.start;
+    pieces
+        .iter()
+        .zip(&weights)
+        .enumerate()
+        .map(|(k, (piece, weight))| {
+            let end = if k == pieces.len() - 1 { word.end } else { start + duration * weight / total };
+            let piece = Word {
+                id: word.id.clone(),
+                word: piece.to_string(),
+                start,
+                end,
+                confidence: word.confidence,
+            };
+            start = end;
+            piece
+        })
+        .collect()
+}
+
+/// Spread inserted words over the gap between their neighbours, or over the neighbours' closer halves
+/// when the gap is too short for them
+fn place_inserted(words: &mut [Word], previous: Option<&Word>, next: Option<&Word>) {
+    let (mut from, mut to) = match (previous, next) {
+        (Some(p), Some(n)) => (p.end, n.start.max(p.end)),
+        (Some(p), None) => (p.end, p.end),
+        (None, Some(n)) => (n.start, n.start),
+        (None, None) => (0.0, 0.0),
+    };
+    if to - from < MIN_INSERTED_WORD_SECONDS * words.len() as f64 {
+        match (previous, next) {
+            (Some(p), Some(n)) => (from, to) = ((p.start + p.end) / 2.0, (n.start + n.end) / 2.0),
+            (Some(p), None) => from = (p.start + p.end) / 2.0,
+            (None, Some(n)) => to = (n.start + n.end) / 2.0,
+            (None, None) => {}
+        }
+    }
+
+    let step = (to - from) / words.len() as f64;
+    for (k, word) in words.iter_mut().enumerate() {
+        word.start = from + step * k as f64;
+        word.end = from + step * (k + 1) as f64;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Segments of 0.5s words, one per string, numbered in order
+    fn segments(texts: &[&str]) -> Vec<Segment> {
+        let mut time = 0.0;
+        texts
+            .iter()
+            .enumerate()
+            .map(|(id, text)| {
+                let start = time;
+                let words: Vec<Word> = text
+                    .split_whitespace()
+                    .map(|word| {
+                        time += 0.5;
+                        Word {
+                            id: format!("w{}", time * 2.0),
+                            word: word.to_string(),
+                            start: time - 0.5,
+                            end: time,
+                            confidence: None,
+                        }
+                    })
+                    .collect();
+                Segment {
+                    id,
+                    start,
+                    end: time,
+                    text: format!(" {}", text),
+                    words,
+                    confidence: None,
+                    avg_logprob: None,
+                }
+            })
+            .collect()
+    }
+
+    fn text(words: &[Word]) -> String {
+        words.iter().map(|w| w.word.as_str()).collect::<Vec<_>>().join(" ")
+    }
+
+    #[test]
+    fn truncated_reply_is_one_deletion() {
+        let words: Vec<String> = (0..1000).map(|n| format!("kilo{}", n)).collect();
+        let texts: Vec<String> = words.chunks(100).map(|chunk| chunk.join(" ")).collect();
+        let texts: Vec<&str> = texts.iter().map(String::as_str).collect();
+
+        let changes = align(&segments(&texts), &words[..700].join(" "));
+
+        assert_eq!(changes.len(), 1, "{:?}", changes.iter().map(|c| c.kind).collect::<Vec<_>>());
+        assert_eq!(changes[0].kind, ChangeKind::Delete);
+        assert_eq!(changes[0].position, 700);
+        assert_eq!(changes[0].original.len(), 300);
+    }
+
+    #[test]
+    fn one_word_is_split_over_its_own_time() {
+        let segments = segments(&["I like alot of it"]);
+        let changes = align(&segments, "I like a lot of it");
+
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].kind, ChangeKind::Split);
+        assert_eq!(text(&changes[0].corrected), "a lot");
+        let original = &segments[0].words[2];
+        assert_eq!(changes[0].corrected[0].start, original.start);
+        assert_eq!(changes[0].corrected[1].end, original.end);
+        assert_eq!(changes[0].corrected[0].id, original.id);
+        assert_eq!(changes[0].corrected[1].id, "c0.1");
+    }
+
+    #[test]
+    fn words_are_inserted_at_the_start_and_end() {
+        let segments = segments(&["hello there", "how are you"]);
+        let changes = align(&segments, "well hello there how are you today");
+
+        assert_eq!(changes.len(), 2);
+        assert_eq!((changes[0].kind, changes[0].position, changes[0].segment_id), (ChangeKind::Insert, 0, 0));
+        assert_eq!((changes[1].kind, changes[1].position, changes[1].segment_id), (ChangeKind::Insert, 5, 1));
+        assert!(changes[0].corrected[0].end <= segments[0].words[0].end);
+        assert!(changes[1].corrected[0].start >= segments[1].words[1].start);
+
+        let corrected = apply_changes(&segments, &changes).unwrap();
+        assert_eq!(corrected[0].text, " well hello there");
+        assert_eq!(corrected[1].text, " how are you today");
+    }
+
+    #[test]
+    fn fragments_are_merged_across_segments() {
+        let segments = segments(&["los nego", "cios van bien"]);
+        let changes = align(&segments, "los negocios van bien");
+
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].kind, ChangeKind::Merge);
+        assert_eq!((changes[0].position, changes[0].segment_id), (1, 0));
+        assert_eq!(text(&changes[0].original), "nego cios");
+        assert_eq!((changes[0].corrected[0].start, changes[0].corrected[0].end), (0.5, 1.5));
+
+        let corrected = apply_changes(&segments, &changes).unwrap();
+        assert_eq!(corrected[0].text, " los negocios");
+        assert_eq!(corrected[1].text, " van bien");
+        assert_eq!(corrected[1].start, corrected[0].end);
+    }
+}
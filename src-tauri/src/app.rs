@@ -0,0 +1,410 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use crate::job::CancelToken;
+use crate::models::{
+    Chapter, ChapterFormat, ExportSettings, LlmProviderConfig, MediaInfo, ModelVerification, PipelineConfig,
+    PipelineEvent, Segment, SubtitleFormat, SubtitleSettings, TranscriptChange, TranscriptResult, VideoEncoderInfo,
+    VideoMetadata, WhisperModelInfo, WhisperParams, WhisperPreset, Word,
+};
+use crate::prompts::{PromptTask, PromptTemplates};
+use crate::timeline::Timeline;
+use crate::transcribe::{TranscribeOptions, Transcriber};
+use crate::transcript_cache::TranscriptCache;
+use crate::whisper_models::ModelManager;
+use crate::{align, encoder, ffmpeg, llm, metadata, output_paths, pipeline, probe, subtitles, transcribe, words};
+use tauri::{Emitter, Manager};
+
+/// Running pipeline jobs, keyed by the id handed back to the frontend
+#[derive(Default)]
+struct JobRegistry {
+    next_id: AtomicU64,
+    jobs: Mutex<HashMap<String, CancelToken>>,
+}
+
+impl JobRegistry {
+    fn register(&self) -> (String, CancelToken) {
+        let job_id = format!("job-{}", self.next_id.fetch_add(1, Ordering::SeqCst) + 1);
+        let cancel = CancelToken::new();
+        self.lock().insert(job_id.clone(), cancel.clone());
+        (job_id, cancel)
+    }
+
+    /// Register a job under an id the frontend picked, for commands that only return once done
+    fn register_as(&self, job_id: &str) -> Result<CancelToken, String> {
+        let mut jobs = self.lock();
+        if jobs.contains_key(job_id) {
+            return Err(format!("A job with id {} is already running", job_id));
+        }
+        let cancel = CancelToken::new();
+        jobs.insert(job_id.to_string(), cancel.clone());
+        Ok(cancel)
+    }
+
+    /// Returns false if no job with this id is running
+    fn cancel(&self, job_id: &str) -> bool {
+        match self.lock().get(job_id) {
+            Some(cancel) => {
+                cancel.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn remove(&self, job_id: &str) {
+        self.lock().remove(job_id);
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, CancelToken>> {
+        self.jobs.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[tauri::command]
+async fn get_ffmpeg_version(app: tauri::AppHandle) -> Result<String, String> {
+    use tauri_plugin_shell::ShellExt;
+
+    let output = app
+        .shell()
+        .sidecar("ffmpeg")
+        .map_err(|e| e.to_string())?
+        .args(["-version"])
+        .output()
+        .await
+        .map_err(|e| e.to_string())?;
+
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    } else {
+        Err(String::from_utf8_lossy(&output.stderr).to_string())
+    }
+}
+
+/// Starts the pipeline in the background and returns its job id.
+/// Progress, the result and failures are all reported through `pipeline-progress` events.
+#[tauri::command]
+async fn process_video(
+    app: tauri::AppHandle,
+    jobs: tauri::State<'_, JobRegistry>,
+    input_path: String,
+    config: PipelineConfig,
+) -> Result<String, String> {
+    let (job_id, cancel) = jobs.register();
+    eprintln!("🚀 Starting pipeline job {} for {}", job_id, input_path);
+
+    let id = job_id.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        run_pipeline_job(&app, &id, &input_path, &config, &cancel);
+        app.state::<JobRegistry>().remove(&id);
+    });
+
+    Ok(job_id)
+}
+
+fn run_pipeline_job(
+    app: &tauri::AppHandle,
+    job_id: &str,
+    input_path: &str,
+    config: &PipelineConfig,
+    cancel: &CancelToken,
+) {
+    let transcriber = app.state::<Transcriber>();
+    let templates = app.state::<PromptTemplates>();
+    let outcome = pipeline::process_video(
+        input_path,
+        config,
+        transcriber.inner(),
+        templates.inner(),
+        cancel,
+        |event: PipelineEvent| app.emit("pipeline-progress", event).map_err(|e| e.to_string()),
+    );
+
+    let event = match outcome {
+        Ok(result) => {
+            eprintln!("🎉 Pipeline completed successfully!");
+            PipelineEvent::PipelineCompleted { result }
+        }
+        Err(_) if cancel.is_cancelled() => {
+            eprintln!("🛑 Pipeline job {} cancelled", job_id);
+            pipeline::clean_up_partial_outputs(input_path);
+            PipelineEvent::PipelineCancelled {
+                job_id: job_id.to_string(),
+            }
+        }
+        Err(error) => {
+            eprintln!("❌ Pipeline failed: {}", error);
+            PipelineEvent::PipelineFailed { error }
+        }
+    };
+
+    if let Err(e) = app.emit("pipeline-progress", event) {
+        eprintln!("⚠️ Failed to emit final pipeline event: {}", e);
+    }
+}
+
+#[tauri::command]
+async fn cancel_job(jobs: tauri::State<'_, JobRegistry>, job_id: String) -> Result<(), String> {
+    if jobs.cancel(&job_id) {
+        eprintln!("🛑 Cancel requested for job {}", job_id);
+        Ok(())
+    } else {
+        Err(format!("No running job with id {}", job_id))
+    }
+}
+
+/// Transcribes for the editor and returns the transcript. `job_id` is picked by the frontend so it can
+/// `cancel_job` while this runs.
+#[tauri::command]
+async fn transcribe_video(
+    app: tauri::AppHandle,
+    jobs: tauri::State<'_, JobRegistry>,
+    input_path: String,
+    config: PipelineConfig,
+    job_id: String,
+) -> Result<TranscriptResult, String> {
+    let cancel = jobs.register_as(&job_id)?;
+    eprintln!("🚀 Starting transcription job {} for {}", job_id, input_path);
+
+    // Whisper, FFmpeg and the LLM cleanup all block, so they stay off the async runtime
+    let outcome = tauri::async_runtime::spawn_blocking(move || {
+        let transcriber = app.state::<Transcriber>();
+        let templates = app.state::<PromptTemplates>();
+        transcribe::transcribe_video_for_editor(
+            transcriber.inner(),
+            &input_path,
+            &TranscribeOptions::from_config(&config),
+            config.llm.as_ref(),
+            templates.inner(),
+            &cancel,
+            |event| app.emit("pipeline-progress", event).map_err(|e| e.to_string()),
+        )
+    })
+    .await;
+    jobs.remove(&job_id);
+
+    outcome.map_err(|e| format!("Transcription task failed: {}", e))?
+}
+
+/// Decoding settings of a named preset, for the options panel
+#[tauri::command]
+async fn whisper_preset(preset: WhisperPreset) -> Result<WhisperParams, String> {
+    Ok(preset.params())
+}
+
+/// Deletes all cached transcripts and returns how many there were
+#[tauri::command]
+async fn clear_transcript_cache(transcriber: tauri::State<'_, Transcriber>) -> Result<usize, String> {
+    transcriber.cache().map_or(Ok(0), |cache| cache.clear())
+}
+
+/// Ids of words Whisper was unsure about, for the editor to flag for review
+#[tauri::command]
+async fn low_confidence_words(words: Vec<Word>, threshold: Option<f64>) -> Result<Vec<String>, String> {
+    let threshold = threshold.unwrap_or(words::LOW_CONFIDENCE_THRESHOLD);
+    Ok(words::low_confidence_words(&words, threshold)
+        .into_iter()
+        .map(|word| word.id.clone())
+        .collect())
+}
+
+/// Whisper's segments with the accepted LLM corrections applied
+#[tauri::command]
+async fn apply_transcript_changes(
+    original_segments: Vec<Segment>,
+    changes: Vec<TranscriptChange>,
+) -> Result<Vec<Segment>, String> {
+    align::apply_changes(&original_segments, &changes)
+}
+
+/// Registry models (installed or not) plus custom models in the models directory
+#[tauri::command]
+async fn list_whisper_models(transcriber: tauri::State<'_, Transcriber>) -> Result<Vec<WhisperModelInfo>, String> {
+    Ok(transcriber.models().list())
+}
+
+/// Copies a GGML model file into the models directory, checking it against its known checksum
+#[tauri::command]
+async fn import_whisper_model(
+    transcriber: tauri::State<'_, Transcriber>,
+    source_path: String,
+) -> Result<WhisperModelInfo, String> {
+    let models = transcriber.models().clone();
+    tauri::async_runtime::spawn_blocking(move || models.import(&source_path))
+        .await
+        .map_err(|e| format!("Model import task failed: {}", e))?
+}
+
+#[tauri::command]
+async fn verify_whisper_model(
+    transcriber: tauri::State<'_, Transcriber>,
+    name: String,
+) -> Result<ModelVerification, String> {
+    let models = transcriber.models().clone();
+    tauri::async_runtime::spawn_blocking(move || models.verify(&name))
+        .await
+        .map_err(|e| format!("Model verification task failed: {}", e))?
+}
+
+#[tauri::command]
+async fn export_edited_video(
+    input_path: String,
+    keep_ranges: Vec<(f64, f64)>,
+    enhance_audio: bool,
+    export: Option<ExportSettings>,
+    segments: Option<Vec<Segment>>,
+) -> Result<String, String> {
+    let timeline = Timeline::new(&keep_ranges, probe::get_duration(&input_path)?)?;
+    let output_path = output_paths::edited_video_path(&input_path);
+    ffmpeg::cut_silences_and_export(
+        &input_path,
+        &timeline,
+        &output_path,
+        enhance_audio,
+        &export.unwrap_or_default(),
+        &segments.unwrap_or_default(),
+        &CancelToken::new(),
+        |_| {},
+    )?;
+    Ok(output_path)
+}
+
+/// Writes captions next to the input. With keep ranges they are timed for the `_edited.mp4` export.
+#[tauri::command]
+async fn export_subtitles(
+    input_path: String,
+    segments: Vec<Segment>,
+    format: SubtitleFormat,
+    settings: Option<SubtitleSettings>,
+    keep_ranges: Option<Vec<(f64, f64)>>,
+    language: Option<String>,
+) -> Result<String, String> {
+    let timeline = match keep_ranges {
+        Some(ref keep_ranges) => Some(Timeline::new(keep_ranges, probe::get_duration(&input_path)?)?),
+        None => None,
+    };
+    let output_path = output_paths::subtitle_path(&input_path, timeline.is_some(), language.as_deref(), format);
+    subtitles::export_subtitles(
+        &segments,
+        timeline.as_ref(),
+        format,
+        &settings.unwrap_or_default(),
+        &output_path,
+    )?;
+    Ok(output_path)
+}
+
+/// Titles, a description, tags and chapters for the transcript, from the configured LLM
+#[tauri::command]
+async fn generate_metadata(
+    templates: tauri::State<'_, PromptTemplates>,
+    transcript: TranscriptResult,
+    llm: LlmProviderConfig,
+) -> Result<VideoMetadata, String> {
+    let provider = llm::provider_from_config(&llm)?;
+    let language = transcript.language.as_deref();
+    let template = templates.get(PromptTask::Metadata, language)?;
+    let merge_template = templates.get(PromptTask::MetadataMerge, language)?;
+    let prompt = llm::MetadataPrompt {
+        template: &template,
+        merge_template: &merge_template,
+        language,
+    };
+    llm::generate_metadata(&transcript.segments, provider, &prompt, &CancelToken::new()).await
+}
+
+/// Writes chapters next to the input, or embeds them in a copy of the video. With keep ranges they are
+/// moved onto the `_edited.mp4` export's timeline and embedded in that file; without, a `_chapters.mp4`
+/// copy of the input gets them.
+#[tauri::command]
+async fn export_chapters(
+    input_path: String,
+    chapters: Vec<Chapter>,
+    format: ChapterFormat,
+    keep_ranges: Option<Vec<(f64, f64)>>,
+) -> Result<String, String> {
+    let source_duration = probe::get_duration(&input_path)?;
+    let timeline = match keep_ranges {
+        Some(ref keep_ranges) => Some(Timeline::new(keep_ranges, source_duration)?),
+        None => None,
+    };
+    let duration = timeline.as_ref().map_or(source_duration, Timeline::output_duration);
+    let chapters = metadata::place_chapters(&chapters, timeline.as_ref(), duration);
+
+    match format {
+        ChapterFormat::YouTube => {
+            let output_path = output_paths::chapters_path(&input_path, timeline.is_some());
+            metadata::write_youtube_chapters(&chapters, duration, &output_path)?;
+            Ok(output_path)
+        }
+        ChapterFormat::Mp4 => {
+            // The edited export is rewritten with its chapters; the source is never touched
+            let (video_path, output_path) = if timeline.is_some() {
+                (output_paths::edited_video_path(&input_path), output_paths::edited_video_path(&input_path))
+            } else {
+                (input_path.clone(), output_paths::chaptered_video_path(&input_path))
+            };
+            tauri::async_runtime::spawn_blocking(move || {
+                metadata::embed_chapters(&video_path, &output_path, &chapters, &CancelToken::new()).map(|_| output_path)
+            })
+            .await
+            .map_err(|e| format!("Chapter embedding task failed: {}", e))?
+        }
+    }
+}
+
+#[tauri::command]
+async fn list_video_encoders() -> Result<Vec<VideoEncoderInfo>, String> {
+    Ok(encoder::available_encoders().to_vec())
+}
+
+#[tauri::command]
+async fn probe_media(input_path: String) -> Result<MediaInfo, String> {
+    probe::probe_media(&input_path)
+}
+
+#[cfg_attr(mobile, tauri::mobile_entry_point)]
+pub fn run() -> Result<(), tauri::Error> {
+    tauri::Builder::default()
+        .plugin(tauri_plugin_opener::init())
+        .plugin(tauri_plugin_shell::init())
+        .plugin(tauri_plugin_dialog::init())
+        .plugin(tauri_plugin_notification::init())
+        .plugin(tauri_plugin_fs::init())
+        .manage(JobRegistry::default())
+        .setup(|app| {
+            let data_dir = app.path().app_data_dir()?;
+            app.manage(Transcriber::new(
+                ModelManager::new(data_dir.join("models")),
+                Some(TranscriptCache::new(data_dir.join("transcripts"))),
+            ));
+            // Users override the built-in prompts with files of the same name here
+            app.manage(PromptTemplates::new(Some(app.path().app_config_dir()?.join("prompts"))));
+
+            // Probe encoders in the background so the first export doesn't pay for it
+            std::thread::spawn(encoder::available_encoders);
+            Ok(())
+        })
+        .invoke_handler(tauri::generate_handler![
+            get_ffmpeg_version,
+            process_video,
+            cancel_job,
+            transcribe_video,
+            whisper_preset,
+            clear_transcript_cache,
+            low_confidence_words,
+            apply_transcript_changes,
+            list_whisper_models,
+            import_whisper_model,
+            verify_whisper_model,
+            export_edited_video,
+            export_subtitles,
+            generate_metadata,
+            export_chapters,
+            list_video_encoders,
+            probe_media
+        ])
+        .run(tauri::generate_context!())
+        .map_err(|e| e.into())
+}
@@ -0,0 +1,309 @@
+//! Headless front end to the pipeline, for batch processing without the desktop app.
+//!
+//! autovideo-cli process in.mp4 --cut-silences --threshold -35 --margin 0.2 --enhance
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use clap::{Args, Parser, Subcommand};
+
+use autovideo_lib::job::CancelToken;
+use autovideo_lib::models::{
+    BurnInCaptions, ExportMode, ExportSettings, PipelineConfig, PipelineEvent, RateControl, TranscriptionTask,
+    WhisperPreset,
+};
+use autovideo_lib::pipeline;
+use autovideo_lib::prompts::PromptTemplates;
+use autovideo_lib::transcribe::Transcriber;
+use autovideo_lib::transcript_cache::TranscriptCache;
+use autovideo_lib::whisper_models::ModelManager;
+
+const BAR_WIDTH: usize = 30;
+
+#[derive(Parser)]
+#[command(name = "autovideo-cli", version, about = "Transcribe, cut silences and enhance recordings")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Run the full pipeline on one video
+    Process(ProcessArgs),
+}
+
+#[derive(Args)]
+struct ProcessArgs {
+    /// Video to process; the output is written next to it
+    input: String,
+
+    /// Remove silent parts
+    #[arg(long)]
+    cut_silences: bool,
+
+    /// Silence threshold in dB
+    #[arg(long, default_value_t = -30.0, allow_negative_numbers = true)]
+    threshold: f64,
+
+    /// Shortest pause counted as silence, in seconds
+    #[arg(long, default_value_t = 0.5)]
+    min_silence: f64,
+
+    /// Padding kept around speech at each cut, in seconds
+    #[arg(long, default_value_t = 0.2)]
+    margin: f64,
+
+    /// Denoise and normalize the audio
+    #[arg(long)]
+    enhance: bool,
+
+    /// Transcription language code (e.g. "en")
+    #[arg(long)]
+    language: Option<String>,
+
+    /// Whisper model name (e.g. "small" or "large-v3-q5_0"); defaults to "base"
+    #[arg(long)]
+    model: Option<String>,
+
+    /// Also translate the speech to English; the result gets a separate translation track
+    #[arg(long)]
+    translate: bool,
+
+    /// Text to prime Whisper with, e.g. a sentence about the topic
+    #[arg(long, value_name = "TEXT")]
+    initial_prompt: Option<String>,
+
+    /// File with product names and jargon to spell right, one per line
+    #[arg(long, value_name = "PATH")]
+    glossary: Option<PathBuf>,
+
+    /// Whisper decoding preset
+    #[arg(long, value_parser = ["fast", "balanced", "accurate"], default_value = "balanced")]
+    preset: String,
+
+    /// Use beam search with this many beams, overriding the preset
+    #[arg(long)]
+    beam_size: Option<u32>,
+
+    /// CPU threads for Whisper
+    #[arg(long)]
+    threads: Option<u32>,
+
+    /// Longest transcript segment in characters
+    #[arg(long, value_name = "CHARS")]
+    max_segment_length: Option<u32>,
+
+    /// With --max-segment-length, only break segments between words
+    #[arg(long, requires = "max_segment_length")]
+    split_on_word: bool,
+
+    /// Directory holding ggml-<model>.bin files
+    #[arg(long, value_name = "DIR", default_value = "models")]
+    models_dir: PathBuf,
+
+    /// FFmpeg video encoder (e.g. "libx264"); picks the best available when omitted
+    #[arg(long)]
+    encoder: Option<String>,
+
+    /// Constant quality instead of a target bitrate
+    #[arg(long, conflicts_with = "bitrate")]
+    crf: Option<u8>,
+
+    /// Target video bitrate in kbps
+    #[arg(long)]
+    bitrate: Option<u32>,
+
+    /// Stream-copy untouched GOPs and only re-encode around cuts
+    #[arg(long)]
+    smart_cut: bool,
+
+    /// Burn word-highlighted captions into the cut video
+    #[arg(long)]
+    burn_captions: bool,
+
+    /// Reuse transcripts stored in this directory, and store new ones there
+    #[arg(long, value_name = "DIR")]
+    cache_dir: Option<PathBuf>,
+
+    /// Run Whisper even when the cache has a transcript
+    #[arg(long)]
+    force_transcribe: bool,
+
+    /// Print pipeline events as JSON lines on stdout instead of a progress bar
+    #[arg(long)]
+    json: bool,
+
+    /// Write the result JSON to this file instead of stdout
+    #[arg(long, value_name = "PATH")]
+    result: Option<PathBuf>,
+}
+
+impl ProcessArgs {
+    fn pipeline_config(&self) -> Result<PipelineConfig, String> {
+        let mut export = ExportSettings {
+            encoder: self.encoder.clone(),
+            ..ExportSettings::default()
+        };
+        if let Some(crf) = self.crf {
+            export.rate_control = RateControl::Crf { crf };
+        } else if let Some(kbps) = self.bitrate {
+            export.rate_control = RateControl::Bitrate { kbps };
+        }
+        if self.smart_cut {
+            export.mode = ExportMode::SmartCut;
+        }
+        if self.burn_captions {
+            export.captions = Some(BurnInCaptions::default());
+        }
+
+        let glossary = match self.glossary {
+            Some(ref path) => fs::read_to_string(path)
+                .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty())
+                .map(str::to_string)
+                .collect(),
+            None => Vec::new(),
+        };
+
+        let mut whisper = match self.preset.as_str() {
+            "fast" => WhisperPreset::Fast,
+            "accurate" => WhisperPreset::Accurate,
+            _ => WhisperPreset::Balanced,
+        }
+        .params();
+        if self.beam_size.is_some() {
+            whisper.beam_size = self.beam_size;
+        }
+        whisper.threads = self.threads;
+        whisper.max_segment_length = self.max_segment_length;
+        whisper.split_on_word = self.split_on_word;
+
+        Ok(PipelineConfig {
+            enhance_audio: self.enhance,
+            cut_silences: self.cut_silences,
+            silence_threshold_db: self.threshold,
+            silence_min_duration: self.min_silence,
+            cut_margin: self.margin,
+            language: self.language.clone(),
+            model: self.model.clone(),
+            task: if self.translate {
+                TranscriptionTask::Translate
+            } else {
+                TranscriptionTask::Transcribe
+            },
+            initial_prompt: self.initial_prompt.clone(),
+            glossary,
+            whisper,
+            // LLM cleanup is only configured in the app
+            llm: None,
+            export,
+            force_transcribe: self.force_transcribe,
+        })
+    }
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    let outcome = match cli.command {
+        Command::Process(args) => process(args),
+    };
+
+    match outcome {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("❌ {}", error);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn process(args: ProcessArgs) -> Result<(), String> {
+    let config = args.pipeline_config()?;
+    let transcriber = Transcriber::new(
+        ModelManager::new(&args.models_dir),
+        args.cache_dir.as_ref().map(TranscriptCache::new),
+    );
+
+    let templates = PromptTemplates::default();
+    let outcome = pipeline::process_video(&args.input, &config, &transcriber, &templates, &CancelToken::new(), |event| {
+        if args.json {
+            print_json_line(&event)
+        } else {
+            draw_progress(&event);
+            Ok(())
+        }
+    });
+
+    let result = match outcome {
+        Ok(result) => result,
+        Err(error) => {
+            if args.json {
+                print_json_line(&PipelineEvent::PipelineFailed { error: error.clone() })?;
+            }
+            return Err(error);
+        }
+    };
+
+    eprintln!("🎉 Wrote {}", result.output_path);
+
+    if let Some(ref path) = args.result {
+        let json = serde_json::to_string_pretty(&result)
+            .map_err(|e| format!("Failed to serialize result: {}", e))?;
+        fs::write(path, json).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    }
+
+    if args.json {
+        print_json_line(&PipelineEvent::PipelineCompleted { result })
+    } else if args.result.is_none() {
+        let json = serde_json::to_string_pretty(&result)
+            .map_err(|e| format!("Failed to serialize result: {}", e))?;
+        println!("{}", json);
+        Ok(())
+    } else {
+        Ok(())
+    }
+}
+
+/// One event per line, in the same shape the desktop app receives
+fn print_json_line(event: &PipelineEvent) -> Result<(), String> {
+    let line = serde_json::to_string(event).map_err(|e| format!("Failed to serialize event: {}", e))?;
+    let mut stdout = io::stdout().lock();
+    writeln!(stdout, "{}", line)
+        .and_then(|_| stdout.flush())
+        .map_err(|e| format!("Failed to write event: {}", e))
+}
+
+/// Single-line progress bar on stderr, redrawn in place
+fn draw_progress(event: &PipelineEvent) {
+    match event {
+        PipelineEvent::StageStarted { stage } => eprintln!("▶️ {}", stage),
+        PipelineEvent::StageProgress { stage, progress, eta_seconds } => {
+            let progress = progress.clamp(0.0, 1.0);
+            let filled = (progress * BAR_WIDTH as f64).round() as usize;
+            let eta = eta_seconds.map(|s| format!(" ETA {}", format_eta(s))).unwrap_or_default();
+            eprint!(
+                "\r{:<16} [{}{}] {:>3.0}%{}   ",
+                stage,
+                "#".repeat(filled),
+                " ".repeat(BAR_WIDTH - filled),
+                progress * 100.0,
+                eta
+            );
+            let _ = io::stderr().flush();
+        }
+        PipelineEvent::StageCompleted { stage } => eprintln!("\r✅ {:<60}", stage),
+        PipelineEvent::StageFailed { stage, error } => eprintln!("\r❌ {} failed: {}", stage, error),
+        _ => {}
+    }
+}
+
+fn format_eta(seconds: f64) -> String {
+    let seconds = seconds.max(0.0).round() as u64;
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
@@ -0,0 +1,285 @@
+//! Splitting long recordings into overlapping windows for Whisper, and stitching the results back together.
+//!
+//! Each chunk is cut at the quietest stretch near its target length and Whisper also hears a few seconds
+//! past the cut, so words at the boundary are never chopped. A segment belongs to the chunk its midpoint
+//! falls in; the copy the neighbouring chunk produced from the overlap is dropped.
+
+use crate::models::Segment;
+
+/// Target length of one chunk; about 38 MB of 16 kHz mono samples
+pub const CHUNK_SECONDS: f64 = 600.0;
+
+/// How far before or after the target length a chunk may be cut to land in a pause
+pub const SPLIT_SEARCH_SECONDS: f64 = 30.0;
+
+/// Audio Whisper hears on each side of a cut
+pub const CHUNK_OVERLAP_SECONDS: f64 = 5.0;
+
+/// Energy is measured in frames of this length...
+const ENERGY_FRAME_SECONDS: f64 = 0.1;
+
+/// ...and averaged over this many frames, so a pause beats the gap between two syllables
+const ENERGY_SMOOTHING_FRAMES: usize = 5;
+
+/// A repeat of the last segment starting this soon after it ends is the same speech transcribed twice
+const DUPLICATE_TOLERANCE_SECONDS: f64 = 1.0;
+
+/// Where the chunk taking over the recording at `owned_from` starts reading, and for how many seconds;
+/// None reads to the end
+pub fn chunk_read(owned_from: f64, duration_seconds: f64) -> (f64, Option<f64>) {
+    let offset = (owned_from - CHUNK_OVERLAP_SECONDS).max(0.0);
+
+    // A chunk reads past its furthest possible cut plus the overlap after it; the last one runs to the end
+    let read_seconds = (owned_from - offset) + CHUNK_SECONDS + SPLIT_SEARCH_SECONDS + CHUNK_OVERLAP_SECONDS;
+    let reaches_end = duration_seconds - owned_from <= CHUNK_SECONDS + SPLIT_SEARCH_SECONDS;
+
+    (offset, (!reaches_end).then_some(read_seconds))
+}
+
+/// Cut a chunk read from `offset` at the quietest point near its target length, keeping the overlap after
+/// the cut. Returns the cut in recording time: where this chunk stops owning segments and the next one starts.
+pub fn cut_chunk(samples: &mut Vec<f32>, sample_rate: u32, owned_from: f64, offset: f64) -> f64 {
+    let target = owned_from + CHUNK_SECONDS - offset;
+    let split = quietest_point(samples, sample_rate, target - SPLIT_SEARCH_SECONDS, target + SPLIT_SEARCH_SECONDS);
+    samples.truncate(((split + CHUNK_OVERLAP_SECONDS) * sample_rate as f64) as usize);
+    offset + split
+}
+
+/// Seconds from the start of `samples` to the quietest point between `from` and `to`
+pub fn quietest_point(samples: &[f32], sample_rate: u32, from: f64, to: f64) -> f64 {
+    let frame_len = ((sample_rate as f64 * ENERGY_FRAME_SECONDS) as usize).max(1);
+    let first_frame = (from / ENERGY_FRAME_SECONDS).floor().max(0.0) as usize;
+    let last_frame = ((to / ENERGY_FRAME_SECONDS).ceil() as usize).min(samples.len() / frame_len);
+
+    if first_frame >= last_frame {
+        return from.clamp(0.0, samples.len() as f64 / sample_rate as f64);
+    }
+
+    let energies: Vec<f64> = (first_frame..last_frame)
+        .map(|frame| {
+            let frame_samples = &samples[frame * frame_len..(frame + 1) * frame_len];
+            frame_samples.iter().map(|&s| (s as f64) * (s as f64)).sum::<f64>() / frame_len as f64
+        })
+        .collect();
+
+    let window = ENERGY_SMOOTHING_FRAMES.min(energies.len());
+    let (quietest, _) = energies
+        .windows(window)
+        .map(|frames| frames.iter().sum::<f64>())
+        .enumerate()
+        .fold((0, f64::INFINITY), |best, (i, energy)| if energy < best.1 { (i, energy) } else { best });
+
+    // Middle of the quietest window
+    (first_frame + quietest) as f64 * ENERGY_FRAME_SECONDS + window as f64 * ENERGY_FRAME_SECONDS / 2.0
+}
+
+/// Shift a chunk's segments from chunk time to recording time, keeping the ones centred in `[owned_from, owned_to)`
+pub fn place_segments(segments: Vec<Segment>, offset: f64, owned_from: f64, owned_to: f64) -> Vec<Segment> {
+    segments
+        .into_iter()
+        .map(|mut segment| {
+            segment.start += offset;
+            segment.end += offset;
+            for word in &mut segment.words {
+                word.start += offset;
+                word.end += offset;
+            }
+            segment
+        })
+        .filter(|segment| {
+            let middle = (segment.start + segment.end) / 2.0;
+            middle >= owned_from && middle < owned_to
+        })
+        .collect()
+}
+
+/// Append a chunk's segments, skipping a leading segment that repeats the last one.
+/// Whisper sometimes places the same sentence slightly differently in two overlapping chunks,
+/// so it lands on both sides of the cut.
+pub fn stitch(transcript: &mut Vec<Segment>, next: Vec<Segment>) {
+    let mut next = next.into_iter().peekable();
+
+    if let (Some(last), Some(first)) = (transcript.last(), next.peek()) {
+        let same_text = normalized(&last.text) == normalized(&first.text);
+        if same_text && first.start < last.end + DUPLICATE_TOLERANCE_SECONDS {
+            next.next();
+        }
+    }
+
+    transcript.extend(next);
+}
+
+/// Give segments and words sequential ids across the whole transcript
+pub fn renumber(segments: &mut [Segment]) {
+    let mut word_index = 0;
+    for (i, segment) in segments.iter_mut().enumerate() {
+        segment.id = i;
+        for word in &mut segment.words {
+            word.id = format!("w{}", word_index);
+            word_index += 1;
+        }
+    }
+}
+
+fn normalized(text: &str) -> String {
+    text.chars()
+        .filter(|c| c.is_alphanumeric() || c.is_whitespace())
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::Word;
+    use crate::transcript_cache::TranscriptCheckpoint;
+
+    /// Low enough that a test recording is small; one energy frame is 10 samples
+    const RATE: u32 = 100;
+
+    /// A recording that is loud except for the pauses in `quiet` (start, end)
+    fn recording(seconds: f64, quiet: &[(f64, f64)]) -> Vec<f32> {
+        (0..(seconds * RATE as f64) as usize)
+            .map(|i| {
+                let t = i as f64 / RATE as f64;
+                if quiet.iter().any(|&(from, to)| t >= from && t < to) {
+                    0.0
+                } else {
+                    0.5
+                }
+            })
+            .collect()
+    }
+
+    fn segment(start: f64, end: f64, text: &str) -> Segment {
+        Segment {
+            id: 0,
+            start,
+            end,
+            text: text.to_string(),
+            words: vec![Word { id: String::new(), word: text.to_string(), start, end, confidence: None }],
+            confidence: None,
+            avg_logprob: None,
+        }
+    }
+
+    /// One three-second sentence every four seconds
+    fn speech(seconds: f64) -> Vec<Segment> {
+        (0..(seconds / 4.0) as usize)
+            .map(|i| segment(i as f64 * 4.0, i as f64 * 4.0 + 3.0, &format!("sentence {}", i)))
+            .collect()
+    }
+
+    /// Stand-in for Whisper: every sentence that lies wholly inside the chunk, in chunk time
+    fn hear(speech: &[Segment], offset: f64, seconds: f64) -> Vec<Segment> {
+        speech
+            .iter()
+            .filter(|s| s.start >= offset && s.end <= offset + seconds)
+            .map(|s| segment(s.start - offset, s.end - offset, &s.text))
+            .collect()
+    }
+
+    /// The chunk loop of `Transcriber::run`, stopping after `max_chunks` like an interrupted job would
+    fn transcribe(
+        audio: &[f32],
+        speech: &[Segment],
+        mut checkpoint: TranscriptCheckpoint,
+        max_chunks: usize,
+    ) -> TranscriptCheckpoint {
+        let duration = audio.len() as f64 / RATE as f64;
+        for _ in 0..max_chunks {
+            let owned_from = checkpoint.transcribed_until;
+            let (offset, read_seconds) = chunk_read(owned_from, duration);
+            let first = (offset * RATE as f64) as usize;
+            let last = read_seconds.map_or(audio.len(), |s| (first + (s * RATE as f64) as usize).min(audio.len()));
+            let mut samples = audio[first..last].to_vec();
+
+            let is_last = read_seconds.is_none_or(|s| samples.len() < (s * RATE as f64) as usize);
+            let owned_to = if is_last { f64::INFINITY } else { cut_chunk(&mut samples, RATE, owned_from, offset) };
+
+            let heard = hear(speech, offset, samples.len() as f64 / RATE as f64);
+            stitch(&mut checkpoint.segments, place_segments(heard, offset, owned_from, owned_to));
+            if is_last {
+                break;
+            }
+            checkpoint.transcribed_until = owned_to;
+        }
+        checkpoint
+    }
+
+    fn texts(segments: &[Segment]) -> Vec<&str> {
+        segments.iter().map(|s| s.text.as_str()).collect()
+    }
+
+    #[test]
+    fn chunks_overlap_the_cut() {
+        assert_eq!(chunk_read(0.0, 1500.0), (0.0, Some(635.0)));
+        assert_eq!(chunk_read(600.0, 1500.0), (595.0, Some(640.0)));
+        // Close enough to the end that the chunk can't be cut again
+        assert_eq!(chunk_read(900.0, 1500.0), (895.0, None));
+    }
+
+    #[test]
+    fn cuts_in_the_pause_nearest_the_target_length() {
+        let mut samples = recording(635.0, &[(590.0, 592.0), (640.0, 700.0)]);
+        let owned_to = cut_chunk(&mut samples, RATE, 0.0, 0.0);
+
+        assert!((590.0..592.0).contains(&owned_to), "cut at {}", owned_to);
+        let kept = samples.len() as f64 / RATE as f64;
+        assert!((kept - (owned_to + CHUNK_OVERLAP_SECONDS)).abs() < 0.01);
+
+        // A later chunk reads from before its start, so the cut is shifted back to recording time
+        let mut samples = recording(640.0, &[(620.0, 621.0)]);
+        let owned_to = cut_chunk(&mut samples, RATE, 600.0, 595.0);
+        assert!((1215.0..1216.0).contains(&owned_to), "cut at {}", owned_to);
+    }
+
+    #[test]
+    fn sentences_at_the_cut_belong_to_one_chunk() {
+        // The cut lands mid-sentence: "sentence 147" runs 588-591
+        let audio = recording(1500.0, &[(590.0, 592.0), (1180.0, 1182.0)]);
+        let speech = speech(1500.0);
+
+        let first = transcribe(&audio, &speech, TranscriptCheckpoint::default(), 1);
+        let cut = first.transcribed_until;
+        assert!((590.0..592.0).contains(&cut), "cut at {}", cut);
+        assert_eq!(first.segments.last().unwrap().text, "sentence 147");
+
+        let transcript = transcribe(&audio, &speech, TranscriptCheckpoint::default(), usize::MAX);
+        assert_eq!(texts(&transcript.segments), texts(&speech));
+        // Words come back in recording time, not chunk time
+        let boundary = &transcript.segments[148];
+        assert_eq!((boundary.start, boundary.words[0].start), (592.0, 592.0));
+    }
+
+    #[test]
+    fn resumes_from_a_partial_checkpoint() {
+        let audio = recording(1500.0, &[(590.0, 592.0), (1180.0, 1182.0)]);
+        let speech = speech(1500.0);
+
+        let interrupted = transcribe(&audio, &speech, TranscriptCheckpoint::default(), 1);
+        // Round-trip through JSON as the cache stores it
+        let saved: TranscriptCheckpoint =
+            serde_json::from_str(&serde_json::to_string(&interrupted).unwrap()).unwrap();
+        let resumed = transcribe(&audio, &speech, saved, usize::MAX);
+
+        let uninterrupted = transcribe(&audio, &speech, TranscriptCheckpoint::default(), usize::MAX);
+        assert_eq!(texts(&resumed.segments), texts(&uninterrupted.segments));
+        assert_eq!(resumed.transcribed_until, uninterrupted.transcribed_until);
+    }
+
+    #[test]
+    fn stitching_drops_a_sentence_heard_on_both_sides() {
+        let mut transcript = vec![segment(588.0, 591.5, "And that's it.")];
+        // The next chunk placed the same sentence a little later
+        stitch(&mut transcript, vec![segment(590.2, 591.6, "and that's it"), segment(592.0, 595.0, "Next.")]);
+        assert_eq!(texts(&transcript), ["And that's it.", "Next."]);
+
+        // Same words much later are a real repeat
+        stitch(&mut transcript, vec![segment(600.0, 601.0, "next")]);
+        assert_eq!(transcript.len(), 3);
+    }
+}
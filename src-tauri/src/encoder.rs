@@ -0,0 +1,362 @@
+use std::process::Command;
+use std::sync::OnceLock;
+
+use crate::models::{ExportSettings, RateControl, VideoCodec, VideoEncoderInfo};
+
+/// Bitrate used when the chosen encoder has no constant-quality mode
+const FALLBACK_BITRATE_KBPS: u32 = 8000;
+
+/// Encoders we know how to drive, in order of preference per codec.
+/// Hardware encoders come first; the software ones are the fallback.
+const KNOWN_ENCODERS: &[(&str, VideoCodec, bool)] = &[
+    ("h264_videotoolbox", VideoCodec::H264, true),
+    ("h264_nvenc", VideoCodec::H264, true),
+    ("h264_qsv", VideoCodec::H264, true),
+    ("h264_amf", VideoCodec::H264, true),
+    ("h264_mf", VideoCodec::H264, true),
+    ("libx264", VideoCodec::H264, false),
+    ("libopenh264", VideoCodec::H264, false),
+    ("hevc_videotoolbox", VideoCodec::Hevc, true),
+    ("hevc_nvenc", VideoCodec::Hevc, true),
+    ("hevc_qsv", VideoCodec::Hevc, true),
+    ("hevc_amf", VideoCodec::Hevc, true),
+    ("hevc_mf", VideoCodec::Hevc, true),
+    ("libx265", VideoCodec::Hevc, false),
+];
+
+static AVAILABLE_ENCODERS: OnceLock<Vec<VideoEncoderInfo>> = OnceLock::new();
+
+/// Encoders that this FFmpeg build ships and that actually work on this machine.
+/// Probed once on first use; call early at startup to keep it off the export path.
+pub fn available_encoders() -> &'static [VideoEncoderInfo] {
+    AVAILABLE_ENCODERS.get_or_init(probe_encoders)
+}
+
+fn probe_encoders() -> Vec<VideoEncoderInfo> {
+    let output = match Command::new("ffmpeg").args(["-hide_banner", "-encoders"]).output() {
+        Ok(output) => output,
+        Err(e) => {
+            eprintln!("❌ Failed to list FFmpeg encoders: {}", e);
+            return Vec::new();
+        }
+    };
+
+    let listed = parse_encoder_list(&String::from_utf8_lossy(&output.stdout));
+
+    let encoders: Vec<VideoEncoderInfo> = known_encoders(&listed)
+        .into_iter()
+        // A hardware encoder can be compiled in without the device being present
+        .filter(|e| !e.hardware || test_encoder(&e.name))
+        .collect();
+
+    eprintln!(
+        "🎞️ Usable video encoders: {}",
+        encoders.iter().map(|e| e.name.as_str()).collect::<Vec<_>>().join(", ")
+    );
+
+    encoders
+}
+
+/// Returns the names of all video encoders in `ffmpeg -encoders` output
+fn parse_encoder_list(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let mut parts = line.split_whitespace();
+            let flags = parts.next()?;
+            let name = parts.next()?;
+            // Flag column looks like "V....D"; the legend lines above use "V..... = Video"
+            if flags.len() == 6 && flags.starts_with('V') && name != "=" {
+                Some(name.to_string())
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+/// The `KNOWN_ENCODERS` among `listed`, in preference order
+fn known_encoders(listed: &[String]) -> Vec<VideoEncoderInfo> {
+    KNOWN_ENCODERS
+        .iter()
+        .filter(|(name, _, _)| listed.iter().any(|l| l == name))
+        .map(|(name, codec, hardware)| VideoEncoderInfo {
+            name: name.to_string(),
+            codec: *codec,
+            hardware: *hardware,
+        })
+        .collect()
+}
+
+/// Encode a single blank frame to check the encoder can open its device
+fn test_encoder(name: &str) -> bool {
+    Command::new("ffmpeg")
+        .args([
+            "-hide_banner",
+            "-loglevel", "error",
+            "-f", "lavfi",
+            "-i", "color=c=black:s=256x256:d=0.1",
+            "-frames:v", "1",
+            "-pix_fmt", pixel_format(name),
+            "-c:v", name,
+            "-f", "null",
+            "-",
+        ])
+        .output()
+        .map(|output| output.status.success())
+        .unwrap_or(false)
+}
+
+/// Pick the encoder for an export: the one the user asked for, or the best available for the codec
+pub fn select_encoder(settings: &ExportSettings) -> Result<VideoEncoderInfo, String> {
+    let available = available_encoders();
+
+    if let Some(ref name) = settings.encoder {
+        return available
+            .iter()
+            .find(|e| &e.name == name)
+            .cloned()
+            .ok_or_else(|| format!("Video encoder {} is not available on this machine", name));
+    }
+
+    // KNOWN_ENCODERS order puts hardware first, so the first match is the preferred one
+    available
+        .iter()
+        .find(|e| e.codec == settings.codec)
+        .cloned()
+        .ok_or_else(|| format!("No {:?} video encoder available in this FFmpeg build", settings.codec))
+}
+
+/// FFmpeg output arguments for the video stream (`-c:v`, rate control, preset, profile, pixel format)
+pub fn video_encoder_args(encoder: &VideoEncoderInfo, settings: &ExportSettings) -> Vec<String> {
+    let name = encoder.name.as_str();
+    let mut args = vec!["-c:v".to_string(), name.to_string()];
+
+    args.extend(rate_control_args(name, settings.rate_control));
+    args.extend(preset_args(name, settings.preset.as_deref()));
+
+    args.extend(profile_args(name, encoder.codec, settings.profile.as_deref()));
+
+    args.extend(["-pix_fmt".to_string(), pixel_format(name).to_string()]);
+
+    args
+}
+
+/// Rate control options for `name`; constant quality falls back to a bitrate where the encoder has none
+pub fn rate_control_args(name: &str, rate_control: RateControl) -> Vec<String> {
+    match rate_control {
+        RateControl::Crf { crf } => constant_quality_args(name, crf).unwrap_or_else(|| {
+            eprintln!("⚠️ {} has no constant-quality mode, using {}k bitrate", name, FALLBACK_BITRATE_KBPS);
+            bitrate_args(FALLBACK_BITRATE_KBPS)
+        }),
+        RateControl::Bitrate { kbps } => bitrate_args(kbps),
+    }
+}
+
+/// `-preset` for encoders that take one
+pub fn preset_args(name: &str, preset: Option<&str>) -> Vec<String> {
+    match preset {
+        Some(preset) if supports_preset(name) => vec!["-preset".to_string(), preset.to_string()],
+        Some(preset) => {
+            eprintln!("⚠️ {} does not take a preset, ignoring {}", name, preset);
+            Vec::new()
+        }
+        None => Vec::new(),
+    }
+}
+
+/// `-profile:v` under the encoder's own name for the profile; nothing when the encoder doesn't take it.
+/// Defaults to High for H.264 and Main for HEVC.
+pub fn profile_args(name: &str, codec: VideoCodec, profile: Option<&str>) -> Vec<String> {
+    let wanted = profile.unwrap_or(match codec {
+        VideoCodec::H264 => "high",
+        VideoCodec::Hevc => "main",
+    });
+
+    let accepted = encoder_profiles(name);
+    // AMF only has the constrained flavours of baseline and high
+    let matched = accepted.iter().find(|p| p.eq_ignore_ascii_case(wanted)).or_else(|| {
+        accepted
+            .iter()
+            .find(|p| p.strip_prefix("constrained_").is_some_and(|p| p.eq_ignore_ascii_case(wanted)))
+    });
+
+    match matched {
+        Some(matched) => vec!["-profile:v".to_string(), matched.to_string()],
+        None => {
+            if profile.is_some() {
+                eprintln!("⚠️ {} has no {} profile, leaving it to the encoder", name, wanted);
+            }
+            Vec::new()
+        }
+    }
+}
+
+/// Profile names each encoder accepts for `-profile:v`; empty for encoders we don't pass a profile to
+fn encoder_profiles(name: &str) -> &'static [&'static str] {
+    match name {
+        "libx264" => &["baseline", "main", "high", "high10", "high422", "high444"],
+        "h264_nvenc" => &["baseline", "main", "high", "high444p"],
+        "h264_qsv" => &["baseline", "main", "high"],
+        "h264_videotoolbox" => &["baseline", "main", "high", "extended"],
+        "h264_amf" => &["main", "high", "constrained_baseline", "constrained_high"],
+        "libx265" => &["main", "main10", "mainstillpicture"],
+        "hevc_nvenc" => &["main", "main10", "rext"],
+        "hevc_qsv" => &["main", "main10", "mainsp", "rext"],
+        "hevc_videotoolbox" => &["main", "main10"],
+        "hevc_amf" => &["main"],
+        // h264_mf, hevc_mf and libopenh264 pick their own profile
+        _ => &[],
+    }
+}
+
+fn bitrate_args(kbps: u32) -> Vec<String> {
+    vec![
+        "-b:v".to_string(),
+        format!("{}k", kbps),
+        "-maxrate".to_string(),
+        format!("{}k", kbps * 5 / 4),
+        "-bufsize".to_string(),
+        format!("{}k", kbps * 2),
+    ]
+}
+
+/// Map a CRF value onto each encoder family's constant-quality option
+fn constant_quality_args(name: &str, crf: u8) -> Option<Vec<String>> {
+    let crf = crf.to_string();
+    let args: Vec<&str> = if name == "libx264" || name == "libx265" {
+        vec!["-crf", &crf]
+    } else if name.ends_with("_nvenc") {
+        vec!["-rc", "vbr", "-cq", &crf, "-b:v", "0"]
+    } else if name.ends_with("_qsv") {
+        vec!["-global_quality", &crf]
+    } else if name.ends_with("_amf") {
+        vec!["-rc", "cqp", "-qp_i", &crf, "-qp_p", &crf]
+    } else {
+        return None;
+    };
+
+    Some(args.into_iter().map(String::from).collect())
+}
+
+fn supports_preset(name: &str) -> bool {
+    name == "libx264" || name == "libx265" || name.ends_with("_nvenc") || name.ends_with("_qsv")
+}
+
+fn pixel_format(name: &str) -> &'static str {
+    // Quick Sync only accepts NV12 input
+    if name.ends_with("_qsv") {
+        "nv12"
+    } else {
+        "yuv420p"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `ffmpeg -hide_banner -encoders` from a Linux build with NVENC, Quick Sync and VAAPI, trimmed
+    const ENCODERS_OUTPUT: &str = "\
+Encoders:
+ V..... = Video
+ A..... = Audio
+ S..... = Subtitle
+ .F.... = Frame-level multithreading
+ ..S... = Slice-level multithreading
+ ...X.. = Codec is experimental
+ ....B. = Supports draw_horiz_band
+ .....D = Supports direct rendering method 1
+ ------
+ V....D libx264              libx264 H.264 / AVC / MPEG-4 AVC / MPEG-4 part 10 (codec h264)
+ V....D libx264rgb           libx264 H.264 / AVC / MPEG-4 AVC / MPEG-4 part 10 RGB (codec h264)
+ V....D h264_nvenc           NVIDIA NVENC H.264 encoder (codec h264)
+ V..... h264_qsv             H.264 / AVC / MPEG-4 AVC / MPEG-4 part 10 (Intel Quick Sync Video acceleration) (codec h264)
+ V....D h264_vaapi           H.264/AVC (VAAPI) (codec h264)
+ V....D libx265              libx265 H.265 / HEVC (codec hevc)
+ V....D hevc_nvenc           NVIDIA NVENC hevc encoder (codec hevc)
+ V..... hevc_qsv             HEVC (Intel Quick Sync Video acceleration) (codec hevc)
+ V....D hevc_vaapi           H.265/HEVC (VAAPI) (codec hevc)
+ A....D aac                  AAC (Advanced Audio Coding)
+ A....D libopus              libopus Opus (codec opus)
+ S..... srt                  SubRip subtitle
+";
+
+    fn encoder(name: &str, codec: VideoCodec) -> VideoEncoderInfo {
+        VideoEncoderInfo { name: name.to_string(), codec, hardware: !name.starts_with("lib") }
+    }
+
+    fn value<'a>(args: &'a [String], option: &str) -> Option<&'a str> {
+        args.windows(2).find(|pair| pair[0] == option).map(|pair| pair[1].as_str())
+    }
+
+    #[test]
+    fn parses_video_encoders_from_ffmpeg_output() {
+        let listed = parse_encoder_list(ENCODERS_OUTPUT);
+        assert_eq!(
+            listed,
+            [
+                "libx264", "libx264rgb", "h264_nvenc", "h264_qsv", "h264_vaapi",
+                "libx265", "hevc_nvenc", "hevc_qsv", "hevc_vaapi",
+            ]
+        );
+
+        // Only the ones we know how to drive, hardware first
+        let known: Vec<String> = known_encoders(&listed).into_iter().map(|e| e.name).collect();
+        assert_eq!(known, ["h264_nvenc", "h264_qsv", "libx264", "hevc_nvenc", "hevc_qsv", "libx265"]);
+    }
+
+    #[test]
+    fn listed_encoders_get_arguments_they_accept() {
+        let settings = ExportSettings::default();
+        for info in known_encoders(&parse_encoder_list(ENCODERS_OUTPUT)) {
+            let args = video_encoder_args(&info, &settings);
+            assert_eq!(value(&args, "-c:v"), Some(info.name.as_str()));
+            let expected = match info.codec {
+                VideoCodec::H264 => "high",
+                VideoCodec::Hevc => "main",
+            };
+            assert_eq!(value(&args, "-profile:v"), Some(expected), "{}", info.name);
+        }
+
+        let args = video_encoder_args(&encoder("h264_qsv", VideoCodec::H264), &settings);
+        assert_eq!(value(&args, "-pix_fmt"), Some("nv12"));
+        assert_eq!(value(&args, "-b:v"), Some("8000k"));
+    }
+
+    #[test]
+    fn profiles_use_each_encoders_own_names() {
+        let settings = ExportSettings { profile: Some("baseline".to_string()), ..ExportSettings::default() };
+
+        let args = video_encoder_args(&encoder("libx264", VideoCodec::H264), &settings);
+        assert_eq!(value(&args, "-profile:v"), Some("baseline"));
+        let args = video_encoder_args(&encoder("h264_amf", VideoCodec::H264), &settings);
+        assert_eq!(value(&args, "-profile:v"), Some("constrained_baseline"));
+
+        // Encoders that don't take a profile, or not this one, get none rather than an FFmpeg error
+        for name in ["h264_mf", "libopenh264", "h264_vaapi"] {
+            let args = video_encoder_args(&encoder(name, VideoCodec::H264), &settings);
+            assert_eq!(value(&args, "-profile:v"), None, "{}", name);
+        }
+        let high = ExportSettings { profile: Some("high".to_string()), ..ExportSettings::default() };
+        let args = video_encoder_args(&encoder("hevc_videotoolbox", VideoCodec::Hevc), &high);
+        assert_eq!(value(&args, "-profile:v"), None);
+
+        let main10 = ExportSettings { profile: Some("Main10".to_string()), ..ExportSettings::default() };
+        let args = video_encoder_args(&encoder("hevc_videotoolbox", VideoCodec::Hevc), &main10);
+        assert_eq!(value(&args, "-profile:v"), Some("main10"));
+    }
+
+    #[test]
+    fn constant_quality_maps_per_encoder_family() {
+        let crf = ExportSettings { rate_control: RateControl::Crf { crf: 23 }, ..ExportSettings::default() };
+
+        let args = video_encoder_args(&encoder("libx265", VideoCodec::Hevc), &crf);
+        assert_eq!(value(&args, "-crf"), Some("23"));
+        let args = video_encoder_args(&encoder("hevc_nvenc", VideoCodec::Hevc), &crf);
+        assert_eq!(value(&args, "-cq"), Some("23"));
+        // VideoToolbox has no constant-quality mode here
+        let args = video_encoder_args(&encoder("h264_videotoolbox", VideoCodec::H264), &crf);
+        assert_eq!(value(&args, "-b:v"), Some("8000k"));
+    }
+}
@@ -1,7 +1,46 @@
-use crate::models::PipelineStage;
+use crate::encoder::{select_encoder, video_encoder_args};
+use crate::job::{CancelToken, CANCELLED_ERROR};
+use crate::models::{ExportMode, ExportSettings, PipelineStage, Segment};
+use crate::scratch::ScratchDir;
+use crate::timeline::Timeline;
+use crate::{smart_cut, subtitles};
 use regex::Regex;
-use std::io::{BufRead, BufReader};
-use std::process::{Child, Command, Stdio};
+use std::io::{BufRead, BufReader, ErrorKind, Read};
+use std::process::{Child, ChildStdout, Command, Stdio};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// How often a running FFmpeg child is checked for exit or cancellation
+const CANCEL_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Noise reduction followed by loudness normalization
+pub const ENHANCE_AUDIO_FILTER: &str = "afftdn=nf=-25,loudnorm=I=-16:TP=-1.5:LRA=11";
+
+/// A progress update parsed from FFmpeg's `-progress` output
+#[derive(Debug, Clone, Copy)]
+pub struct FFmpegProgress {
+    /// Fraction of the expected output written so far, 0.0 to 1.0
+    pub progress: f64,
+    /// Estimated seconds remaining, extrapolated from elapsed wall-clock time
+    pub eta_seconds: Option<f64>,
+}
+
+impl FFmpegProgress {
+    pub fn new(out_time: f64, total_duration: f64, elapsed: Duration) -> Self {
+        let progress = (out_time / total_duration).clamp(0.0, 1.0);
+        let eta_seconds = if progress > 0.0 {
+            Some(elapsed.as_secs_f64() * (1.0 - progress) / progress)
+        } else {
+            None
+        };
+
+        FFmpegProgress {
+            progress,
+            eta_seconds,
+        }
+    }
+}
 
 pub struct FFmpegProcess {
     process: Child,
@@ -35,35 +74,154 @@ impl FFmpegProcess {
         self.current_stage
     }
 
-    pub fn read_stderr(&mut self) -> Result<String, String> {
-        let stderr = self.process.stderr.as_mut().ok_or("No stderr available")?;
+    /// Wait for FFmpeg to exit, killing it as soon as the job is cancelled.
+    /// Reports `-progress pipe:1` updates against `total_duration` (skipped when it is 0).
+    /// Returns the full stderr output on success.
+    pub fn wait_with_progress<F>(
+        mut self,
+        total_duration: f64,
+        cancel: &CancelToken,
+        mut on_progress: F,
+    ) -> Result<String, String>
+    where
+        F: FnMut(FFmpegProgress),
+    {
+        // Drain both pipes on separate threads so FFmpeg never blocks on a full pipe
+        let stderr_reader = self.read_stderr()?;
+
+        let stdout = self.process.stdout.take().ok_or("No stdout available")?;
+        let (out_time_tx, out_time_rx) = mpsc::channel();
+        thread::spawn(move || read_progress(stdout, out_time_tx));
+
+        let started = Instant::now();
+
+        let status = loop {
+            if cancel.is_cancelled() {
+                eprintln!("🛑 Cancelling FFmpeg");
+                self.kill();
+                let _ = self.process.wait();
+                let _ = stderr_reader.join();
+                return Err(CANCELLED_ERROR.to_string());
+            }
 
-        let mut buffer = String::new();
-        let mut reader = BufReader::new(stderr);
+            match self.process.try_wait() {
+                Ok(Some(status)) => break status,
+                Ok(None) => {}
+                Err(e) => return Err(format!("Failed to wait for FFmpeg: {}", e)),
+            }
 
-        reader
-            .read_line(&mut buffer)
-            .map_err(|e| format!("Failed to read stderr: {}", e))?;
+            match out_time_rx.recv_timeout(CANCEL_POLL_INTERVAL) {
+                Ok(out_time) if total_duration > 0.0 => {
+                    on_progress(FFmpegProgress::new(out_time, total_duration, started.elapsed()));
+                }
+                Ok(_) | Err(RecvTimeoutError::Timeout) => {}
+                // stdout closed, FFmpeg is about to exit
+                Err(RecvTimeoutError::Disconnected) => thread::sleep(CANCEL_POLL_INTERVAL),
+            }
+        };
+
+        let stderr = stderr_reader.join().unwrap_or_default();
+
+        if !status.success() {
+            return Err(format!("FFmpeg failed: {}", stderr));
+        }
 
-        Ok(buffer)
+        Ok(stderr)
     }
 
-    pub fn wait_for_completion(mut self) -> Result<(), String> {
-        let status = self
-            .process
-            .wait()
-            .map_err(|e| format!("Failed to wait for FFmpeg: {}", e))?;
+    /// Collect the raw f32le samples FFmpeg writes to stdout, killing it as soon as the job is cancelled
+    pub fn read_samples(mut self, cancel: &CancelToken) -> Result<Vec<f32>, String> {
+        let stderr_reader = self.read_stderr()?;
+
+        let stdout = self.process.stdout.take().ok_or("No stdout available")?;
+        let samples_reader = thread::spawn(move || read_f32_samples(stdout));
+
+        let status = loop {
+            if cancel.is_cancelled() {
+                eprintln!("🛑 Cancelling FFmpeg");
+                self.kill();
+                let _ = self.process.wait();
+                let _ = stderr_reader.join();
+                let _ = samples_reader.join();
+                return Err(CANCELLED_ERROR.to_string());
+            }
+
+            match self.process.try_wait() {
+                Ok(Some(status)) => break status,
+                Ok(None) => thread::sleep(CANCEL_POLL_INTERVAL),
+                Err(e) => return Err(format!("Failed to wait for FFmpeg: {}", e)),
+            }
+        };
+
+        let stderr = stderr_reader.join().unwrap_or_default();
+        let samples = samples_reader
+            .join()
+            .map_err(|_| "Audio reader thread panicked".to_string())??;
 
         if !status.success() {
-            return Err("FFmpeg command failed".to_string());
+            return Err(format!("FFmpeg failed: {}", stderr));
         }
 
-        Ok(())
+        Ok(samples)
     }
 
     pub fn kill(&mut self) {
         let _ = self.process.kill();
     }
+
+    /// Read all of stderr on a thread, for the error message or silencedetect output
+    fn read_stderr(&mut self) -> Result<JoinHandle<String>, String> {
+        let mut stderr = self.process.stderr.take().ok_or("No stderr available")?;
+        Ok(thread::spawn(move || {
+            let mut buffer = Vec::new();
+            let _ = stderr.read_to_end(&mut buffer);
+            String::from_utf8_lossy(&buffer).to_string()
+        }))
+    }
+}
+
+/// Decode little-endian f32 samples until EOF; a sample may be split across reads
+fn read_f32_samples(mut stdout: ChildStdout) -> Result<Vec<f32>, String> {
+    let mut samples = Vec::new();
+    let mut buffer = vec![0u8; 64 * 1024];
+    let mut filled = 0;
+
+    loop {
+        let read = match stdout.read(&mut buffer[filled..]) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(format!("Failed to read decoded audio: {}", e)),
+        };
+        filled += read;
+
+        let whole = filled - filled % 4;
+        samples.extend(
+            buffer[..whole]
+                .chunks_exact(4)
+                .map(|bytes| f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])),
+        );
+        buffer.copy_within(whole..filled, 0);
+        filled -= whole;
+    }
+
+    Ok(samples)
+}
+
+/// Read `-progress pipe:1` key=value lines and send each `out_time_ms` as seconds
+fn read_progress(stdout: ChildStdout, out_time_tx: Sender<f64>) {
+    let reader = BufReader::new(stdout);
+
+    for line in reader.lines().map_while(Result::ok) {
+        // Despite its name, out_time_ms is in microseconds (and "N/A" before the first frame)
+        if let Some(value) = line.strip_prefix("out_time_ms=") {
+            if let Ok(microseconds) = value.trim().parse::<f64>() {
+                if out_time_tx.send(microseconds / 1_000_000.0).is_err() {
+                    break;
+                }
+            }
+        }
+    }
 }
 
 /// Returns Vec of (silence_start, silence_end) tuples
@@ -93,17 +251,32 @@ pub fn parse_silencedetect(output: &str) -> Vec<(f64, f64)> {
     silences
 }
 
-pub fn extract_audio(
+/// Decode the audio from `start` (seconds) for `duration`, or to the end, as interleaved f32 samples.
+/// They are streamed from FFmpeg's stdout, so nothing is written next to the input.
+pub fn decode_audio(
     input_path: &str,
-    output_path: &str,
+    start: f64,
+    duration: Option<f64>,
     sample_rate: u32,
     num_channels: u16,
-) -> Result<(), String> {
+    cancel: &CancelToken,
+) -> Result<Vec<f32>, String> {
+    cancel.check()?;
+
+    let start_str = format!("{:.3}", start);
+    let duration_str = duration.map(|d| format!("{:.3}", d));
     let sample_rate_str = sample_rate.to_string();
     let num_channels_str = num_channels.to_string();
-    let args = vec![
+
+    // Seeking before -i is fast, and exact for audio
+    let mut args = vec!["-nostdin", "-ss", &start_str];
+    if let Some(ref duration_str) = duration_str {
+        args.extend(["-t", duration_str.as_str()]);
+    }
+    args.extend([
         "-i",
         input_path,
+        "-vn",
         "-ar",
         &sample_rate_str,
         "-ac",
@@ -112,88 +285,54 @@ pub fn extract_audio(
         "f32le",
         "-acodec",
         "pcm_f32le",
-        "-y",
-        output_path,
-    ];
-
-    let mut process = Command::new("ffmpeg")
-        .args(&args)
-        .spawn()
-        .map_err(|e| format!("Failed to spawn FFmpeg: {}", e))?;
-
-    let status = process
-        .wait()
-        .map_err(|e| format!("Failed to wait for FFmpeg: {}", e))?;
-
-    if !status.success() {
-        return Err("FFmpeg extraction failed".to_string());
-    }
-
-    Ok(())
-}
-
-pub fn get_video_duration(input_path: &str) -> Result<f64, String> {
-    let args = vec!["-i", input_path, "-t", "0.000001", "-f", "null", "-"];
-
-    let output = Command::new("ffmpeg")
-        .args(&args)
-        .output()
-        .map_err(|e| format!("Failed to get video duration: {}", e))?;
-
-    // FFmpeg outputs to stderr, not stdout
-    let stderr = String::from_utf8_lossy(&output.stderr);
-    // Match duration with flexible millisecond precision
-    let pattern = Regex::new(r"Duration: (\d{2}):(\d{2}):(\d{2})\.(\d+)").unwrap();
-
-    for line in stderr.lines() {
-        if let Some(captures) = pattern.captures(line) {
-            if let (Ok(h), Ok(m), Ok(s), Ok(ms)) = (
-                captures[1].parse::<f64>(),
-                captures[2].parse::<f64>(),
-                captures[3].parse::<f64>(),
-                captures[4].parse::<f64>(),
-            ) {
-                // ms is in centiseconds (2 digits) or more precision
-                let ms_divisor = 10_f64.powi(captures[4].len() as i32);
-                let duration = h * 3600.0 + m * 60.0 + s + ms / ms_divisor;
-                eprintln!("📹 Video duration: {:.2}s ({:02}:{:02}:{:02})", duration, h as i32, m as i32, s as i32);
-                return Ok(duration);
-            }
-        }
-    }
+        "pipe:1",
+    ]);
 
-    eprintln!("❌ Failed to parse duration from FFmpeg output");
-    eprintln!("FFmpeg stderr: {}", stderr);
-    Err("Could not parse duration".to_string())
+    FFmpegProcess::new("ffmpeg", &args)?.read_samples(cancel)
 }
 
-pub fn enhance_audio(input_path: &str, output_path: &str) -> Result<(), String> {
+pub fn enhance_audio<F>(
+    input_path: &str,
+    output_path: &str,
+    duration: f64,
+    cancel: &CancelToken,
+    on_progress: F,
+) -> Result<(), String>
+where
+    F: FnMut(FFmpegProgress),
+{
     let args = vec![
         "-i",
         input_path,
         "-af",
-        "afftdn=nf=-25,loudnorm=I=-16:TP=-1.5:LRA=11",
+        ENHANCE_AUDIO_FILTER,
         "-c:v",
         "copy",
         "-y",
         output_path,
     ];
 
-    run_ffmpeg_command(args)
+    run_ffmpeg_command_with_progress(args, duration, cancel, on_progress).map(|_| ())
 }
 
 /// Returns Vec of (silence_start, silence_end) tuples
-pub fn detect_silences(
+pub fn detect_silences<F>(
     input_path: &str,
     threshold_db: f64,
     min_duration: f64,
-) -> Result<Vec<(f64, f64)>, String> {
+    duration: f64,
+    cancel: &CancelToken,
+    on_progress: F,
+) -> Result<Vec<(f64, f64)>, String>
+where
+    F: FnMut(FFmpegProgress),
+{
     // threshold_db is already negative (e.g., -30.0), so don't add another minus sign
     let silence_filter = format!("silencedetect=noise={}dB:d={}", threshold_db, min_duration);
     let args = vec!["-i", input_path, "-af", &silence_filter, "-f", "null", "-"];
 
     eprintln!("🔍 Detecting silences with filter: {}", silence_filter);
-    let output = run_ffmpeg_command_raw(args)?;
+    let output = run_ffmpeg_command_with_progress(args, duration, cancel, on_progress)?;
 
     let silences = parse_silencedetect(&output);
     let total_silence: f64 = silences.iter().map(|(s, e)| e - s).sum();
@@ -201,54 +340,111 @@ pub fn detect_silences(
     Ok(silences)
 }
 
-pub fn cut_silences_and_export(
-    input_path: &str,
-    keep_ranges: Vec<(f64, f64)>,
-    output_path: &str,
-    enhance_audio: bool,
-) -> Result<(), String> {
-    // Build the select expression: between(t,start1,end1)+between(t,start2,end2)+...
-    let keep_expr: String = keep_ranges
+/// Build the select expression: between(t,start1,end1)+between(t,start2,end2)+...
+fn keep_ranges_expr(keep_ranges: &[(f64, f64)]) -> String {
+    keep_ranges
         .iter()
         .map(|(start, end)| format!("between(t,{},{})", start, end))
         .collect::<Vec<_>>()
-        .join("+");
-
-    let select_expr = format!("select='{}',setpts=N/FRAME_RATE/TB", keep_expr);
+        .join("+")
+}
 
-    // Build audio filter chain - aselect + optional enhancement
-    let aselect_base = format!("aselect='{}',asetpts=N/SR/TB", keep_expr);
-    let audio_filter = if enhance_audio {
-        format!("{},afftdn=nf=-25,loudnorm=I=-16:TP=-1.5:LRA=11", aselect_base)
+/// Audio filter chain for a cut: aselect + optional enhancement
+pub fn audio_cut_filter(keep_ranges: &[(f64, f64)], enhance_audio: bool) -> String {
+    let aselect_base = format!("aselect='{}',asetpts=N/SR/TB", keep_ranges_expr(keep_ranges));
+    if enhance_audio {
+        format!("{},{}", aselect_base, ENHANCE_AUDIO_FILTER)
     } else {
         aselect_base
+    }
+}
+
+/// Export the kept ranges of `input_path`. `caption_segments` is the transcript used for
+/// burned-in captions when `export.captions` is set.
+#[allow(clippy::too_many_arguments)]
+pub fn cut_silences_and_export<F>(
+    input_path: &str,
+    timeline: &Timeline,
+    output_path: &str,
+    enhance_audio: bool,
+    export: &ExportSettings,
+    caption_segments: &[Segment],
+    cancel: &CancelToken,
+    on_progress: F,
+) -> Result<(), String>
+where
+    F: FnMut(FFmpegProgress),
+{
+    let captions = match export.captions {
+        Some(_) if caption_segments.is_empty() => {
+            eprintln!("⚠️ No transcript to caption, exporting without burned-in captions");
+            None
+        }
+        ref captions => captions.as_ref(),
     };
 
+    if export.mode == ExportMode::SmartCut {
+        if captions.is_some() {
+            eprintln!("⚠️ Burned-in captions need every frame re-encoded, not using smart cut");
+        } else {
+            match smart_cut::probe_source(input_path, export) {
+                Ok(source) => {
+                    return source.export(timeline, output_path, enhance_audio, cancel, on_progress);
+                }
+                Err(e) => eprintln!("⚠️ Smart cut not possible, re-encoding instead: {}", e),
+            }
+        }
+    }
+
+    let encoder = select_encoder(export)?;
+    let video_args = video_encoder_args(&encoder, export);
+
+    // Progress is measured on the output timeline, which is the sum of the kept ranges
+    let output_duration = timeline.output_duration();
+
+    let keep_expr = keep_ranges_expr(timeline.ranges());
+    let mut select_expr = format!("select='{}',setpts=N/FRAME_RATE/TB", keep_expr);
+    let audio_filter = audio_cut_filter(timeline.ranges(), enhance_audio);
+
+    // Captions are timed on the output timeline, so they are drawn after the cut.
+    // The scratch dir keeps the script alive until FFmpeg is done with it.
+    let mut _caption_dir = None;
+    if let Some(captions) = captions {
+        let dir = ScratchDir::new("captions")?;
+        let script_path = dir.file("captions.ass");
+        subtitles::write_karaoke_captions(input_path, caption_segments, timeline, captions, &script_path)?;
+        select_expr.push_str(&format!(",ass=filename={}", filter_path(&script_path)));
+        _caption_dir = Some(dir);
+    }
+
     eprintln!("🎬 Video filter: {}", select_expr);
     eprintln!("🔊 Audio filter: {}", audio_filter);
+    eprintln!("🎞️ Video encoder: {}", video_args.join(" "));
 
-    let args = vec![
+    let mut args = vec![
         "-i", input_path,
         "-vf", &select_expr,
         "-af", &audio_filter,
-        "-c:v", "h264_videotoolbox",
-        "-b:v", "8M",
-        "-maxrate", "10M",
-        "-bufsize", "16M",
-        "-profile:v", "high",
+    ];
+    args.extend(video_args.iter().map(String::as_str));
+    args.extend([
         "-c:a", "aac",
         "-b:a", "192k",
         "-ar", "44100",
-        "-pix_fmt", "yuv420p",
         "-movflags", "+faststart",
         "-y", output_path,
-    ];
+    ]);
+
+    run_ffmpeg_command_with_progress(args, output_duration, cancel, on_progress).map(|_| ())
+}
 
-    run_ffmpeg_command(args)
+/// Quote a file path as a filter option value; colons need escaping inside filtergraphs (`C:\...`)
+fn filter_path(path: &str) -> String {
+    format!("'{}'", path.replace('\\', "/").replace(':', "\\:").replace('\'', "'\\''"))
 }
 
 /// Copy video with re-encoded audio (no video processing)
-pub fn copy_video(input_path: &str, output_path: &str) -> Result<(), String> {
+pub fn copy_video(input_path: &str, output_path: &str, cancel: &CancelToken) -> Result<(), String> {
     let args = vec![
         "-i", input_path,
         "-c:v", "copy",
@@ -259,25 +455,32 @@ pub fn copy_video(input_path: &str, output_path: &str) -> Result<(), String> {
         "-y", output_path,
     ];
 
-    run_ffmpeg_command(args)
+    run_ffmpeg_command(args, cancel)
 }
 
-fn run_ffmpeg_command(args: Vec<&str>) -> Result<(), String> {
-    run_ffmpeg_command_raw(args).map(|_| ())
+pub fn run_ffmpeg_command(args: Vec<&str>, cancel: &CancelToken) -> Result<(), String> {
+    run_ffmpeg_command_raw(args, cancel).map(|_| ())
 }
 
-fn run_ffmpeg_command_raw(args: Vec<&str>) -> Result<String, String> {
-    let output = Command::new("ffmpeg")
-        .args(&args)
-        .output()
-        .map_err(|e| format!("Failed to run FFmpeg: {}", e))?;
+fn run_ffmpeg_command_raw(args: Vec<&str>, cancel: &CancelToken) -> Result<String, String> {
+    run_ffmpeg_command_with_progress(args, 0.0, cancel, |_| {})
+}
 
-    // FFmpeg outputs most info (including silencedetect) to stderr
-    let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+pub fn run_ffmpeg_command_with_progress<F>(
+    args: Vec<&str>,
+    total_duration: f64,
+    cancel: &CancelToken,
+    on_progress: F,
+) -> Result<String, String>
+where
+    F: FnMut(FFmpegProgress),
+{
+    cancel.check()?;
+
+    // Machine-readable progress goes to stdout, the human-readable stats line is dropped
+    let mut full_args = vec!["-progress", "pipe:1", "-nostats"];
+    full_args.extend(args);
 
-    if !output.status.success() {
-        return Err(format!("FFmpeg failed: {}", stderr));
-    }
-
-    Ok(stderr)
+    // FFmpeg outputs most info (including silencedetect) to stderr
+    FFmpegProcess::new("ffmpeg", &full_args)?.wait_with_progress(total_duration, cancel, on_progress)
 }
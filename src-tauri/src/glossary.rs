@@ -0,0 +1,305 @@
+//! Custom vocabulary for Whisper: a prompt that primes it with the terms, and a pass that fixes
+//! the near misses it still produces ("Kubernetis" → "Kubernetes", "chat GPT" → "ChatGPT").
+
+use crate::models::{Confidence, Segment, Word};
+use crate::words::LOW_CONFIDENCE_THRESHOLD;
+
+/// Glossary terms are appended to the prompt up to this many characters;
+/// Whisper only reads the last ~224 tokens of a prompt anyway
+const MAX_PROMPT_CHARS: usize = 800;
+
+/// Text for `FullParams::set_initial_prompt`: the user's prompt followed by the glossary terms.
+/// None when both are empty.
+pub fn initial_prompt(prompt: Option<&str>, glossary: &[String]) -> Option<String> {
+    let mut text = prompt.map(str::trim).unwrap_or_default().to_string();
+
+    let terms: Vec<&str> = glossary.iter().map(|t| t.trim()).filter(|t| !t.is_empty()).collect();
+    if !terms.is_empty() {
+        if !text.is_empty() && !text.ends_with(['.', '!', '?']) {
+            text.push('.');
+        }
+        if !text.is_empty() {
+            text.push(' ');
+        }
+        let mut listed = String::new();
+        for term in terms {
+            if listed.len() + term.len() > MAX_PROMPT_CHARS {
+                break;
+            }
+            if !listed.is_empty() {
+                listed.push_str(", ");
+            }
+            listed.push_str(term);
+        }
+        text.push_str(&listed);
+        text.push('.');
+    }
+
+    // whisper-rs panics on interior NULs
+    let text = text.replace('\0', "");
+    (!text.is_empty()).then_some(text)
+}
+
+/// A glossary term, compared without case, spaces or punctuation
+struct Term<'a> {
+    text: &'a str,
+    words: Vec<&'a str>,
+    key: Vec<char>,
+}
+
+/// Replace words that almost match a glossary term with the term, keeping their timings.
+/// A term may also match a run of words ("chat GPT"); if the word counts match, each word
+/// takes the corresponding term word, otherwise the run becomes one word spanning all of them.
+/// Misspellings are only fixed in words Whisper was unsure of, and a word that is the term in
+/// another case ("react" for "React") is left alone: it is usually the ordinary word.
+/// Returns how many replacements were made.
+pub fn snap_to_glossary(segments: &mut [Segment], glossary: &[String]) -> usize {
+    let terms: Vec<Term> = glossary
+        .iter()
+        .map(|term| Term {
+            text: term.trim(),
+            words: term.split_whitespace().collect(),
+            key: normalized(term),
+        })
+        .filter(|term| !term.key.is_empty())
+        .collect();
+    if terms.is_empty() {
+        return 0;
+    }
+
+    // A term may be split over one more word than it has
+    let max_span = terms.iter().map(|term| term.words.len() + 1).max().unwrap_or(1);
+
+    let mut snapped = 0;
+    for segment in segments.iter_mut() {
+        let words = std::mem::take(&mut segment.words);
+        let mut result: Vec<Word> = Vec::with_capacity(words.len());
+        let mut changed = false;
+        let mut i = 0;
+
+        while i < words.len() {
+            match best_match(&words[i..], &terms, max_span) {
+                Some((span, term)) => {
+                    let replacement = replace_span(&words[i..i + span], term);
+                    let same = replacement.len() == span
+                        && replacement.iter().zip(&words[i..i + span]).all(|(new, old)| new.word == old.word);
+                    if !same {
+                        eprintln!(
+                            "📖 Glossary: {} → {}",
+                            join_words(&words[i..i + span]),
+                            join_words(&replacement)
+                        );
+                        snapped += 1;
+                        changed = true;
+                    }
+                    result.extend(replacement);
+                    i += span;
+                }
+                None => {
+                    result.push(words[i].clone());
+                    i += 1;
+                }
+            }
+        }
+
+        if changed {
+            // Keep Whisper's leading space
+            let leading = if segment.text.starts_with(' ') { " " } else { "" };
+            segment.text = format!("{}{}", leading, join_words(&result));
+        }
+        segment.words = result;
+    }
+
+    snapped
+}
+
+/// Longest run of words at the start of `words` that is within reach of a term, preferring the closest match
+fn best_match<'t, 'a>(words: &[Word], terms: &'t [Term<'a>], max_span: usize) -> Option<(usize, &'t Term<'a>)> {
+    let mut best: Option<(usize, &Term, usize)> = None;
+
+    for span in 1..=max_span.min(words.len()) {
+        let key: Vec<char> = words[..span].iter().flat_map(|w| normalized(&w.word)).collect();
+        if key.is_empty() {
+            continue;
+        }
+
+        for term in terms {
+            let allowed = max_distance(term.key.len());
+            if key.len().abs_diff(term.key.len()) > allowed {
+                continue;
+            }
+            let distance = edit_distance(&key, &term.key);
+            if distance > allowed
+                || (distance > 0 && is_confident(&words[..span]))
+                || (span == 1 && differs_only_in_case(&words[0].word, term.text))
+            {
+                continue;
+            }
+            if best.is_none_or(|(best_span, _, best_distance)| {
+                distance < best_distance || (distance == best_distance && span > best_span)
+            }) {
+                best = Some((span, term, distance));
+            }
+        }
+    }
+
+    best.map(|(span, term, _)| (span, term))
+}
+
+/// Edits allowed for a term of this many letters; a slip in a short word usually makes a different word
+/// ("must" for "Rust", "black" for "Slack")
+fn max_distance(term_len: usize) -> usize {
+    match term_len {
+        0..=5 => 0,
+        6..=8 => 1,
+        9..=12 => 2,
+        _ => 3,
+    }
+}
+
+/// Every word has a confidence at or above `LOW_CONFIDENCE_THRESHOLD`
+fn is_confident(words: &[Word]) -> bool {
+    words
+        .iter()
+        .all(|w| w.confidence.is_some_and(|c| c.mean >= LOW_CONFIDENCE_THRESHOLD))
+}
+
+/// The word without its surrounding punctuation is the term, ignoring case
+fn differs_only_in_case(word: &str, term: &str) -> bool {
+    word.trim_matches(|c: char| !c.is_alphanumeric()).to_lowercase() == term.to_lowercase()
+}
+
+/// The term's words in place of `words`, keeping the punctuation around the run
+fn replace_span(words: &[Word], term: &Term) -> Vec<Word> {
+    let first = &words[0].word;
+    let last = &words[words.len() - 1].word;
+    let leading: String = first.chars().take_while(|c| !c.is_alphanumeric()).collect();
+    let trailing: String = {
+        let mut trailing: Vec<char> = last.chars().rev().take_while(|c| !c.is_alphanumeric()).collect();
+        trailing.reverse();
+        trailing.into_iter().collect()
+    };
+
+    if words.len() == term.words.len() {
+        let count = words.len();
+        return words
+            .iter()
+            .zip(&term.words)
+            .enumerate()
+            .map(|(i, (word, term_word))| {
+                let prefix = if i == 0 { leading.as_str() } else { "" };
+                let suffix = if i == count - 1 { trailing.as_str() } else { "" };
+                Word {
+                    word: format!("{}{}{}", prefix, term_word, suffix),
+                    ..word.clone()
+                }
+            })
+            .collect();
+    }
+
+    let confidences: Option<Vec<Confidence>> = words.iter().map(|w| w.confidence).collect();
+    vec![Word {
+        id: words[0].id.clone(),
+        word: format!("{}{}{}", leading, term.words.join(" "), trailing),
+        start: words[0].start,
+        end: words[words.len() - 1].end,
+        confidence: confidences.map(|c| Confidence {
+            min: c.iter().map(|c| c.min).fold(f64::INFINITY, f64::min),
+            mean: c.iter().map(|c| c.mean).sum::<f64>() / c.len() as f64,
+        }),
+    }]
+}
+
+/// Levenshtein distance over characters
+pub(crate) fn edit_distance(a: &[char], b: &[char]) -> usize {
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+
+    for (i, ca) in a.iter().enumerate() {
+        current[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != cb);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+
+    previous[b.len()]
+}
+
+/// Lowercase letters and digits only
+pub(crate) fn normalized(text: &str) -> Vec<char> {
+    text.chars().filter(|c| c.is_alphanumeric()).flat_map(char::to_lowercase).collect()
+}
+
+fn join_words(words: &[Word]) -> String {
+    words.iter().map(|w| w.word.as_str()).collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// One segment of `text`'s words, each with a mean confidence of `confidence`
+    fn segment(text: &str, confidence: Option<f64>) -> Segment {
+        let words: Vec<Word> = text
+            .split_whitespace()
+            .enumerate()
+            .map(|(i, word)| Word {
+                id: format!("w{}", i),
+                word: word.to_string(),
+                start: i as f64,
+                end: i as f64 + 0.9,
+                confidence: confidence.map(|mean| Confidence { min: mean, mean }),
+            })
+            .collect();
+        Segment {
+            id: 0,
+            start: 0.0,
+            end: words.len() as f64,
+            text: format!(" {}", text),
+            words,
+            confidence: None,
+            avg_logprob: None,
+        }
+    }
+
+    fn snap(text: &str, confidence: Option<f64>, glossary: &[&str]) -> String {
+        let glossary: Vec<String> = glossary.iter().map(|t| t.to_string()).collect();
+        let mut segments = vec![segment(text, confidence)];
+        snap_to_glossary(&mut segments, &glossary);
+        join_words(&segments[0].words)
+    }
+
+    #[test]
+    fn ordinary_words_are_left_alone() {
+        let text = "I just must say this react code is black";
+        for confidence in [None, Some(0.3), Some(0.95)] {
+            assert_eq!(snap(text, confidence, &["Rust", "React", "Slack"]), text);
+        }
+    }
+
+    #[test]
+    fn short_terms_need_an_exact_spelling() {
+        assert_eq!(snap("we ship it in Rast", Some(0.2), &["Rust"]), "we ship it in Rast");
+        assert_eq!(snap("open Slak now", Some(0.2), &["Slack"]), "open Slak now");
+    }
+
+    #[test]
+    fn unsure_near_misses_are_fixed() {
+        assert_eq!(snap("deploy to Kubernetis.", Some(0.4), &["Kubernetes"]), "deploy to Kubernetes.");
+        assert_eq!(snap("we use Postgress", None, &["Postgres"]), "we use Postgres");
+    }
+
+    #[test]
+    fn confident_words_are_not_respelled() {
+        assert_eq!(snap("deploy to Kubernetis", Some(0.9), &["Kubernetes"]), "deploy to Kubernetis");
+    }
+
+    #[test]
+    fn split_and_punctuated_terms_are_joined() {
+        assert_eq!(snap("ask chat GPT about it", Some(0.9), &["ChatGPT"]), "ask ChatGPT about it");
+        assert_eq!(snap("ask Chat-GPT about it", Some(0.9), &["ChatGPT"]), "ask ChatGPT about it");
+        assert_eq!(snap("edit in final cut pro", Some(0.9), &["Final Cut Pro"]), "edit in Final Cut Pro");
+    }
+}
@@ -0,0 +1,45 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+pub const CANCELLED_ERROR: &str = "Job cancelled";
+
+/// How often `CancelToken::cancelled` looks at the flag
+const CANCEL_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Shared cancellation flag for a pipeline job.
+/// FFmpeg runs poll it to kill their child process, Whisper reads it through its abort callback.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Returns `Err(CANCELLED_ERROR)` once the job has been cancelled, for use between stages
+    pub fn check(&self) -> Result<(), String> {
+        if self.is_cancelled() {
+            Err(CANCELLED_ERROR.to_string())
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Resolves once the job has been cancelled, for racing async work in `tokio::select!`
+    pub async fn cancelled(&self) {
+        while !self.is_cancelled() {
+            tokio::time::sleep(CANCEL_POLL_INTERVAL).await;
+        }
+    }
+}
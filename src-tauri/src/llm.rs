@@ -1,189 +1,1338 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::ops::Range;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
-use crate::models::{Segment, Word};
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+
+use crate::align;
+use crate::job::{CancelToken, CANCELLED_ERROR};
+use crate::metadata::{self, format_timestamp, parse_timestamp};
+use crate::models::{Chapter, LlmProviderConfig, Segment, TranscriptChange, VideoMetadata};
+use crate::prompts::{language_name, PromptTemplate};
+
+/// Local models on modest hardware can take minutes for one chunk
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Transcript text sent per request, in characters; about 1500 tokens, well inside any model's context
+/// and short enough for a local model to answer within the timeout
+const CHUNK_CHARS: usize = 6000;
+
+/// Segments sent on each side of a chunk as context; their corrections come from the neighbouring chunk
+const CHUNK_OVERLAP_SEGMENTS: usize = 2;
+
+/// Requests in flight at once
+const MAX_CONCURRENT_REQUESTS: usize = 4;
+
+/// Tries per chunk when the server is busy or failing
+const MAX_ATTEMPTS: u32 = 5;
+
+/// Wait before the first retry, doubled for each one after
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Longest wait between tries, including a server's own `Retry-After`
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// A cleanup reply may have this share more or fewer words than its chunk; past that it was most likely
+/// cut off by the model's output limit, or rewritten instead of corrected
+const MAX_WORD_COUNT_CHANGE: f64 = 0.25;
+
+/// Word count change always allowed, for short chunks where joining a few fragments is a large share
+const MIN_WORD_COUNT_SLACK: usize = 10;
+
+/// Version header the Anthropic Messages API requires
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+
+/// Anthropic requires a reply length; long enough for a corrected transcript chunk
+const ANTHROPIC_MAX_TOKENS: u32 = 8192;
+
+/// One prompt for a chat model
+#[derive(Debug, Clone)]
+pub struct LlmRequest {
+    pub system: String,
+    pub user: String,
+    pub temperature: f32,
+}
+
+/// Why a request to a provider failed
+#[derive(Debug, Clone, PartialEq)]
+pub enum LlmError {
+    /// The server answered with an error status
+    Status {
+        status: u16,
+        message: String,
+        /// From the `Retry-After` header
+        retry_after: Option<Duration>,
+    },
+    /// No answer within `REQUEST_TIMEOUT`
+    Timeout(String),
+    /// Unreachable server, unreadable or unexpected reply
+    Other(String),
+    /// The job was cancelled while waiting
+    Cancelled,
+}
+
+impl LlmError {
+    /// Rate limits, server errors and timeouts usually pass
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            LlmError::Status { status, .. } => *status == 429 || *status >= 500,
+            LlmError::Timeout(_) => true,
+            LlmError::Other(_) | LlmError::Cancelled => false,
+        }
+    }
+}
+
+impl fmt::Display for LlmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LlmError::Status { message, .. } | LlmError::Timeout(message) | LlmError::Other(message) => {
+                f.write_str(message)
+            }
+            LlmError::Cancelled => f.write_str(CANCELLED_ERROR),
+        }
+    }
+}
+
+impl From<LlmError> for String {
+    fn from(error: LlmError) -> Self {
+        error.to_string()
+    }
+}
+
+/// A chat model that answers a prompt with text
+#[async_trait]
+pub trait LlmProvider: Send + Sync {
+    /// Provider and model, for logs and errors
+    fn name(&self) -> String;
+
+    async fn complete(&self, request: &LlmRequest) -> Result<String, LlmError>;
+}
+
+/// The provider a config describes
+pub fn provider_from_config(config: &LlmProviderConfig) -> Result<Arc<dyn LlmProvider>, String> {
+    let client = reqwest::Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .build()
+        .map_err(|e| format!("Failed to build HTTP client: {}", e))?;
+
+    Ok(match config.clone() {
+        LlmProviderConfig::OpenAiCompatible {
+            base_url,
+            model,
+            api_key,
+            headers,
+        } => Arc::new(OpenAiCompatible {
+            client,
+            base_url,
+            model,
+            api_key,
+            headers,
+        }),
+        LlmProviderConfig::Ollama { base_url, model } => Arc::new(Ollama { client, base_url, model }),
+        LlmProviderConfig::Anthropic {
+            base_url,
+            model,
+            api_key,
+        } => Arc::new(Anthropic {
+            client,
+            base_url,
+            model,
+            api_key,
+        }),
+    })
+}
 
-// Z.ai API request format (OpenAI-compatible)
 #[derive(Debug, Serialize)]
-struct ZAIRequest {
+struct ChatMessage<'a> {
+    role: &'a str,
+    content: &'a str,
+}
+
+/// `POST {base_url}/chat/completions`
+pub struct OpenAiCompatible {
+    client: reqwest::Client,
+    base_url: String,
     model: String,
-    messages: Vec<Message>,
-    temperature: f32,
+    api_key: Option<String>,
+    headers: BTreeMap<String, String>,
 }
 
 #[derive(Debug, Serialize)]
-struct Message {
-    role: String,
-    content: String,
+struct ChatCompletionRequest<'a> {
+    model: &'a str,
+    messages: Vec<ChatMessage<'a>>,
+    temperature: f32,
 }
 
-// Z.ai API response format
 #[derive(Debug, Deserialize)]
-struct ZAIResponse {
-    choices: Vec<Choice>,
+struct ChatCompletionResponse {
+    choices: Vec<ChatCompletionChoice>,
 }
 
 #[derive(Debug, Deserialize)]
-struct Choice {
-    message: ResponseMessage,
+struct ChatCompletionChoice {
+    message: ChatCompletionMessage,
 }
 
 #[derive(Debug, Deserialize)]
-struct ResponseMessage {
+struct ChatCompletionMessage {
     content: String,
 }
 
-/// Clean up transcription using Z.ai GLM-4.7-Flash to fix word fragments and errors
+#[async_trait]
+impl LlmProvider for OpenAiCompatible {
+    fn name(&self) -> String {
+        format!("{} at {}", self.model, self.base_url)
+    }
+
+    async fn complete(&self, request: &LlmRequest) -> Result<String, LlmError> {
+        let body = ChatCompletionRequest {
+            model: &self.model,
+            messages: vec![
+                ChatMessage {
+                    role: "system",
+                    content: &request.system,
+                },
+                ChatMessage {
+                    role: "user",
+                    content: &request.user,
+                },
+            ],
+            temperature: request.temperature,
+        };
+
+        let mut builder = self.client.post(endpoint(&self.base_url, "chat/completions")).json(&body);
+        if let Some(ref api_key) = self.api_key {
+            builder = builder.bearer_auth(api_key);
+        }
+        for (name, value) in &self.headers {
+            builder = builder.header(name, value);
+        }
+
+        let response: ChatCompletionResponse = send(builder, &self.name()).await?;
+        response
+            .choices
+            .into_iter()
+            .next()
+            .map(|choice| choice.message.content)
+            .ok_or_else(|| LlmError::Other(format!("{} returned no choices", self.name())))
+    }
+}
+
+/// `POST {base_url}/api/chat`, without streaming
+pub struct Ollama {
+    client: reqwest::Client,
+    base_url: String,
+    model: String,
+}
+
+#[derive(Debug, Serialize)]
+struct OllamaChatRequest<'a> {
+    model: &'a str,
+    messages: Vec<ChatMessage<'a>>,
+    stream: bool,
+    options: OllamaOptions,
+}
+
+#[derive(Debug, Serialize)]
+struct OllamaOptions {
+    temperature: f32,
+}
+
+#[derive(Debug, Deserialize)]
+struct OllamaChatResponse {
+    message: ChatCompletionMessage,
+}
+
+#[async_trait]
+impl LlmProvider for Ollama {
+    fn name(&self) -> String {
+        format!("Ollama {} at {}", self.model, self.base_url)
+    }
+
+    async fn complete(&self, request: &LlmRequest) -> Result<String, LlmError> {
+        let body = OllamaChatRequest {
+            model: &self.model,
+            messages: vec![
+                ChatMessage {
+                    role: "system",
+                    content: &request.system,
+                },
+                ChatMessage {
+                    role: "user",
+                    content: &request.user,
+                },
+            ],
+            stream: false,
+            options: OllamaOptions {
+                temperature: request.temperature,
+            },
+        };
+
+        let builder = self.client.post(endpoint(&self.base_url, "api/chat")).json(&body);
+        let response: OllamaChatResponse = send(builder, &self.name()).await?;
+        Ok(response.message.content)
+    }
+}
+
+/// `POST {base_url}/v1/messages`
+pub struct Anthropic {
+    client: reqwest::Client,
+    base_url: String,
+    model: String,
+    api_key: String,
+}
+
+#[derive(Debug, Serialize)]
+struct MessagesRequest<'a> {
+    model: &'a str,
+    max_tokens: u32,
+    system: &'a str,
+    messages: Vec<ChatMessage<'a>>,
+    temperature: f32,
+}
+
+#[derive(Debug, Deserialize)]
+struct MessagesResponse {
+    content: Vec<ContentBlock>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ContentBlock {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    text: String,
+}
+
+#[async_trait]
+impl LlmProvider for Anthropic {
+    fn name(&self) -> String {
+        format!("{} at {}", self.model, self.base_url)
+    }
+
+    async fn complete(&self, request: &LlmRequest) -> Result<String, LlmError> {
+        let body = MessagesRequest {
+            model: &self.model,
+            max_tokens: ANTHROPIC_MAX_TOKENS,
+            system: &request.system,
+            messages: vec![ChatMessage {
+                role: "user",
+                content: &request.user,
+            }],
+            temperature: request.temperature,
+        };
+
+        let builder = self
+            .client
+            .post(endpoint(&self.base_url, "v1/messages"))
+            .header("x-api-key", &self.api_key)
+            .header("anthropic-version", ANTHROPIC_VERSION)
+            .json(&body);
+
+        let response: MessagesResponse = send(builder, &self.name()).await?;
+        let text: String = response
+            .content
+            .into_iter()
+            .filter(|block| block.kind == "text")
+            .map(|block| block.text)
+            .collect();
+
+        if text.is_empty() {
+            Err(LlmError::Other(format!("{} returned no text", self.name())))
+        } else {
+            Ok(text)
+        }
+    }
+}
+
+/// `base_url` and `path` joined with exactly one slash
+fn endpoint(base_url: &str, path: &str) -> String {
+    format!("{}/{}", base_url.trim_end_matches('/'), path)
+}
+
+/// Send a request and parse its JSON reply; non-2xx statuses become errors with the body
+async fn send<T: DeserializeOwned>(builder: reqwest::RequestBuilder, name: &str) -> Result<T, LlmError> {
+    eprintln!("📤 Sending request to {}...", name);
+
+    let response = builder.send().await.map_err(|e| {
+        let message = format!("Failed to call {}: {}", name, e);
+        if e.is_timeout() {
+            LlmError::Timeout(message)
+        } else {
+            LlmError::Other(message)
+        }
+    })?;
+
+    let status = response.status();
+    let retry_after = response
+        .headers()
+        .get(reqwest::header::RETRY_AFTER)
+        .and_then(|value| value.to_str().ok()?.trim().parse().ok())
+        .map(Duration::from_secs);
+    let text = response
+        .text()
+        .await
+        .map_err(|e| LlmError::Other(format!("Failed to read response from {}: {}", name, e)))?;
+
+    if !status.is_success() {
+        eprintln!("❌ {} error {}: {}", name, status, text);
+        return Err(LlmError::Status {
+            status: status.as_u16(),
+            message: format!("{} error {}: {}", name, status, text),
+            retry_after,
+        });
+    }
+
+    serde_json::from_str(&text)
+        .map_err(|e| LlmError::Other(format!("Failed to parse response from {}: {} - Response: {}", name, e, text)))
+}
+
+/// `provider.complete`, retrying rate limits and server errors with exponential backoff.
+/// Cancelling stops both a request in flight and the wait before the next attempt.
+pub async fn complete_with_retry(
+    provider: &dyn LlmProvider,
+    request: &LlmRequest,
+    cancel: &CancelToken,
+) -> Result<String, LlmError> {
+    let mut backoff = INITIAL_BACKOFF;
+    let mut attempt = 1;
+    loop {
+        if cancel.is_cancelled() {
+            return Err(LlmError::Cancelled);
+        }
+        let outcome = tokio::select! {
+            outcome = provider.complete(request) => outcome,
+            _ = cancel.cancelled() => return Err(LlmError::Cancelled),
+        };
+
+        match outcome {
+            Err(error) if error.is_retryable() && attempt < MAX_ATTEMPTS => {
+                let wait = match &error {
+                    LlmError::Status {
+                        retry_after: Some(retry_after),
+                        ..
+                    } => *retry_after,
+                    _ => backoff,
+                }
+                .min(MAX_BACKOFF);
+                eprintln!("🔁 {} (attempt {} of {}); retrying in {:?}", error, attempt, MAX_ATTEMPTS, wait);
+                tokio::select! {
+                    _ = tokio::time::sleep(wait) => {}
+                    _ = cancel.cancelled() => return Err(LlmError::Cancelled),
+                }
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+                attempt += 1;
+            }
+            outcome => return outcome,
+        }
+    }
+}
+
+/// Send `requests` at most `MAX_CONCURRENT_REQUESTS` at a time, handing each outcome and its index to
+/// `on_reply` as it arrives. Stops early when cancelled or when `on_reply` fails, aborting the rest.
+async fn complete_all(
+    provider: &Arc<dyn LlmProvider>,
+    requests: Vec<LlmRequest>,
+    cancel: &CancelToken,
+    mut on_reply: impl FnMut(usize, Result<String, LlmError>) -> Result<(), String>,
+) -> Result<(), String> {
+    let semaphore = Arc::new(Semaphore::new(MAX_CONCURRENT_REQUESTS));
+    let mut running = JoinSet::new();
+    for (index, request) in requests.into_iter().enumerate() {
+        let provider = provider.clone();
+        let semaphore = semaphore.clone();
+        let cancel = cancel.clone();
+
+        running.spawn(async move {
+            let _permit = semaphore.acquire_owned().await;
+            (index, complete_with_retry(provider.as_ref(), &request, &cancel).await)
+        });
+    }
+
+    // Dropping `running` on an early return aborts the requests still going
+    loop {
+        let joined = tokio::select! {
+            joined = running.join_next() => joined,
+            _ = cancel.cancelled() => return Err(CANCELLED_ERROR.to_string()),
+        };
+        let Some(joined) = joined else { return Ok(()) };
+        let (index, reply) = joined.map_err(|e| format!("LLM request failed: {}", e))?;
+        on_reply(index, reply)?;
+    }
+}
+
+/// Segments sent in one request: `owned` are corrected from this request's reply, the rest of `context`
+/// only helps the model read the edges
+#[derive(Debug, Clone, PartialEq)]
+struct CleanupChunk {
+    context: Range<usize>,
+    owned: Range<usize>,
+}
+
+/// Runs of whole segments of about `CHUNK_CHARS`, each with `CHUNK_OVERLAP_SEGMENTS` more on both sides
+fn cleanup_chunks(segments: &[Segment]) -> Vec<CleanupChunk> {
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    while start < segments.len() {
+        let mut end = start;
+        let mut chars = 0;
+        while end < segments.len() && (end == start || chars + segments[end].text.len() <= CHUNK_CHARS) {
+            chars += segments[end].text.len();
+            end += 1;
+        }
+        chunks.push(CleanupChunk {
+            context: start.saturating_sub(CHUNK_OVERLAP_SEGMENTS)..(end + CHUNK_OVERLAP_SEGMENTS).min(segments.len()),
+            owned: start..end,
+        });
+        start = end;
+    }
+    chunks
+}
+
+/// Prompt for the cleanup pass: the template and what to fill into it besides the transcript
+pub struct CleanupPrompt<'a> {
+    pub template: &'a PromptTemplate,
+    /// The transcript's language code
+    pub language: Option<&'a str>,
+    pub glossary: &'a [String],
+}
+
+/// Ask the LLM to fix word fragments and errors; returns its edits, aligned with Whisper's words.
+/// Long transcripts are sent in chunks, several at a time, with `on_progress` getting the fraction done.
+/// A chunk that still fails after retries is left uncorrected; only if every chunk fails is it an error.
 pub async fn clean_transcript_with_llm(
     segments: &[Segment],
-    api_key: &str,
-) -> Result<Vec<Segment>, String> {
-    eprintln!("🤖 Cleaning transcript with Z.ai GLM-4.7-flash...");
+    provider: Arc<dyn LlmProvider>,
+    prompt: &CleanupPrompt<'_>,
+    cancel: &CancelToken,
+    on_progress: impl Fn(f64) -> Result<(), String>,
+) -> Result<Vec<TranscriptChange>, String> {
+    let chunks = cleanup_chunks(segments);
+    eprintln!(
+        "🤖 Cleaning transcript with {} in {} chunk(s)...",
+        provider.name(),
+        chunks.len()
+    );
 
-    // Build the full transcript text
-    let full_text: Vec<String> = segments.iter().map(|s| s.text.clone()).collect();
-    let transcript_text = full_text.join(" ");
+    let language = prompt.language.map(language_name).unwrap_or_else(|| "unknown".to_string());
+    let glossary = if prompt.glossary.is_empty() {
+        "none".to_string()
+    } else {
+        prompt.glossary.join(", ")
+    };
 
-    eprintln!("📝 Original text length: {} chars", transcript_text.len());
+    let requests = chunks
+        .iter()
+        .map(|chunk| {
+            let text: Vec<&str> = segments[chunk.context.clone()].iter().map(|s| s.text.trim()).collect();
+            let (system, user) = prompt.template.render(&[
+                ("transcript", &text.join(" ")),
+                ("glossary", &glossary),
+                ("language", &language),
+            ]);
+            LlmRequest {
+                system,
+                user,
+                temperature: 0.3,
+            }
+        })
+        .collect();
 
-    // Create the prompt
-    let system_prompt = "You are a transcription correction assistant. Fix word fragments and spelling errors in Spanish transcriptions while maintaining the same word count and timing.";
+    let mut replies: Vec<Option<String>> = vec![None; chunks.len()];
+    let mut first_error = None;
+    let mut finished = 0;
+    complete_all(&provider, requests, cancel, |index, reply| {
+        match reply.and_then(|text| check_cleanup_reply(&segments[chunks[index].context.clone()], text.trim())) {
+            Ok(text) => replies[index] = Some(text),
+            Err(e) => {
+                eprintln!("⚠️ Chunk {} of {} left as is: {}", index + 1, chunks.len(), e);
+                first_error.get_or_insert(e);
+            }
+        }
+        finished += 1;
+        on_progress(finished as f64 / chunks.len() as f64)
+    })
+    .await?;
+
+    if replies.iter().all(Option::is_none) {
+        return Err(match first_error {
+            Some(error) => error.into(),
+            None => format!("{} returned an empty transcript", provider.name()),
+        });
+    }
 
-    let user_prompt = format!(
-        r#"Fix this Spanish transcription from Whisper AI. It has word fragments that need to be joined:
+    Ok(merge_chunk_changes(segments, &chunks, &replies))
+}
 
-Rules:
-1. Join word fragments (e.g., "dis av ivo" → "dispositivo", "nego cios" → "negocios", "fr acas ar" → "fracasar")
-2. Fix obvious spelling errors
-3. Keep the same approximate word count (don't add or remove content)
-4. Minimal punctuation
-5. Return ONLY the corrected text, no explanations
+/// The reply, unless it is empty or its length shows it isn't the chunk's transcript with fixes
+fn check_cleanup_reply(segments: &[Segment], reply: &str) -> Result<String, LlmError> {
+    let original = segments.iter().map(|s| s.words.len()).sum::<usize>();
+    let corrected = reply.split_whitespace().count();
+    if corrected == 0 {
+        return Err(LlmError::Other("Empty reply".to_string()));
+    }
+    let allowed = ((original as f64 * MAX_WORD_COUNT_CHANGE) as usize).max(MIN_WORD_COUNT_SLACK);
+    if original.abs_diff(corrected) > allowed {
+        return Err(LlmError::Other(format!(
+            "Reply has {} words for {} in the transcript; truncated or rewritten",
+            corrected, original
+        )));
+    }
+    Ok(reply.to_string())
+}
 
-Original: {}
+/// Each chunk's edits to the segments it owns, in transcript order and with transcript-wide positions.
+/// An edit that reaches into the next chunk's segments wins over that chunk's edits to the same words.
+fn merge_chunk_changes(segments: &[Segment], chunks: &[CleanupChunk], replies: &[Option<String>]) -> Vec<TranscriptChange> {
+    // Words before each segment
+    let mut word_offsets = Vec::with_capacity(segments.len() + 1);
+    let mut words = 0;
+    for segment in segments {
+        word_offsets.push(words);
+        words += segment.words.len();
+    }
+    word_offsets.push(words);
 
-Corrected:"#,
-        transcript_text
-    );
+    let mut changes: Vec<TranscriptChange> = Vec::new();
+    let mut covered_until = 0;
+    for (chunk, reply) in chunks.iter().zip(replies) {
+        let Some(reply) = reply else { continue };
+        let offset = word_offsets[chunk.context.start];
+        let owned = word_offsets[chunk.owned.start]..word_offsets[chunk.owned.end];
 
-    // Call Z.ai API (OpenAI-compatible endpoint)
-    let client = reqwest::Client::builder()
-        .timeout(std::time::Duration::from_secs(60))
-        .build()
-        .map_err(|e| format!("Failed to build HTTP client: {}", e))?;
+        for mut change in align::align(&segments[chunk.context.clone()], reply) {
+            change.position += offset;
+            // An insert after the last word belongs to the last chunk
+            let at_end = change.original.is_empty() && change.position == owned.end && chunk.owned.end == segments.len();
+            let inside = owned.contains(&change.position) || at_end;
+            if !inside || change.position < covered_until {
+                continue;
+            }
+            covered_until = change.position + change.original.len();
+            changes.push(change);
+        }
+    }
 
-    let url = "https://api.z.ai/api/paas/v4/chat/completions";
+    align::renumber(&mut changes);
+    changes
+}
 
-    let request_body = ZAIRequest {
-        model: "GLM-4.7-Flash".to_string(),
-        messages: vec![
-            Message {
-                role: "system".to_string(),
-                content: system_prompt.to_string(),
-            },
-            Message {
-                role: "user".to_string(),
-                content: user_prompt,
-            },
-        ],
-        temperature: 0.3,
+/// Metadata as the LLM writes it, before its chapter starts are checked against the transcript
+#[derive(Deserialize)]
+struct MetadataReply {
+    #[serde(default)]
+    titles: Vec<String>,
+    #[serde(default)]
+    description: String,
+    #[serde(default)]
+    tags: Vec<String>,
+    #[serde(default)]
+    chapters: Vec<ChapterReply>,
+}
+
+#[derive(Deserialize)]
+struct ChapterReply {
+    title: String,
+    start: ChapterStart,
+}
+
+/// Models write the start as in the transcript ("12:34") or as seconds
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ChapterStart {
+    Seconds(f64),
+    Text(String),
+}
+
+/// Prompts for writing metadata: `template` for the transcript, or each chunk of a long one, and
+/// `merge_template` to combine the chunks' drafts
+pub struct MetadataPrompt<'a> {
+    pub template: &'a PromptTemplate,
+    pub merge_template: &'a PromptTemplate,
+    /// The transcript's language code
+    pub language: Option<&'a str>,
+}
+
+/// Ask the LLM for titles, a description, tags and chapters, from one timestamped line per segment.
+/// A long transcript is drafted in the same chunks as cleanup, then the drafts are merged in one more request.
+/// Chapter starts come back snapped to segment starts, in source time.
+pub async fn generate_metadata(
+    segments: &[Segment],
+    provider: Arc<dyn LlmProvider>,
+    prompt: &MetadataPrompt<'_>,
+    cancel: &CancelToken,
+) -> Result<VideoMetadata, String> {
+    if segments.is_empty() {
+        return Err("No transcript to write metadata for".to_string());
+    }
+    let chunks = cleanup_chunks(segments);
+    eprintln!("🤖 Generating metadata with {} from {} chunk(s)...", provider.name(), chunks.len());
+
+    let with_hours = segments.last().is_some_and(|s| s.start >= 3600.0);
+    let language = prompt
+        .language
+        .map(language_name)
+        .unwrap_or_else(|| "the transcript's language".to_string());
+    let requests = chunks
+        .iter()
+        .map(|chunk| {
+            let lines: Vec<String> = segments[chunk.owned.clone()]
+                .iter()
+                .map(|s| format!("[{}] {}", format_timestamp(s.start, with_hours), s.text.trim()))
+                .collect();
+            let (system, user) = prompt
+                .template
+                .render(&[("transcript", &lines.join("\n")), ("language", &language)]);
+            LlmRequest {
+                system,
+                user,
+                temperature: 0.5,
+            }
+        })
+        .collect();
+
+    let name = provider.name();
+    let mut drafts: Vec<Option<VideoMetadata>> = vec![None; chunks.len()];
+    complete_all(&provider, requests, cancel, |index, reply| {
+        drafts[index] = Some(parse_metadata_reply(&reply?, &name)?);
+        Ok(())
+    })
+    .await?;
+    let mut drafts: Vec<VideoMetadata> = drafts.into_iter().flatten().collect();
+
+    let mut metadata = if drafts.len() == 1 {
+        drafts.remove(0)
+    } else {
+        let parts: Vec<String> = drafts
+            .iter()
+            .zip(&chunks)
+            .enumerate()
+            .map(|(i, (draft, chunk))| {
+                let start = segments[chunk.owned.start].start;
+                let end = segments[chunk.owned.end - 1].end;
+                let chapters: Vec<String> = draft
+                    .chapters
+                    .iter()
+                    .map(|c| format!("[{}] {}", format_timestamp(c.start, with_hours), c.title))
+                    .collect();
+                format!(
+                    "Part {} ({} to {})\nTitles: {}\nDescription: {}\nTags: {}\nChapters:\n{}",
+                    i + 1,
+                    format_timestamp(start, with_hours),
+                    format_timestamp(end, with_hours),
+                    draft.titles.join(" | "),
+                    draft.description,
+                    draft.tags.join(", "),
+                    chapters.join("\n")
+                )
+            })
+            .collect();
+        let (system, user) = prompt
+            .merge_template
+            .render(&[("parts", &parts.join("\n\n")), ("language", &language)]);
+        let request = LlmRequest {
+            system,
+            user,
+            temperature: 0.5,
+        };
+        let mut merged = parse_metadata_reply(&complete_with_retry(provider.as_ref(), &request, cancel).await?, &name)?;
+        // The drafts' chapters stand when the merge leaves them out
+        if merged.chapters.is_empty() {
+            merged.chapters = drafts.into_iter().flat_map(|d| d.chapters).collect();
+        }
+        merged
     };
 
-    eprintln!("📤 Sending request to Z.ai...");
+    metadata.chapters = metadata::snap_to_segments(metadata.chapters, segments);
+    eprintln!(
+        "✨ Metadata: {} titles, {} tags, {} chapters",
+        metadata.titles.len(),
+        metadata.tags.len(),
+        metadata.chapters.len()
+    );
+    Ok(metadata)
+}
 
-    let response = client
-        .post(url)
-        .header("Authorization", format!("Bearer {}", api_key))
-        .json(&request_body)
-        .send()
-        .await
-        .map_err(|e| format!("Failed to call Z.ai API: {}", e))?;
+/// The JSON object in a metadata reply, with chapter starts read but not yet snapped
+fn parse_metadata_reply(reply: &str, provider_name: &str) -> Result<VideoMetadata, String> {
+    // Models like to wrap JSON in a code fence or a sentence; the object is what counts
+    let json = match (reply.find('{'), reply.rfind('}')) {
+        (Some(start), Some(end)) if start < end => &reply[start..=end],
+        _ => return Err(format!("{} returned no JSON object: {}", provider_name, reply)),
+    };
+    let parsed: MetadataReply = serde_json::from_str(json)
+        .map_err(|e| format!("Failed to parse metadata from {}: {} - Response: {}", provider_name, e, json))?;
+
+    let chapters = parsed
+        .chapters
+        .into_iter()
+        .filter_map(|chapter| {
+            let start = match &chapter.start {
+                ChapterStart::Seconds(seconds) => Some(*seconds),
+                ChapterStart::Text(text) => parse_timestamp(text),
+            };
+            if start.is_none() {
+                eprintln!("⚠️ Dropping chapter \"{}\": unreadable start", chapter.title);
+            }
+            Some(Chapter {
+                title: chapter.title,
+                start: start?,
+            })
+        })
+        .collect();
+    let clean = |items: Vec<String>| -> Vec<String> {
+        items.into_iter().map(|i| i.trim().to_string()).filter(|i| !i.is_empty()).collect()
+    };
+
+    Ok(VideoMetadata {
+        titles: clean(parsed.titles),
+        description: parsed.description.trim().to_string(),
+        tags: clean(parsed.tags),
+        chapters,
+    })
+}
 
-    eprintln!("📥 Received response with status: {}", response.status());
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{ChangeKind, Word};
+    use crate::prompts::{PromptTask, PromptTemplates};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
 
-    if !response.status().is_success() {
-        let status = response.status();
-        let error_text = response.text().await.unwrap_or_default();
-        eprintln!("❌ Z.ai API error {}: {}", status, error_text);
-        return Err(format!("Z.ai API error {}: {}", status, error_text));
+    /// A request as the mock server received it
+    struct Received {
+        head: String,
+        body: serde_json::Value,
     }
 
-    eprintln!("🔄 Parsing response...");
+    impl Received {
+        fn header(&self, name: &str) -> Option<&str> {
+            self.head.lines().find_map(|line| {
+                let (key, value) = line.split_once(':')?;
+                key.eq_ignore_ascii_case(name).then(|| value.trim())
+            })
+        }
+    }
 
-    let response_text = response.text().await
-        .map_err(|e| format!("Failed to read response: {}", e))?;
+    /// Serve one request with `status` and `reply`; returns the base URL and the request it got
+    async fn mock_server(status: u16, reply: &str) -> (String, tokio::task::JoinHandle<Received>) {
+        let reply = reply.to_string();
+        let (base_url, server) = mock_server_with(1, move |_| (status, reply.clone())).await;
+        (base_url, tokio::spawn(async move { server.await.unwrap().remove(0) }))
+    }
 
-    eprintln!("📄 Response preview: {}", &response_text[..response_text.len().min(200)]);
+    /// Serve `count` requests, one per connection, answering each with `respond`'s status and body
+    async fn mock_server_with(
+        count: usize,
+        respond: impl Fn(&Received) -> (u16, String) + Send + 'static,
+    ) -> (String, tokio::task::JoinHandle<Vec<Received>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
 
-    let zai_response: ZAIResponse = serde_json::from_str(&response_text)
-        .map_err(|e| format!("Failed to parse Z.ai response: {} - Response: {}", e, response_text))?;
+        let handle = tokio::spawn(async move {
+            let mut requests = Vec::new();
+            for _ in 0..count {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut data = Vec::new();
+                let mut buf = [0u8; 4096];
 
-    eprintln!("✅ Successfully parsed response");
+                let head_end = loop {
+                    let n = socket.read(&mut buf).await.unwrap();
+                    data.extend_from_slice(&buf[..n]);
+                    if let Some(pos) = data.windows(4).position(|w| w == b"\r\n\r\n") {
+                        break pos + 4;
+                    }
+                };
+                let mut received = Received {
+                    head: String::from_utf8_lossy(&data[..head_end]).to_string(),
+                    body: serde_json::Value::Null,
+                };
+                let length: usize = received.header("content-length").and_then(|v| v.parse().ok()).unwrap_or(0);
+                while data.len() < head_end + length {
+                    let n = socket.read(&mut buf).await.unwrap();
+                    data.extend_from_slice(&buf[..n]);
+                }
+                received.body = serde_json::from_slice(&data[head_end..head_end + length]).unwrap();
 
-    let cleaned_text = zai_response
-        .choices
-        .first()
-        .map(|c| c.message.content.trim().to_string())
-        .ok_or("No response from Z.ai")?;
+                let (status, reply) = respond(&received);
+                let response = format!(
+                    "HTTP/1.1 {} Mock\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    reply.len(),
+                    reply
+                );
+                socket.write_all(response.as_bytes()).await.unwrap();
+                socket.shutdown().await.ok();
+                requests.push(received);
+            }
+            requests
+        });
 
-    eprintln!("✨ Cleaned text length: {} chars", cleaned_text.len());
-    eprintln!("📊 Sample: {}", &cleaned_text[..cleaned_text.len().min(100)]);
+        (base_url, handle)
+    }
 
-    // Now we need to redistribute this cleaned text back to segments with timestamps
-    redistribute_text_to_segments(segments, &cleaned_text)
-}
+    /// A segment of 0.4s words starting at `start`
+    fn segment(id: usize, start: f64, words: &[&str]) -> Segment {
+        let words: Vec<Word> = words
+            .iter()
+            .enumerate()
+            .map(|(i, word)| Word {
+                id: format!("s{}w{}", id, i),
+                word: word.to_string(),
+                start: start + i as f64 * 0.4,
+                end: start + (i + 1) as f64 * 0.4,
+                confidence: None,
+            })
+            .collect();
+        Segment {
+            id,
+            start,
+            end: words.last().map_or(start, |w| w.end),
+            text: words.iter().map(|w| format!(" {}", w.word)).collect(),
+            words,
+            confidence: None,
+            avg_logprob: None,
+        }
+    }
+
+    fn request() -> LlmRequest {
+        LlmRequest {
+            system: "Fix the text".to_string(),
+            user: "dis av ivo".to_string(),
+            temperature: 0.3,
+        }
+    }
+
+    #[tokio::test]
+    async fn openai_compatible_sends_chat_completion() {
+        let (base_url, server) =
+            mock_server(200, r#"{"choices":[{"message":{"role":"assistant","content":"dispositivo"}}]}"#).await;
+        let provider = provider_from_config(&LlmProviderConfig::OpenAiCompatible {
+            base_url: format!("{}/v1/", base_url),
+            model: "local-model".to_string(),
+            api_key: Some("secret".to_string()),
+            headers: BTreeMap::from([("X-Tenant".to_string(), "studio".to_string())]),
+        })
+        .unwrap();
+
+        assert_eq!(provider.complete(&request()).await.unwrap(), "dispositivo");
+
+        let received = server.await.unwrap();
+        assert!(received.head.starts_with("POST /v1/chat/completions "));
+        assert_eq!(received.header("authorization"), Some("Bearer secret"));
+        assert_eq!(received.header("x-tenant"), Some("studio"));
+        assert_eq!(received.body["model"], "local-model");
+        assert_eq!(received.body["messages"][0]["role"], "system");
+        assert_eq!(received.body["messages"][0]["content"], "Fix the text");
+        assert_eq!(received.body["messages"][1]["role"], "user");
+        assert_eq!(received.body["messages"][1]["content"], "dis av ivo");
+    }
+
+    #[tokio::test]
+    async fn openai_compatible_without_key_sends_no_authorization() {
+        let (base_url, server) = mock_server(200, r#"{"choices":[{"message":{"content":"ok"}}]}"#).await;
+        let provider = provider_from_config(&LlmProviderConfig::OpenAiCompatible {
+            base_url,
+            model: "local-model".to_string(),
+            api_key: None,
+            headers: BTreeMap::new(),
+        })
+        .unwrap();
+
+        provider.complete(&request()).await.unwrap();
+
+        let received = server.await.unwrap();
+        assert!(received.head.starts_with("POST /chat/completions "));
+        assert_eq!(received.header("authorization"), None);
+    }
+
+    #[tokio::test]
+    async fn ollama_sends_chat_without_streaming() {
+        let (base_url, server) =
+            mock_server(200, r#"{"model":"llama3","message":{"role":"assistant","content":"dispositivo"},"done":true}"#)
+                .await;
+        let provider = provider_from_config(&LlmProviderConfig::Ollama {
+            base_url,
+            model: "llama3".to_string(),
+        })
+        .unwrap();
+
+        assert_eq!(provider.complete(&request()).await.unwrap(), "dispositivo");
+
+        let received = server.await.unwrap();
+        assert!(received.head.starts_with("POST /api/chat "));
+        assert_eq!(received.body["model"], "llama3");
+        assert_eq!(received.body["stream"], false);
+        assert_eq!(received.body["messages"][1]["content"], "dis av ivo");
+        assert!((received.body["options"]["temperature"].as_f64().unwrap() - 0.3).abs() < 1e-6);
+    }
+
+    #[tokio::test]
+    async fn anthropic_sends_messages_request() {
+        let (base_url, server) = mock_server(
+            200,
+            r#"{"content":[{"type":"text","text":"dispo"},{"type":"text","text":"sitivo"}],"stop_reason":"end_turn"}"#,
+        )
+        .await;
+        let provider = provider_from_config(&LlmProviderConfig::Anthropic {
+            base_url,
+            model: "claude-model".to_string(),
+            api_key: "secret".to_string(),
+        })
+        .unwrap();
+
+        assert_eq!(provider.complete(&request()).await.unwrap(), "dispositivo");
+
+        let received = server.await.unwrap();
+        assert!(received.head.starts_with("POST /v1/messages "));
+        assert_eq!(received.header("x-api-key"), Some("secret"));
+        assert_eq!(received.header("anthropic-version"), Some(ANTHROPIC_VERSION));
+        assert_eq!(received.body["system"], "Fix the text");
+        assert_eq!(received.body["max_tokens"], ANTHROPIC_MAX_TOKENS);
+        assert_eq!(received.body["messages"].as_array().unwrap().len(), 1);
+        assert_eq!(received.body["messages"][0]["role"], "user");
+    }
+
+    #[tokio::test]
+    async fn error_status_is_reported_with_body() {
+        let (base_url, server) = mock_server(429, r#"{"error":"rate limited"}"#).await;
+        let provider = provider_from_config(&LlmProviderConfig::Ollama {
+            base_url,
+            model: "llama3".to_string(),
+        })
+        .unwrap();
+
+        let error = provider.complete(&request()).await.unwrap_err();
+        assert!(matches!(error, LlmError::Status { status: 429, .. }), "{:?}", error);
+        assert!(error.is_retryable());
+        assert!(error.to_string().contains("rate limited"), "{}", error);
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn unexpected_reply_is_an_error() {
+        let (base_url, server) = mock_server(200, r#"{"choices":[]}"#).await;
+        let provider = provider_from_config(&LlmProviderConfig::OpenAiCompatible {
+            base_url,
+            model: "local-model".to_string(),
+            api_key: None,
+            headers: BTreeMap::new(),
+        })
+        .unwrap();
+
+        assert!(provider.complete(&request()).await.is_err());
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn cleanup_aligns_reply_with_words() {
+        let (base_url, server) = mock_server(200, r#"{"message":{"content":"el dispositivo funciona"}}"#).await;
+        let provider = provider_from_config(&LlmProviderConfig::Ollama {
+            base_url,
+            model: "llama3".to_string(),
+        })
+        .unwrap();
+        let segments = vec![segment(0, 0.0, &["el", "dis", "av", "ivo", "funciona"])];
+
+        let template = PromptTemplates::default().get(PromptTask::Cleanup, Some("es")).unwrap();
+        let glossary = vec!["Final Cut Pro".to_string()];
+
+        let prompt = CleanupPrompt {
+            template: &template,
+            language: Some("es"),
+            glossary: &glossary,
+        };
+        let changes = clean_transcript_with_llm(&segments, provider, &prompt, &CancelToken::new(), |_| Ok(()))
+            .await
+            .unwrap();
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].kind, ChangeKind::Merge);
+        assert_eq!(changes[0].corrected[0].word, "dispositivo");
+        // The fragments' own timings, not an even share of the segment
+        assert_eq!((changes[0].corrected[0].start, changes[0].corrected[0].end), (0.4, 1.6));
+        let received = server.await.unwrap();
+        let system = received.body["messages"][0]["content"].as_str().unwrap();
+        let user = received.body["messages"][1]["content"].as_str().unwrap();
+        assert!(system.contains("Spanish"), "{}", system);
+        assert!(user.contains("Original: el dis av ivo funciona"), "{}", user);
+        assert!(user.contains("Final Cut Pro"), "{}", user);
+        assert!(!user.contains("{{"), "{}", user);
+    }
+
+    #[test]
+    fn chunks_own_each_segment_once_and_overlap_their_neighbours() {
+        let words = vec!["palabra"; 40];
+        let segments: Vec<Segment> = (0..100).map(|id| segment(id, id as f64 * 16.0, &words)).collect();
+        let chunks = cleanup_chunks(&segments);
+        assert!(chunks.len() > 1);
 
-/// Redistribute cleaned text back to segments, maintaining timestamps
-fn redistribute_text_to_segments(
-    original_segments: &[Segment],
-    cleaned_text: &str,
-) -> Result<Vec<Segment>, String> {
-    // Split cleaned text into words
-    let cleaned_words: Vec<&str> = cleaned_text.split_whitespace().collect();
+        let mut next = 0;
+        for chunk in &chunks {
+            assert_eq!(chunk.owned.start, next);
+            let chars: usize = segments[chunk.owned.clone()].iter().map(|s| s.text.len()).sum();
+            assert!(chars <= CHUNK_CHARS);
+            assert_eq!(chunk.context.start, chunk.owned.start.saturating_sub(CHUNK_OVERLAP_SEGMENTS));
+            assert_eq!(chunk.context.end, (chunk.owned.end + CHUNK_OVERLAP_SEGMENTS).min(segments.len()));
+            next = chunk.owned.end;
+        }
+        assert_eq!(next, segments.len());
+    }
 
-    eprintln!("🔄 Redistributing {} words to {} segments", cleaned_words.len(), original_segments.len());
+    #[tokio::test]
+    async fn server_errors_are_retried() {
+        let attempts = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counter = attempts.clone();
+        let (base_url, server) = mock_server_with(2, move |_| {
+            if counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst) == 0 {
+                (503, r#"{"error":"overloaded"}"#.to_string())
+            } else {
+                (200, r#"{"message":{"content":"ok"}}"#.to_string())
+            }
+        })
+        .await;
+        let provider = provider_from_config(&LlmProviderConfig::Ollama {
+            base_url,
+            model: "llama3".to_string(),
+        })
+        .unwrap();
 
-    let mut result_segments = Vec::new();
-    let mut word_index = 0;
+        let reply = complete_with_retry(provider.as_ref(), &request(), &CancelToken::new()).await.unwrap();
+        assert_eq!(reply, "ok");
+        assert_eq!(server.await.unwrap().len(), 2);
+    }
 
-    for original_segment in original_segments {
-        let segment_duration = original_segment.end - original_segment.start;
+    #[tokio::test]
+    async fn client_errors_are_not_retried() {
+        let (base_url, server) = mock_server(400, r#"{"error":"bad request"}"#).await;
+        let provider = provider_from_config(&LlmProviderConfig::Ollama {
+            base_url,
+            model: "llama3".to_string(),
+        })
+        .unwrap();
 
-        // Estimate how many words should be in this segment based on original word count
-        let original_word_count = original_segment.words.len();
+        let error = complete_with_retry(provider.as_ref(), &request(), &CancelToken::new()).await.unwrap_err();
+        assert!(matches!(error, LlmError::Status { status: 400, .. }), "{:?}", error);
+        assert!(!error.is_retryable());
+        server.await.unwrap();
+    }
 
-        // Take words for this segment (proportional to original)
-        let words_for_segment: Vec<&str> = cleaned_words
-            .get(word_index..word_index + original_word_count)
-            .unwrap_or(&cleaned_words[word_index..])
-            .to_vec();
+    #[tokio::test]
+    async fn long_transcript_is_cleaned_in_chunks() {
+        let words = ["los", "nego", "cios", "de", "la", "semana", "van", "bien", "y", "crecen"];
+        let segments: Vec<Segment> = (0..300).map(|id| segment(id, id as f64 * 4.0, &words)).collect();
+        let expected_requests = cleanup_chunks(&segments).len();
+        assert!(expected_requests > 1);
 
-        // Build segment text
-        let segment_text = words_for_segment.join(" ");
+        // Echo each chunk's transcript with the fragments joined
+        let (base_url, server) = mock_server_with(expected_requests, |received| {
+            let user = received.body["messages"][1]["content"].as_str().unwrap_or_default();
+            let transcript = user
+                .lines()
+                .find_map(|line| line.strip_prefix("Original: "))
+                .unwrap_or_default()
+                .replace("nego cios", "negocios");
+            let reply = serde_json::json!({ "message": { "content": transcript } });
+            (200, reply.to_string())
+        })
+        .await;
+        let provider = provider_from_config(&LlmProviderConfig::Ollama {
+            base_url,
+            model: "llama3".to_string(),
+        })
+        .unwrap();
 
-        // Distribute timestamps evenly across words in this segment
-        let mut segment_words = Vec::new();
-        let time_per_word = segment_duration / words_for_segment.len() as f64;
+        let template = PromptTemplates::default().get(PromptTask::Cleanup, Some("es")).unwrap();
+        let prompt = CleanupPrompt {
+            template: &template,
+            language: Some("es"),
+            glossary: &[],
+        };
+        let progress = std::sync::Mutex::new(Vec::new());
+        let changes = clean_transcript_with_llm(&segments, provider, &prompt, &CancelToken::new(), |fraction| {
+            progress.lock().unwrap().push(fraction);
+            Ok(())
+        })
+        .await
+        .unwrap();
 
-        for (i, word_text) in words_for_segment.iter().enumerate() {
-            let word_start = original_segment.start + (i as f64 * time_per_word);
-            let word_end = word_start + time_per_word;
+        assert_eq!(server.await.unwrap().len(), expected_requests);
+        assert_eq!(progress.into_inner().unwrap().last(), Some(&1.0));
 
-            segment_words.push(Word {
-                id: format!("w{}", word_index + i),
-                word: word_text.to_string(),
-                start: word_start,
-                end: word_end,
-            });
+        // One merge per segment, each found once despite the overlapping chunks
+        assert_eq!(changes.len(), segments.len());
+        for (i, change) in changes.iter().enumerate() {
+            assert_eq!(change.id, i);
+            assert_eq!(change.kind, ChangeKind::Merge);
+            assert_eq!(change.segment_id, i);
+            assert_eq!(change.position, i * words.len() + 1);
+            assert_eq!(change.corrected[0].word, "negocios");
         }
 
-        result_segments.push(Segment {
-            id: original_segment.id,
-            start: original_segment.start,
-            end: original_segment.end,
-            text: segment_text,
-            words: segment_words,
+        let corrected = align::apply_changes(&segments, &changes).unwrap();
+        assert_eq!(corrected.len(), segments.len());
+        assert!(corrected.iter().all(|s| s.text == " los negocios de la semana van bien y crecen"));
+    }
+
+    #[tokio::test]
+    async fn metadata_is_read_from_fenced_json_and_snapped_to_segments() {
+        let metadata = r#"Here you go:
+```json
+{"titles": ["Mi primer vlog ", ""], "description": "Un día en la ciudad.", "tags": ["vlog", "ciudad"],
+ "chapters": [{"title": "Intro", "start": "0:02"}, {"title": "El mercado", "start": 75},
+              {"title": "Sin hora", "start": "pronto"}, {"title": "La playa", "start": "2:00"}]}
+```"#;
+        let (base_url, server) = mock_server(200, &serde_json::json!({ "message": { "content": metadata } }).to_string()).await;
+        let provider = provider_from_config(&LlmProviderConfig::Ollama {
+            base_url,
+            model: "llama3".to_string(),
+        })
+        .unwrap();
+        let segments = vec![
+            segment(0, 0.0, &["hola", "a", "todos"]),
+            segment(1, 74.6, &["vamos", "al", "mercado"]),
+            segment(2, 119.5, &["y", "ahora", "la", "playa"]),
+        ];
+
+        let templates = PromptTemplates::default();
+        let prompt = MetadataPrompt {
+            template: &templates.get(PromptTask::Metadata, Some("es")).unwrap(),
+            merge_template: &templates.get(PromptTask::MetadataMerge, Some("es")).unwrap(),
+            language: Some("es"),
+        };
+        let metadata = generate_metadata(&segments, provider, &prompt, &CancelToken::new()).await.unwrap();
+
+        assert_eq!(metadata.titles, vec!["Mi primer vlog"]);
+        assert_eq!(metadata.tags, vec!["vlog", "ciudad"]);
+        let chapters: Vec<(&str, f64)> = metadata.chapters.iter().map(|c| (c.title.as_str(), c.start)).collect();
+        assert_eq!(chapters, vec![("Intro", 0.0), ("El mercado", 74.6), ("La playa", 119.5)]);
+
+        let received = server.await.unwrap();
+        let user = received.body["messages"][1]["content"].as_str().unwrap();
+        assert!(user.contains("[1:14] vamos al mercado"), "{}", user);
+        assert!(user.contains("Spanish"), "{}", user);
+    }
+
+    #[tokio::test]
+    async fn long_transcript_metadata_is_drafted_in_chunks_and_merged() {
+        let words = ["hoy", "hablamos", "de", "la", "cosecha", "y", "del", "tiempo", "en", "el"];
+        let segments: Vec<Segment> = (0..300).map(|id| segment(id, id as f64 * 4.0, &words)).collect();
+        let chunks = cleanup_chunks(&segments).len();
+        assert!(chunks > 1);
+
+        // Each part gets a chapter at its first line; the merge keeps the titles short and leaves the chapters out
+        let (base_url, server) = mock_server_with(chunks + 1, |received| {
+            let user = received.body["messages"][1]["content"].as_str().unwrap_or_default();
+            let content = if user.contains("Part 1 (") {
+                serde_json::json!({ "titles": ["Final"], "description": "Todo", "tags": ["campo"], "chapters": [] })
+            } else {
+                let first = user
+                    .lines()
+                    .find_map(|line| line.strip_prefix('[')?.split(']').next())
+                    .unwrap_or_default();
+                serde_json::json!({ "titles": ["Parte"], "description": "", "tags": [], "chapters": [{ "title": "Parte", "start": first }] })
+            };
+            (200, serde_json::json!({ "message": { "content": content.to_string() } }).to_string())
+        })
+        .await;
+        let provider = provider_from_config(&LlmProviderConfig::Ollama {
+            base_url,
+            model: "llama3".to_string(),
+        })
+        .unwrap();
+
+        let templates = PromptTemplates::default();
+        let prompt = MetadataPrompt {
+            template: &templates.get(PromptTask::Metadata, Some("es")).unwrap(),
+            merge_template: &templates.get(PromptTask::MetadataMerge, Some("es")).unwrap(),
+            language: Some("es"),
+        };
+        let metadata = generate_metadata(&segments, provider, &prompt, &CancelToken::new()).await.unwrap();
+
+        assert_eq!(metadata.titles, vec!["Final"]);
+        assert_eq!(metadata.chapters.len(), chunks);
+        assert_eq!(metadata.chapters[0].start, 0.0);
+        assert!(metadata.chapters.windows(2).all(|pair| pair[0].start < pair[1].start));
+
+        let received = server.await.unwrap();
+        assert_eq!(received.len(), chunks + 1);
+        // No line goes out twice: the parts hold only their own segments
+        let lines: usize = received
+            .iter()
+            .map(|r| r.body["messages"][1]["content"].as_str().unwrap().matches(" hoy hablamos").count())
+            .sum();
+        assert_eq!(lines, segments.len());
+    }
+
+    #[test]
+    fn truncated_cleanup_reply_is_rejected() {
+        let words: Vec<String> = (0..100).map(|n| format!("kilo{}", n)).collect();
+        let names: Vec<&str> = words.iter().map(String::as_str).collect();
+        let segments = vec![segment(0, 0.0, &names)];
+
+        assert!(check_cleanup_reply(&segments, &words.join(" ")).is_ok());
+        assert!(check_cleanup_reply(&segments, &words[..90].join(" ")).is_ok());
+        assert!(check_cleanup_reply(&segments, &words[..70].join(" ")).is_err());
+        assert!(check_cleanup_reply(&segments, "").is_err());
+    }
+
+    #[tokio::test]
+    async fn cancel_stops_a_request_in_flight() {
+        // Accepts the connection and never answers
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move {
+            let (_socket, _) = listener.accept().await.unwrap();
+            tokio::time::sleep(Duration::from_secs(30)).await;
+        });
+        let provider = provider_from_config(&LlmProviderConfig::Ollama {
+            base_url,
+            model: "llama3".to_string(),
+        })
+        .unwrap();
+
+        let cancel = CancelToken::new();
+        let canceller = cancel.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            canceller.cancel();
         });
 
-        word_index += words_for_segment.len();
+        let started = std::time::Instant::now();
+        let error = complete_with_retry(provider.as_ref(), &request(), &cancel).await.unwrap_err();
+        assert_eq!(error, LlmError::Cancelled);
+        assert_eq!(error.to_string(), CANCELLED_ERROR);
+        assert!(started.elapsed() < Duration::from_secs(2), "{:?}", started.elapsed());
+        server.abort();
     }
 
-    Ok(result_segments)
+    #[tokio::test]
+    async fn cancel_stops_the_wait_between_attempts() {
+        let (base_url, _server) = mock_server(503, r#"{"error":"overloaded"}"#).await;
+        let provider = provider_from_config(&LlmProviderConfig::Ollama {
+            base_url,
+            model: "llama3".to_string(),
+        })
+        .unwrap();
+
+        let cancel = CancelToken::new();
+        let canceller = cancel.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(300)).await;
+            canceller.cancel();
+        });
+
+        let started = std::time::Instant::now();
+        let error = complete_with_retry(provider.as_ref(), &request(), &cancel).await.unwrap_err();
+        assert_eq!(error, LlmError::Cancelled);
+        // The first backoff alone is INITIAL_BACKOFF
+        assert!(started.elapsed() < INITIAL_BACKOFF, "{:?}", started.elapsed());
+    }
 }
@@ -0,0 +1,174 @@
+//! Chapters for publishing: snapping the LLM's chapter starts to the transcript, moving them onto
+//! the edited timeline and writing them out as YouTube description lines or MP4 chapter markers.
+
+use std::fs;
+
+use crate::ffmpeg::run_ffmpeg_command;
+use crate::job::CancelToken;
+use crate::models::{Chapter, Segment};
+use crate::probe::get_duration;
+use crate::scratch::ScratchDir;
+use crate::timeline::Timeline;
+
+/// YouTube ignores the chapter list when any chapter is shorter than this
+const MIN_CHAPTER_SECONDS: f64 = 10.0;
+
+/// Chapters sorted and starting where a segment does, the first at 0. The LLM reads the segments'
+/// timestamps rounded to the second, so the nearest segment start is the one it meant.
+pub fn snap_to_segments(chapters: Vec<Chapter>, segments: &[Segment]) -> Vec<Chapter> {
+    let mut snapped: Vec<Chapter> = chapters
+        .into_iter()
+        .filter(|c| !c.title.trim().is_empty() && c.start.is_finite())
+        .map(|chapter| {
+            let start = segments
+                .iter()
+                .map(|s| s.start)
+                .min_by(|a, b| (a - chapter.start).abs().total_cmp(&(b - chapter.start).abs()))
+                .unwrap_or(chapter.start);
+            Chapter {
+                title: chapter.title.trim().to_string(),
+                start: start.max(0.0),
+            }
+        })
+        .collect();
+
+    snapped.sort_by(|a, b| a.start.total_cmp(&b.start));
+    // Two chapters snapped to one segment: the first title wins
+    snapped.dedup_by(|later, earlier| later.start == earlier.start);
+    if let Some(first) = snapped.first_mut() {
+        first.start = 0.0;
+    }
+    snapped
+}
+
+/// Chapters moved onto the export's timeline (kept as they are without one) and thinned out to what
+/// YouTube accepts: the first at 0 and none shorter than `MIN_CHAPTER_SECONDS`. A chapter starting in
+/// a cut begins where playback resumes.
+pub fn place_chapters(chapters: &[Chapter], timeline: Option<&Timeline>, duration: f64) -> Vec<Chapter> {
+    let mut placed: Vec<Chapter> = Vec::with_capacity(chapters.len());
+    for chapter in chapters {
+        let start = match timeline {
+            Some(timeline) => timeline.source_to_output_clamped(chapter.start),
+            None => chapter.start,
+        };
+        let too_close = match placed.last() {
+            Some(previous) => start - previous.start < MIN_CHAPTER_SECONDS,
+            None => false,
+        };
+        if too_close || (!placed.is_empty() && duration - start < MIN_CHAPTER_SECONDS) {
+            eprintln!("⚠️ Dropping chapter \"{}\": less than {}s long", chapter.title, MIN_CHAPTER_SECONDS);
+            continue;
+        }
+        placed.push(Chapter {
+            title: chapter.title.clone(),
+            start,
+        });
+    }
+
+    if let Some(first) = placed.first_mut() {
+        first.start = 0.0;
+    }
+    placed
+}
+
+/// One `0:00 Title` line per chapter, with hours on every line once the video is an hour long
+pub fn to_youtube(chapters: &[Chapter], duration: f64) -> String {
+    let with_hours = duration >= 3600.0;
+    chapters
+        .iter()
+        .map(|c| format!("{} {}\n", format_timestamp(c.start, with_hours), c.title))
+        .collect()
+}
+
+/// FFmpeg's metadata file format with one `[CHAPTER]` section per chapter, each ending where the next starts
+pub fn to_ffmetadata(chapters: &[Chapter], duration: f64) -> String {
+    let mut text = String::from(";FFMETADATA1\n");
+    for (i, chapter) in chapters.iter().enumerate() {
+        let end = chapters.get(i + 1).map_or(duration, |next| next.start);
+        text.push_str(&format!(
+            "\n[CHAPTER]\nTIMEBASE=1/1000\nSTART={}\nEND={}\ntitle={}\n",
+            (chapter.start * 1000.0).round() as u64,
+            (end * 1000.0).round() as u64,
+            escape_ffmetadata(&chapter.title)
+        ));
+    }
+    text
+}
+
+/// Write the chapters as YouTube description lines
+pub fn write_youtube_chapters(chapters: &[Chapter], duration: f64, output_path: &str) -> Result<(), String> {
+    fs::write(output_path, to_youtube(chapters, duration))
+        .map_err(|e| format!("Failed to write chapters to {}: {}", output_path, e))
+}
+
+/// Copy `video_path` to `output_path` as MP4 with `chapters` as its chapter markers, copying the streams
+/// as they are. `output_path` may be `video_path` itself only for a file the app wrote, like the edited export.
+pub fn embed_chapters(
+    video_path: &str,
+    output_path: &str,
+    chapters: &[Chapter],
+    cancel: &CancelToken,
+) -> Result<(), String> {
+    let duration = get_duration(video_path)?;
+    let dir = ScratchDir::new("chapters")?;
+    let metadata_path = dir.file("chapters.txt");
+    fs::write(&metadata_path, to_ffmetadata(chapters, duration))
+        .map_err(|e| format!("Failed to write chapter metadata to {}: {}", metadata_path, e))?;
+
+    // Written next to the output, so a failed run leaves no partial file and the rename stays on one disk
+    let temp_path = format!("{}.chapters.tmp", output_path);
+    let args = vec![
+        "-i", video_path,
+        "-i", &metadata_path,
+        "-map", "0",
+        "-map_metadata", "0",
+        "-map_chapters", "1",
+        "-c", "copy",
+        "-movflags", "+faststart",
+        "-f", "mp4",
+        "-y", &temp_path,
+    ];
+    if let Err(e) = run_ffmpeg_command(args, cancel) {
+        let _ = fs::remove_file(&temp_path);
+        return Err(e);
+    }
+
+    fs::rename(&temp_path, output_path).map_err(|e| format!("Failed to write {}: {}", output_path, e))?;
+    Ok(())
+}
+
+/// `M:SS`, or `H:MM:SS` with hours
+pub fn format_timestamp(seconds: f64, with_hours: bool) -> String {
+    let total = seconds.max(0.0).floor() as u64;
+    if with_hours {
+        format!("{}:{:02}:{:02}", total / 3600, total / 60 % 60, total % 60)
+    } else {
+        format!("{}:{:02}", total / 60, total % 60)
+    }
+}
+
+/// Seconds in `H:MM:SS`, `M:SS` or plain seconds; None for anything else
+pub fn parse_timestamp(text: &str) -> Option<f64> {
+    let text = text.trim().trim_start_matches('[').trim_end_matches(']');
+    let mut seconds = 0.0;
+    for part in text.split(':') {
+        let value: f64 = part.trim().parse().ok()?;
+        if !value.is_finite() || value < 0.0 {
+            return None;
+        }
+        seconds = seconds * 60.0 + value;
+    }
+    Some(seconds)
+}
+
+/// Backslash before the characters FFmpeg's metadata format treats as syntax
+fn escape_ffmetadata(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '=' | ';' | '#' | '\\' | '\n') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
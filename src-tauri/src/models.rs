@@ -1,3 +1,5 @@
+use std::collections::BTreeMap;
+
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -12,6 +14,48 @@ pub struct TranscriptResult {
     pub words: Vec<Word>,
     pub duration_seconds: f64,
     pub input_path: String,
+    /// Language Whisper transcribed in, detected unless one was requested
+    #[serde(default)]
+    pub language: Option<String>,
+    /// English translation with segment timing only, when the task was `Translate`
+    #[serde(default)]
+    pub translation: Option<Transcript>,
+    /// LLM corrections, already applied to `segments`
+    #[serde(default)]
+    pub correction: Option<TranscriptCorrection>,
+}
+
+/// Edits an LLM made to a transcript, so each can be accepted or rejected
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TranscriptCorrection {
+    /// Segments as Whisper produced them
+    pub original_segments: Vec<Segment>,
+    pub changes: Vec<TranscriptChange>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeKind {
+    /// A word respelled in place
+    Replace,
+    /// Fragments joined into one word ("nego cios" → "negocios")
+    Merge,
+    /// One word split into several
+    Split,
+    Insert,
+    Delete,
+}
+
+/// One edit: `original` words starting at `position` become `corrected`
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TranscriptChange {
+    pub id: usize,
+    pub kind: ChangeKind,
+    /// Segment the corrected words belong to
+    pub segment_id: usize,
+    /// Index of the first original word among all the transcript's words; for an insert, the word it goes before
+    pub position: usize,
+    pub original: Vec<Word>,
+    pub corrected: Vec<Word>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -21,6 +65,11 @@ pub struct Segment {
     pub end: f64,
     pub text: String,
     pub words: Vec<Word>,
+    #[serde(default)]
+    pub confidence: Option<Confidence>,
+    /// Mean log probability of the segment's text tokens
+    #[serde(default)]
+    pub avg_logprob: Option<f64>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -29,6 +78,164 @@ pub struct Word {
     pub word: String,
     pub start: f64,
     pub end: f64,
+    /// None for words Whisper didn't hear (e.g. words an LLM inserted)
+    #[serde(default)]
+    pub confidence: Option<Confidence>,
+}
+
+/// Whisper token probabilities (0-1) over the tokens of a word or segment
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct Confidence {
+    pub min: f64,
+    pub mean: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum SubtitleFormat {
+    Srt,
+    Vtt,
+}
+
+impl SubtitleFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            SubtitleFormat::Srt => "srt",
+            SubtitleFormat::Vtt => "vtt",
+        }
+    }
+}
+
+/// How transcript words are grouped into caption cues
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SubtitleSettings {
+    pub max_chars_per_line: usize,
+    pub max_lines: usize,
+    /// Seconds; short cues are held on screen longer when there is room before the next one
+    pub min_cue_duration: f64,
+    /// Seconds; longer runs of speech are split into several cues
+    pub max_cue_duration: f64,
+}
+
+impl Default for SubtitleSettings {
+    fn default() -> Self {
+        SubtitleSettings {
+            max_chars_per_line: 42,
+            max_lines: 2,
+            min_cue_duration: 1.0,
+            max_cue_duration: 7.0,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BurnInCaptions {
+    pub style: CaptionStyle,
+    pub layout: SubtitleSettings,
+}
+
+impl Default for BurnInCaptions {
+    fn default() -> Self {
+        // Short-form clips want a few big words at a time
+        BurnInCaptions {
+            style: CaptionStyle::default(),
+            layout: SubtitleSettings {
+                max_chars_per_line: 24,
+                max_lines: 2,
+                min_cue_duration: 0.5,
+                max_cue_duration: 4.0,
+            },
+        }
+    }
+}
+
+/// Look of burned-in karaoke captions. Colors are `#RRGGBB`, sizes are pixels of the output video.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CaptionStyle {
+    pub font: String,
+    pub font_size: u32,
+    pub bold: bool,
+    pub text_color: String,
+    /// Color a word turns once it has been spoken
+    pub highlight_color: String,
+    pub outline_color: String,
+    pub outline_width: f64,
+    pub position: CaptionPosition,
+    /// Distance from the top/bottom edge
+    pub margin: u32,
+}
+
+impl Default for CaptionStyle {
+    fn default() -> Self {
+        CaptionStyle {
+            font: "Arial".to_string(),
+            font_size: 64,
+            bold: true,
+            text_color: "#FFFFFF".to_string(),
+            highlight_color: "#FFD400".to_string(),
+            outline_color: "#000000".to_string(),
+            outline_width: 3.0,
+            position: CaptionPosition::Bottom,
+            margin: 120,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum CaptionPosition {
+    Top,
+    Middle,
+    Bottom,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MediaInfo {
+    pub path: String,
+    pub container: ContainerInfo,
+    pub video_streams: Vec<VideoStreamInfo>,
+    pub audio_streams: Vec<AudioStreamInfo>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ContainerInfo {
+    /// ffprobe format name, e.g. "mov,mp4,m4a,3gp,3g2,mj2"
+    pub format_name: String,
+    pub format_long_name: Option<String>,
+    pub duration: Option<f64>,
+    pub start_time: f64,
+    pub size_bytes: Option<u64>,
+    pub bit_rate: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct VideoStreamInfo {
+    pub index: usize,
+    pub codec: String,
+    pub profile: Option<String>,
+    /// Codec level as ffprobe reports it: 10x the level for H.264 (41 = 4.1), 30x for HEVC (123 = 4.1)
+    pub level: Option<i32>,
+    pub width: u32,
+    pub height: u32,
+    pub fps: Option<f64>,
+    /// Clockwise display rotation in degrees (0, 90, 180 or 270)
+    pub rotation: i32,
+    pub pixel_format: Option<String>,
+    pub color_transfer: Option<String>,
+    pub color_primaries: Option<String>,
+    /// PQ (HDR10/HDR10+) or HLG transfer
+    pub hdr: bool,
+    pub dolby_vision: bool,
+    pub duration: Option<f64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AudioStreamInfo {
+    pub index: usize,
+    pub codec: String,
+    pub channels: u32,
+    pub channel_layout: Option<String>,
+    pub sample_rate: u32,
+    pub language: Option<String>,
+    pub duration: Option<f64>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -39,6 +246,27 @@ pub struct PipelineConfig {
     pub silence_min_duration: f64,
     pub cut_margin: f64,
     pub language: Option<String>,
+    /// Whisper model name (see `whisper_models`); None uses the default model
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub task: TranscriptionTask,
+    /// Text Whisper is primed with, e.g. a sentence about the topic in the expected style
+    #[serde(default)]
+    pub initial_prompt: Option<String>,
+    /// Product names and jargon; included in the prompt, and near misses are corrected to them
+    #[serde(default)]
+    pub glossary: Vec<String>,
+    #[serde(default)]
+    pub whisper: WhisperParams,
+    /// LLM that cleans up the transcript in the editor flow; None skips cleanup
+    #[serde(default)]
+    pub llm: Option<LlmProviderConfig>,
+    #[serde(default)]
+    pub export: ExportSettings,
+    /// Run Whisper even if a cached transcript exists
+    #[serde(default)]
+    pub force_transcribe: bool,
 }
 
 impl Default for PipelineConfig {
@@ -50,14 +278,240 @@ impl Default for PipelineConfig {
             silence_min_duration: 0.5,
             cut_margin: 0.2,
             language: None,
+            model: None,
+            task: TranscriptionTask::default(),
+            initial_prompt: None,
+            glossary: Vec::new(),
+            whisper: WhisperParams::default(),
+            llm: None,
+            export: ExportSettings::default(),
+            force_transcribe: false,
         }
     }
 }
 
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TranscriptionTask {
+    #[default]
+    Transcribe,
+    /// Transcribe, then also translate to English as a separate track
+    Translate,
+}
+
+/// Whisper decoding settings; `WhisperPreset` has ready-made combinations
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct WhisperParams {
+    /// Beams for beam search; None decodes greedily
+    pub beam_size: Option<u32>,
+    /// Beam search patience; the bundled whisper.cpp doesn't use it yet
+    pub patience: f32,
+    /// Candidates sampled at each fallback temperature when decoding greedily
+    pub best_of: u32,
+    /// Temperature of the first decode; 0 always picks the most likely token
+    pub temperature: f32,
+    /// Added to the temperature each time a decode fails the thresholds below; 0 disables fallback
+    pub temperature_increment: f32,
+    /// Decodes with a lower token entropy are taken as repetition loops and retried
+    pub entropy_threshold: f32,
+    /// Decodes with a lower mean log probability are retried
+    pub logprob_threshold: f32,
+    /// CPU threads; None uses whisper.cpp's default of up to 4
+    pub threads: Option<u32>,
+    /// Longest segment in characters; None leaves segmenting to Whisper
+    pub max_segment_length: Option<u32>,
+    /// With `max_segment_length`, break between words instead of inside them
+    pub split_on_word: bool,
+}
+
+impl Default for WhisperParams {
+    fn default() -> Self {
+        WhisperPreset::Balanced.params()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum WhisperPreset {
+    /// One greedy pass, no retries
+    Fast,
+    /// Greedy with temperature fallback, like whisper.cpp's command line defaults
+    Balanced,
+    /// Beam search with temperature fallback; several times slower
+    Accurate,
+}
+
+impl WhisperPreset {
+    pub fn params(self) -> WhisperParams {
+        let balanced = WhisperParams {
+            beam_size: None,
+            patience: -1.0,
+            best_of: 5,
+            temperature: 0.0,
+            temperature_increment: 0.2,
+            entropy_threshold: 2.4,
+            logprob_threshold: -1.0,
+            threads: None,
+            max_segment_length: None,
+            split_on_word: false,
+        };
+
+        match self {
+            WhisperPreset::Fast => WhisperParams {
+                best_of: 1,
+                temperature_increment: 0.0,
+                ..balanced
+            },
+            WhisperPreset::Balanced => balanced,
+            WhisperPreset::Accurate => WhisperParams {
+                beam_size: Some(5),
+                ..balanced
+            },
+        }
+    }
+}
+
+/// Title, description, tags and chapters for publishing a video, written by the LLM
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct VideoMetadata {
+    /// Candidates to pick from, best first
+    pub titles: Vec<String>,
+    pub description: String,
+    pub tags: Vec<String>,
+    /// Sorted, starting at a segment start, in source time
+    pub chapters: Vec<Chapter>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Chapter {
+    pub title: String,
+    /// Seconds
+    pub start: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum ChapterFormat {
+    /// `0:00 Title` lines to paste into a YouTube description
+    YouTube,
+    /// Chapter markers embedded in the MP4 itself
+    Mp4,
+}
+
+/// Where transcript cleanup is sent. Ollama and a self-hosted OpenAI-compatible server
+/// keep recordings on premises.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub enum LlmProviderConfig {
+    /// Any `/chat/completions` API: OpenAI, Z.ai, llama.cpp server, vLLM, LM Studio...
+    OpenAiCompatible {
+        /// Up to and including the version, e.g. `https://api.openai.com/v1`
+        base_url: String,
+        model: String,
+        /// Sent as a bearer token
+        api_key: Option<String>,
+        /// Extra headers, e.g. for a gateway in front of the server
+        #[serde(default)]
+        headers: BTreeMap<String, String>,
+    },
+    Ollama {
+        /// e.g. `http://localhost:11434`
+        base_url: String,
+        model: String,
+    },
+    /// Anthropic's Messages API, or a compatible gateway
+    Anthropic {
+        /// e.g. `https://api.anthropic.com`
+        base_url: String,
+        model: String,
+        api_key: String,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ExportSettings {
+    pub codec: VideoCodec,
+    /// FFmpeg encoder name (e.g. "libx264"); None picks the best available for `codec`
+    pub encoder: Option<String>,
+    pub rate_control: RateControl,
+    pub preset: Option<String>,
+    pub profile: Option<String>,
+    #[serde(default)]
+    pub mode: ExportMode,
+    /// Burn word-highlighted captions into the video; needs a full re-encode
+    #[serde(default)]
+    pub captions: Option<BurnInCaptions>,
+}
+
+impl Default for ExportSettings {
+    fn default() -> Self {
+        ExportSettings {
+            codec: VideoCodec::H264,
+            encoder: None,
+            rate_control: RateControl::Bitrate { kbps: 8000 },
+            preset: None,
+            profile: None,
+            mode: ExportMode::default(),
+            captions: None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ExportMode {
+    /// Decode the whole input and re-encode the kept frames through select/aselect
+    #[default]
+    Reencode,
+    /// Stream-copy whole GOPs and re-encode only the partial GOPs at cut edges, joined with the concat demuxer
+    SmartCut,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum VideoCodec {
+    H264,
+    Hevc,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+pub enum RateControl {
+    /// Constant quality; mapped onto each encoder's equivalent (cq, global_quality, ...)
+    Crf { crf: u8 },
+    Bitrate { kbps: u32 },
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct VideoEncoderInfo {
+    pub name: String,
+    pub codec: VideoCodec,
+    pub hardware: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct WhisperModelInfo {
+    /// Model name as used in `PipelineConfig.model`, e.g. "base" or "large-v3-q5_0"
+    pub name: String,
+    pub file_name: String,
+    pub path: String,
+    pub installed: bool,
+    /// Size on disk, or the approximate download size when not installed
+    pub size_bytes: u64,
+    /// False for custom models imported under a name not in the registry
+    pub known: bool,
+    pub quantized: bool,
+    pub multilingual: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub enum ModelVerification {
+    Verified { sha256: String },
+    Mismatch { expected: String, actual: String },
+    /// No expected checksum is known for this model
+    Unknown { sha256: String },
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct PipelineResult {
     pub output_path: String,
     pub transcript: Transcript,
+    /// English translation of `transcript`, in the original video's timing
+    #[serde(default)]
+    pub translation: Option<Transcript>,
     pub stats: TranscriptStats,
 }
 
@@ -73,16 +527,21 @@ pub struct TranscriptStats {
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub enum PipelineEvent {
     StageStarted { stage: String },
-    StageProgress { stage: String, progress: f64 },
+    StageProgress { stage: String, progress: f64, eta_seconds: Option<f64> },
     StageCompleted { stage: String },
     StageFailed { stage: String, error: String },
+    /// Segments decoded so far by Whisper, text only, sent while transcription runs
+    PartialTranscript { segments: Vec<Segment> },
     PipelineCompleted { result: PipelineResult },
     PipelineFailed { error: String },
+    PipelineCancelled { job_id: String },
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, Copy)]
 pub enum PipelineStage {
     Transcribe,
+    Cleanup,
+    Translate,
     DetectSilences,
     EnhanceAudio,
     CutSilences,
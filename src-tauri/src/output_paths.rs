@@ -0,0 +1,63 @@
+//! Where exports are written: every file made from an input sits next to it, named after the input
+//! without its extension, so `video.mov` gives `video_edited.mp4`, `video_edited.en.srt` and so on.
+
+use std::path::Path;
+
+use crate::models::SubtitleFormat;
+
+/// The input path without its file extension
+fn stem(input_path: &str) -> String {
+    Path::new(input_path).with_extension("").to_string_lossy().into_owned()
+}
+
+/// `stem`, plus `_edited` for files that go with the edited export
+fn base(input_path: &str, edited: bool) -> String {
+    let suffix = if edited { "_edited" } else { "" };
+    format!("{}{}", stem(input_path), suffix)
+}
+
+/// The edited video, written by the pipeline and by the editor's export
+pub fn edited_video_path(input_path: &str) -> String {
+    format!("{}.mp4", base(input_path, true))
+}
+
+/// A copy of the input with chapter markers, for when there is no edited export to put them in
+pub fn chaptered_video_path(input_path: &str) -> String {
+    format!("{}_chapters.mp4", stem(input_path))
+}
+
+/// Subtitle file next to the input, or next to its edited export.
+/// A language tag (`video.en.srt`) lets players list several tracks for the same video.
+pub fn subtitle_path(input_path: &str, edited: bool, language: Option<&str>, format: SubtitleFormat) -> String {
+    let language = language.map(|l| format!(".{}", l)).unwrap_or_default();
+    format!("{}{}.{}", base(input_path, edited), language, format.extension())
+}
+
+/// Chapter list next to the input, or next to its edited export
+pub fn chapters_path(input_path: &str, edited: bool) -> String {
+    format!("{}.chapters.txt", base(input_path, edited))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn outputs_sit_next_to_the_edited_video() {
+        let input = "/videos/my.trip.MOV";
+        assert_eq!(edited_video_path(input), "/videos/my.trip_edited.mp4");
+        assert_eq!(
+            subtitle_path(input, true, Some("en"), SubtitleFormat::Srt),
+            "/videos/my.trip_edited.en.srt"
+        );
+        assert_eq!(chapters_path(input, true), "/videos/my.trip_edited.chapters.txt");
+    }
+
+    #[test]
+    fn unedited_outputs_sit_next_to_the_input() {
+        let input = "clip.mp4";
+        assert_eq!(subtitle_path(input, false, None, SubtitleFormat::Vtt), "clip.vtt");
+        assert_eq!(chapters_path(input, false), "clip.chapters.txt");
+        assert_eq!(chaptered_video_path(input), "clip_chapters.mp4");
+    }
+}
@@ -1,50 +1,117 @@
 use std::fs;
+use std::future::Future;
 
+use crate::align;
 use crate::ffmpeg::{
-    copy_video, cut_silences_and_export, detect_silences, enhance_audio, get_video_duration,
+    copy_video, cut_silences_and_export, detect_silences, enhance_audio, FFmpegProgress,
 };
-use crate::models::{PipelineConfig, PipelineEvent, PipelineResult, TranscriptStats};
-use crate::transcribe::{get_model_path, transcribe_with_progress};
+use crate::job::CancelToken;
+use crate::llm::{self, CleanupPrompt};
+use crate::models::{
+    LlmProviderConfig, PipelineConfig, PipelineEvent, PipelineResult, Transcript, TranscriptCorrection,
+    TranscriptStats, TranscriptionTask,
+};
+use crate::output_paths;
+use crate::probe::{get_duration, probe_media};
+use crate::prompts::{PromptTask, PromptTemplates};
+use crate::timeline::Timeline;
+use crate::transcribe::{TranscribeEvent, TranscribeOptions, Transcriber};
 
 pub fn process_video(
     input_path: &str,
     config: &PipelineConfig,
+    transcriber: &Transcriber,
+    templates: &PromptTemplates,
+    cancel: &CancelToken,
     progress_callback: impl Fn(PipelineEvent) -> Result<(), String>,
 ) -> Result<PipelineResult, String> {
-    // Get actual video duration first
-    let original_duration = get_video_duration(input_path)?;
+    // Reject unusable inputs before spending minutes in Whisper
+    let media = probe_media(input_path)?;
+    media.validate_for_pipeline()?;
+    let original_duration = media.duration().unwrap_or_default();
 
     progress_callback(PipelineEvent::StageStarted {
         stage: "transcribe".to_string(),
     })?;
 
-    let pcm_path = input_path.to_string() + ".pcm";
-
-    let transcript = transcribe_with_progress(
+    let options = TranscribeOptions::from_config(config);
+    let mut transcript = transcriber.transcribe(
         input_path,
-        &pcm_path,
-        &get_model_path(),
-        config,
-        |progress| {
-            progress_callback(PipelineEvent::StageProgress {
+        &options,
+        cancel,
+        |event| match event {
+            TranscribeEvent::Progress { progress, eta_seconds } => progress_callback(PipelineEvent::StageProgress {
                 stage: "transcribe".to_string(),
                 progress,
-            })
+                eta_seconds,
+            }),
+            TranscribeEvent::Segments(segments) => progress_callback(PipelineEvent::PartialTranscript { segments }),
         },
     )?;
 
-    let output_path = input_path.to_string() + "_edited.mp4";
+    progress_callback(PipelineEvent::StageCompleted {
+        stage: "transcribe".to_string(),
+    })?;
+
+    if let Some(llm) = &config.llm {
+        cancel.check()?;
+        // No one reviews the corrections here, so all of them are kept
+        block_on(run_cleanup(&mut transcript, llm, templates, &options.glossary, cancel, &progress_callback))??;
+    }
+
+    let translation = if options.task == TranscriptionTask::Translate {
+        cancel.check()?;
+
+        progress_callback(PipelineEvent::StageStarted {
+            stage: "translate".to_string(),
+        })?;
+
+        // Translated segments aren't streamed; the live transcript shows the spoken language
+        let translation = transcriber.translate(
+            input_path,
+            &options.for_translation(transcript.language.as_deref()),
+            cancel,
+            |event| match event {
+                TranscribeEvent::Progress { progress, eta_seconds } => {
+                    progress_callback(PipelineEvent::StageProgress {
+                        stage: "translate".to_string(),
+                        progress,
+                        eta_seconds,
+                    })
+                }
+                TranscribeEvent::Segments(_) => Ok(()),
+            },
+        )?;
+
+        progress_callback(PipelineEvent::StageCompleted {
+            stage: "translate".to_string(),
+        })?;
+        Some(translation)
+    } else {
+        None
+    };
+
+    let output_path = output_paths::edited_video_path(input_path);
 
     let silence_threshold = config.silence_threshold_db;
     let silence_min_duration = config.silence_min_duration;
     let enable_enhancement = config.enhance_audio;
     let cut_silences = config.cut_silences;
 
+    cancel.check()?;
+
     progress_callback(PipelineEvent::StageStarted {
         stage: "detect_silences".to_string(),
     })?;
 
-    let silences = detect_silences(input_path, silence_threshold, silence_min_duration)?;
+    let silences = detect_silences(
+        input_path,
+        silence_threshold,
+        silence_min_duration,
+        original_duration,
+        cancel,
+        |p| report_ffmpeg_progress(&progress_callback, "detect_silences", p),
+    )?;
 
     progress_callback(PipelineEvent::StageCompleted {
         stage: "detect_silences".to_string(),
@@ -53,40 +120,29 @@ pub fn process_video(
     // Calculate total silence duration
     let total_silence: f64 = silences.iter().map(|(start, end)| end - start).sum();
 
-    let mut keep_ranges = Vec::new();
-    let cut_margin = config.cut_margin;
+    cancel.check()?;
 
     if cut_silences && !silences.is_empty() {
         progress_callback(PipelineEvent::StageStarted {
             stage: "cut_silences".to_string(),
         })?;
 
-        // Build keep_ranges from the gaps between silences (the non-silent parts)
-        // Apply cut_margin to preserve a bit of padding around speech
-        let mut last_end = 0.0;
-
-        for (silence_start, silence_end) in &silences {
-            // Keep from last_end to (silence_start + margin)
-            let keep_end = (silence_start + cut_margin).min(original_duration);
-
-            if keep_end > last_end {
-                keep_ranges.push((last_end, keep_end));
-            }
-
-            // Next segment starts at (silence_end - margin), but not before current keep_end
-            let next_start = (silence_end - cut_margin).max(0.0);
-            last_end = next_start.max(keep_end); // Prevent overlap
-        }
-
-        // Keep final segment from last silence end to video end
-        if last_end < original_duration {
-            keep_ranges.push((last_end, original_duration));
-        }
+        // Keep the non-silent parts, with cut_margin of padding around speech
+        let timeline = Timeline::from_silences(&silences, config.cut_margin, original_duration)?;
 
-        eprintln!("📊 Keep ranges ({} segments): {:?}", keep_ranges.len(), keep_ranges);
+        eprintln!("📊 Keep ranges ({} segments): {:?}", timeline.ranges().len(), timeline.ranges());
 
         // Pass original video to cut_silences_and_export (not audio file)
-        cut_silences_and_export(input_path, keep_ranges, &output_path, enable_enhancement)?;
+        cut_silences_and_export(
+            input_path,
+            &timeline,
+            &output_path,
+            enable_enhancement,
+            &config.export,
+            &transcript.segments,
+            cancel,
+            |p| report_ffmpeg_progress(&progress_callback, "cut_silences", p),
+        )?;
 
         progress_callback(PipelineEvent::StageCompleted {
             stage: "cut_silences".to_string(),
@@ -96,14 +152,16 @@ pub fn process_video(
             stage: "enhance_audio".to_string(),
         })?;
 
-        enhance_audio(input_path, &output_path)?;
+        enhance_audio(input_path, &output_path, original_duration, cancel, |p| {
+            report_ffmpeg_progress(&progress_callback, "enhance_audio", p)
+        })?;
 
         progress_callback(PipelineEvent::StageCompleted {
             stage: "enhance_audio".to_string(),
         })?;
     } else {
         // No processing requested - just copy with faststart
-        copy_video(input_path, &output_path)?;
+        copy_video(input_path, &output_path, cancel)?;
     }
 
     let file_size = fs::metadata(&output_path).map(|m| m.len()).unwrap_or(0);
@@ -111,7 +169,7 @@ pub fn process_video(
     let stats = TranscriptStats {
         original_duration,
         original_size_bytes: file_size,
-        processed_duration: get_video_duration(&output_path)?,
+        processed_duration: get_duration(&output_path)?,
         removed_silence_duration: total_silence,
         silence_percentage: (total_silence / original_duration) * 100.0,
     };
@@ -119,14 +177,99 @@ pub fn process_video(
     let result = PipelineResult {
         output_path,
         transcript,
+        translation,
         stats,
     };
 
-    let result_clone = result;
-    Ok(result_clone)
+    Ok(result)
+}
+
+/// The `cleanup` stage: LLM corrections applied to `transcript`, which are also returned so they can be reviewed.
+/// A failing LLM only costs the corrections; the transcript then stays as Whisper wrote it.
+pub async fn run_cleanup(
+    transcript: &mut Transcript,
+    llm: &LlmProviderConfig,
+    templates: &PromptTemplates,
+    glossary: &[String],
+    cancel: &CancelToken,
+    progress_callback: impl Fn(PipelineEvent) -> Result<(), String>,
+) -> Result<Option<TranscriptCorrection>, String> {
+    progress_callback(PipelineEvent::StageStarted {
+        stage: "cleanup".to_string(),
+    })?;
+
+    let provider = llm::provider_from_config(llm)?;
+    // Whisper's detected language picks the template
+    let template = templates.get(PromptTask::Cleanup, transcript.language.as_deref())?;
+    let prompt = CleanupPrompt {
+        template: &template,
+        language: transcript.language.as_deref(),
+        glossary,
+    };
+
+    let cleaned = llm::clean_transcript_with_llm(&transcript.segments, provider, &prompt, cancel, |progress| {
+        progress_callback(PipelineEvent::StageProgress {
+            stage: "cleanup".to_string(),
+            progress,
+            eta_seconds: None,
+        })
+    })
+    .await;
+
+    let correction = match cleaned {
+        Ok(changes) => {
+            eprintln!("✨ LLM cleanup successful: {} changes", changes.len());
+            let corrected = align::apply_changes(&transcript.segments, &changes)?;
+            Some(TranscriptCorrection {
+                original_segments: std::mem::replace(&mut transcript.segments, corrected),
+                changes,
+            })
+        }
+        Err(e) => {
+            cancel.check()?;
+            eprintln!("⚠️ LLM cleanup failed, using original: {}", e);
+            None
+        }
+    };
+
+    progress_callback(PipelineEvent::StageCompleted {
+        stage: "cleanup".to_string(),
+    })?;
+    Ok(correction)
+}
+
+/// Run an async step to completion from the pipeline's blocking thread; outside a Tokio runtime
+/// (e.g. the CLI) a temporary one is started
+pub fn block_on<F: Future>(future: F) -> Result<F::Output, String> {
+    match tokio::runtime::Handle::try_current() {
+        Ok(handle) => Ok(handle.block_on(future)),
+        Err(_) => tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .map(|runtime| runtime.block_on(future))
+            .map_err(|e| format!("Failed to start async runtime: {}", e)),
+    }
+}
+
+/// Forward an FFmpeg progress update as a StageProgress event.
+/// Emission errors are only logged so a dropped event never aborts the encode.
+fn report_ffmpeg_progress(
+    progress_callback: &impl Fn(PipelineEvent) -> Result<(), String>,
+    stage: &str,
+    progress: FFmpegProgress,
+) {
+    let event = PipelineEvent::StageProgress {
+        stage: stage.to_string(),
+        progress: progress.progress,
+        eta_seconds: progress.eta_seconds,
+    };
+
+    if let Err(e) = progress_callback(event) {
+        eprintln!("⚠️ Failed to report {} progress: {}", stage, e);
+    }
 }
 
-pub fn clean_up_temp_files(input_path: &str) {
-    let _ = fs::remove_file(input_path.to_string() + ".pcm");
-    let _ = fs::remove_file(input_path.to_string() + ".enhanced.aac");
+/// Remove a half-written output after a cancelled run
+pub fn clean_up_partial_outputs(input_path: &str) {
+    let _ = fs::remove_file(output_paths::edited_video_path(input_path));
 }
@@ -0,0 +1,416 @@
+use std::collections::HashMap;
+use std::process::Command;
+
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::models::{AudioStreamInfo, ContainerInfo, MediaInfo, VideoStreamInfo};
+
+/// Codec name for streams ffprobe couldn't identify; FFmpeg has no decoder for them
+const UNKNOWN_CODEC: &str = "unknown";
+
+// ffprobe -print_format json output; numbers mostly come back as strings
+#[derive(Debug, Deserialize)]
+struct ProbeOutput {
+    #[serde(default)]
+    streams: Vec<ProbeStream>,
+    format: Option<ProbeFormat>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ProbeStream {
+    index: usize,
+    codec_type: Option<String>,
+    codec_name: Option<String>,
+    profile: Option<String>,
+    level: Option<i32>,
+    width: Option<u32>,
+    height: Option<u32>,
+    pix_fmt: Option<String>,
+    avg_frame_rate: Option<String>,
+    r_frame_rate: Option<String>,
+    color_transfer: Option<String>,
+    color_primaries: Option<String>,
+    channels: Option<u32>,
+    channel_layout: Option<String>,
+    sample_rate: Option<String>,
+    duration: Option<String>,
+    #[serde(default)]
+    disposition: HashMap<String, i32>,
+    #[serde(default)]
+    tags: HashMap<String, String>,
+    #[serde(default)]
+    side_data_list: Vec<Value>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ProbeFormat {
+    format_name: Option<String>,
+    format_long_name: Option<String>,
+    duration: Option<String>,
+    start_time: Option<String>,
+    size: Option<String>,
+    bit_rate: Option<String>,
+}
+
+/// Run ffprobe on a file and read its container and stream metadata
+pub fn probe_media(input_path: &str) -> Result<MediaInfo, String> {
+    let output = Command::new("ffprobe")
+        .args([
+            "-v", "error",
+            "-print_format", "json",
+            "-show_format",
+            "-show_streams",
+            input_path,
+        ])
+        .output()
+        .map_err(|e| format!("Failed to run ffprobe: {}", e))?;
+
+    if !output.status.success() {
+        return Err(format!(
+            "ffprobe failed for {}: {}",
+            input_path,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    let probe: ProbeOutput = serde_json::from_slice(&output.stdout)
+        .map_err(|e| format!("Failed to parse ffprobe output: {}", e))?;
+
+    let media = media_info_from_probe(input_path, probe);
+
+    eprintln!(
+        "📹 Probed {}: {} ({} video, {} audio streams, duration {:?})",
+        input_path,
+        media.container.format_name,
+        media.video_streams.len(),
+        media.audio_streams.len(),
+        media.duration()
+    );
+
+    Ok(media)
+}
+
+/// Duration of a media file in seconds
+pub fn get_duration(input_path: &str) -> Result<f64, String> {
+    probe_media(input_path)?
+        .duration()
+        .ok_or_else(|| format!("Could not determine the duration of {}", input_path))
+}
+
+fn media_info_from_probe(input_path: &str, probe: ProbeOutput) -> MediaInfo {
+    let format = probe.format;
+    let container = ContainerInfo {
+        format_name: format
+            .as_ref()
+            .and_then(|f| f.format_name.clone())
+            .unwrap_or_else(|| "unknown".to_string()),
+        format_long_name: format.as_ref().and_then(|f| f.format_long_name.clone()),
+        duration: format.as_ref().and_then(|f| parse_number(&f.duration)),
+        start_time: format.as_ref().and_then(|f| parse_number(&f.start_time)).unwrap_or(0.0),
+        size_bytes: format.as_ref().and_then(|f| parse_number(&f.size)),
+        bit_rate: format.as_ref().and_then(|f| parse_number(&f.bit_rate)),
+    };
+
+    let mut video_streams = Vec::new();
+    let mut audio_streams = Vec::new();
+
+    for stream in probe.streams {
+        match stream.codec_type.as_deref() {
+            Some("video") => {
+                // Cover art and thumbnails show up as single-frame video streams
+                if stream.disposition.get("attached_pic") == Some(&1) {
+                    continue;
+                }
+                video_streams.push(video_stream_info(stream));
+            }
+            Some("audio") => audio_streams.push(audio_stream_info(stream)),
+            _ => {}
+        }
+    }
+
+    MediaInfo {
+        path: input_path.to_string(),
+        container,
+        video_streams,
+        audio_streams,
+    }
+}
+
+fn video_stream_info(stream: ProbeStream) -> VideoStreamInfo {
+    let fps = parse_frame_rate(&stream.avg_frame_rate).or_else(|| parse_frame_rate(&stream.r_frame_rate));
+
+    let hdr = matches!(
+        stream.color_transfer.as_deref(),
+        Some("smpte2084") | Some("arib-std-b67")
+    );
+    let dolby_vision = side_data(&stream, "DOVI configuration record").is_some();
+
+    VideoStreamInfo {
+        index: stream.index,
+        rotation: rotation(&stream),
+        codec: stream.codec_name.unwrap_or_else(|| UNKNOWN_CODEC.to_string()),
+        profile: stream.profile,
+        // ffprobe reports -99 when the bitstream doesn't say
+        level: stream.level.filter(|level| *level > 0),
+        width: stream.width.unwrap_or(0),
+        height: stream.height.unwrap_or(0),
+        fps,
+        pixel_format: stream.pix_fmt,
+        color_transfer: stream.color_transfer,
+        color_primaries: stream.color_primaries,
+        hdr,
+        dolby_vision,
+        duration: parse_number(&stream.duration),
+    }
+}
+
+fn audio_stream_info(stream: ProbeStream) -> AudioStreamInfo {
+    AudioStreamInfo {
+        index: stream.index,
+        codec: stream.codec_name.unwrap_or_else(|| UNKNOWN_CODEC.to_string()),
+        channels: stream.channels.unwrap_or(0),
+        channel_layout: stream.channel_layout,
+        sample_rate: parse_number(&stream.sample_rate).unwrap_or(0),
+        // "und" is ffprobe's "undetermined"
+        language: stream.tags.get("language").filter(|l| *l != "und").cloned(),
+        duration: parse_number(&stream.duration),
+    }
+}
+
+fn side_data<'a>(stream: &'a ProbeStream, side_data_type: &str) -> Option<&'a Value> {
+    stream
+        .side_data_list
+        .iter()
+        .find(|d| d["side_data_type"].as_str() == Some(side_data_type))
+}
+
+/// Clockwise rotation from the legacy `rotate` tag or the display matrix side data
+fn rotation(stream: &ProbeStream) -> i32 {
+    let degrees = if let Some(rotate) = stream.tags.get("rotate").and_then(|r| r.parse::<i32>().ok()) {
+        rotate
+    } else if let Some(matrix) = side_data(stream, "Display Matrix") {
+        // The display matrix angle is counter-clockwise
+        -(matrix["rotation"].as_f64().unwrap_or(0.0).round() as i32)
+    } else {
+        0
+    };
+
+    degrees.rem_euclid(360)
+}
+
+fn parse_number<T: std::str::FromStr>(value: &Option<String>) -> Option<T> {
+    value.as_deref().and_then(|v| v.parse::<T>().ok())
+}
+
+/// Parse ffprobe rates like "30000/1001"; "0/0" means unknown
+fn parse_frame_rate(rate: &Option<String>) -> Option<f64> {
+    let (num, den) = rate.as_deref()?.split_once('/')?;
+    let num = num.parse::<f64>().ok()?;
+    let den = den.parse::<f64>().ok()?;
+
+    if num > 0.0 && den > 0.0 {
+        Some(num / den)
+    } else {
+        None
+    }
+}
+
+impl MediaInfo {
+    /// Container duration, falling back to the longest stream for formats that don't report one
+    pub fn duration(&self) -> Option<f64> {
+        self.container.duration.filter(|d| *d > 0.0).or_else(|| {
+            self.video_streams
+                .iter()
+                .filter_map(|s| s.duration)
+                .chain(self.audio_streams.iter().filter_map(|s| s.duration))
+                .filter(|d| *d > 0.0)
+                .max_by(|a, b| a.total_cmp(b))
+        })
+    }
+
+    /// Whisper needs an audio track with a known length
+    pub fn validate_for_transcription(&self) -> Result<(), String> {
+        if self.audio_streams.is_empty() {
+            return Err(format!("{} has no audio track to transcribe", self.path));
+        }
+        if self.audio_streams.iter().all(|s| s.codec == UNKNOWN_CODEC) {
+            return Err(format!("{} has no audio track in a codec FFmpeg can decode", self.path));
+        }
+        if self.duration().is_none() {
+            return Err(format!("Could not determine the duration of {}", self.path));
+        }
+        Ok(())
+    }
+
+    /// The full pipeline also cuts and re-encodes video
+    pub fn validate_for_pipeline(&self) -> Result<(), String> {
+        self.validate_for_transcription()?;
+        if self.video_streams.is_empty() {
+            return Err(format!("{} has no video track", self.path));
+        }
+        if self.video_streams.iter().all(|s| s.codec == UNKNOWN_CODEC) {
+            return Err(format!("{} has no video track in a codec FFmpeg can decode", self.path));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `ffprobe -print_format json -show_format -show_streams` of a phone recording, trimmed
+    const PHONE_RECORDING: &str = r#"{
+        "streams": [
+            {
+                "index": 0, "codec_name": "hevc", "codec_type": "video", "profile": "Main 10", "level": 153,
+                "width": 3840, "height": 2160, "pix_fmt": "yuv420p10le",
+                "color_transfer": "arib-std-b67", "color_primaries": "bt2020",
+                "r_frame_rate": "30/1", "avg_frame_rate": "30000/1001", "duration": "12.345000",
+                "disposition": { "default": 1, "attached_pic": 0 },
+                "tags": { "language": "und" },
+                "side_data_list": [ { "side_data_type": "Display Matrix", "rotation": -90 } ]
+            },
+            {
+                "index": 1, "codec_name": "aac", "codec_type": "audio", "profile": "LC",
+                "sample_rate": "48000", "channels": 2, "channel_layout": "stereo", "duration": "12.330000",
+                "disposition": { "default": 1 },
+                "tags": { "language": "eng" }
+            },
+            {
+                "index": 2, "codec_name": "mjpeg", "codec_type": "video", "width": 320, "height": 240,
+                "level": -99, "disposition": { "attached_pic": 1 }
+            },
+            { "index": 3, "codec_type": "data", "tags": { "handler_name": "Core Media Metadata" } }
+        ],
+        "format": {
+            "format_name": "mov,mp4,m4a,3gp,3g2,mj2", "format_long_name": "QuickTime / MOV",
+            "start_time": "0.000000", "duration": "12.345000", "size": "48123456", "bit_rate": "31186000"
+        }
+    }"#;
+
+    fn media(json: &str) -> MediaInfo {
+        media_info_from_probe("input.mov", serde_json::from_str(json).unwrap())
+    }
+
+    #[test]
+    fn parses_streams_and_container() {
+        let media = media(PHONE_RECORDING);
+
+        assert_eq!(media.container.format_name, "mov,mp4,m4a,3gp,3g2,mj2");
+        assert_eq!(media.container.size_bytes, Some(48_123_456));
+        assert_eq!(media.container.bit_rate, Some(31_186_000));
+        assert_eq!(media.duration(), Some(12.345));
+
+        // The cover art and the data stream are skipped
+        assert_eq!(media.video_streams.len(), 1);
+        assert_eq!(media.audio_streams.len(), 1);
+
+        let video = &media.video_streams[0];
+        assert_eq!(video.codec, "hevc");
+        assert_eq!(video.profile.as_deref(), Some("Main 10"));
+        assert_eq!(video.level, Some(153));
+        assert_eq!((video.width, video.height), (3840, 2160));
+        assert!((video.fps.unwrap() - 29.97).abs() < 0.01);
+        assert_eq!(video.rotation, 90);
+        assert!(video.hdr);
+        assert!(!video.dolby_vision);
+
+        let audio = &media.audio_streams[0];
+        assert_eq!(audio.codec, "aac");
+        assert_eq!(audio.sample_rate, 48000);
+        assert_eq!(audio.channels, 2);
+        assert_eq!(audio.language.as_deref(), Some("eng"));
+
+        assert!(media.validate_for_pipeline().is_ok());
+    }
+
+    #[test]
+    fn fills_gaps_in_sparse_output() {
+        // What ffprobe gives for a raw stream: no container duration, unknown level and frame rate
+        let media = media(
+            r#"{
+                "streams": [
+                    { "index": 0, "codec_name": "h264", "codec_type": "video", "level": -99,
+                      "avg_frame_rate": "0/0", "r_frame_rate": "25/1", "duration": "4.0",
+                      "tags": { "rotate": "270" } },
+                    { "index": 1, "codec_name": "pcm_s16le", "codec_type": "audio", "duration": "4.5",
+                      "tags": { "language": "und" } }
+                ],
+                "format": { "format_name": "matroska,webm", "duration": "0.000000" }
+            }"#,
+        );
+
+        let video = &media.video_streams[0];
+        assert_eq!(video.level, None);
+        assert_eq!(video.fps, Some(25.0));
+        assert_eq!(video.rotation, 270);
+        assert_eq!(media.audio_streams[0].language, None);
+        assert_eq!(media.container.start_time, 0.0);
+        // A zero container duration falls back to the longest stream
+        assert_eq!(media.duration(), Some(4.5));
+    }
+
+    #[test]
+    fn rejects_files_without_audio() {
+        let media = media(
+            r#"{
+                "streams": [ { "index": 0, "codec_name": "h264", "codec_type": "video", "duration": "10.0" } ],
+                "format": { "format_name": "mov,mp4,m4a,3gp,3g2,mj2", "duration": "10.0" }
+            }"#,
+        );
+
+        let error = media.validate_for_transcription().unwrap_err();
+        assert!(error.contains("no audio track"), "{}", error);
+        assert!(media.validate_for_pipeline().is_err());
+    }
+
+    #[test]
+    fn rejects_zero_duration() {
+        let media = media(
+            r#"{
+                "streams": [
+                    { "index": 0, "codec_name": "h264", "codec_type": "video", "duration": "0.000000" },
+                    { "index": 1, "codec_name": "aac", "codec_type": "audio" }
+                ],
+                "format": { "format_name": "mov,mp4,m4a,3gp,3g2,mj2", "duration": "0.000000" }
+            }"#,
+        );
+
+        assert_eq!(media.duration(), None);
+        let error = media.validate_for_transcription().unwrap_err();
+        assert!(error.contains("duration"), "{}", error);
+    }
+
+    #[test]
+    fn rejects_codecs_ffmpeg_cannot_decode() {
+        // ffprobe leaves out codec_name when it has no decoder for the stream
+        let unknown_video = media(
+            r#"{
+                "streams": [
+                    { "index": 0, "codec_type": "video", "codec_tag_string": "aprn", "width": 1920, "height": 1080 },
+                    { "index": 1, "codec_name": "aac", "codec_type": "audio" }
+                ],
+                "format": { "format_name": "mov,mp4,m4a,3gp,3g2,mj2", "duration": "8.0" }
+            }"#,
+        );
+        assert_eq!(unknown_video.video_streams[0].codec, UNKNOWN_CODEC);
+        // The audio is still fine for transcription on its own
+        assert!(unknown_video.validate_for_transcription().is_ok());
+        let error = unknown_video.validate_for_pipeline().unwrap_err();
+        assert!(error.contains("no video track in a codec"), "{}", error);
+
+        let unknown_audio = media(
+            r#"{
+                "streams": [
+                    { "index": 0, "codec_name": "h264", "codec_type": "video" },
+                    { "index": 1, "codec_type": "audio", "channels": 6 }
+                ],
+                "format": { "format_name": "mpegts", "duration": "8.0" }
+            }"#,
+        );
+        let error = unknown_audio.validate_for_transcription().unwrap_err();
+        assert!(error.contains("no audio track in a codec"), "{}", error);
+    }
+}
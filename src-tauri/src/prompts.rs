@@ -0,0 +1,149 @@
+//! Prompt templates for the LLM passes, keyed by task and language.
+//!
+//! A template is a text file named `{task}.{language}.txt` (or `{task}.txt` for any language) holding the
+//! system prompt, a line with just `---`, and the user prompt. `{{name}}` placeholders are filled in when
+//! the template is rendered. Files in the user's prompts directory take precedence over the built-in ones.
+
+use std::fs;
+use std::path::PathBuf;
+
+/// Built-in templates by file stem
+const BUILTIN_TEMPLATES: &[(&str, &str)] = &[
+    ("cleanup", include_str!("../prompts/cleanup.txt")),
+    ("cleanup.en", include_str!("../prompts/cleanup.en.txt")),
+    ("cleanup.es", include_str!("../prompts/cleanup.es.txt")),
+    ("metadata", include_str!("../prompts/metadata.txt")),
+    ("metadata_merge", include_str!("../prompts/metadata_merge.txt")),
+];
+
+/// Line separating the system prompt from the user prompt
+const SEPARATOR: &str = "---";
+
+/// What a prompt asks the LLM to do
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PromptTask {
+    /// Join word fragments and fix misspellings in a transcript
+    Cleanup,
+    /// Write titles, a description, tags and chapters for publishing a video
+    Metadata,
+    /// Combine the metadata written for each part of a long transcript
+    MetadataMerge,
+}
+
+impl PromptTask {
+    /// File stem of the task's templates
+    pub fn name(self) -> &'static str {
+        match self {
+            PromptTask::Cleanup => "cleanup",
+            PromptTask::Metadata => "metadata",
+            PromptTask::MetadataMerge => "metadata_merge",
+        }
+    }
+}
+
+/// A system and a user prompt with `{{name}}` placeholders
+#[derive(Debug, Clone, PartialEq)]
+pub struct PromptTemplate {
+    pub system: String,
+    pub user: String,
+}
+
+impl PromptTemplate {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut system = Vec::new();
+        let mut lines = text.lines();
+        for line in lines.by_ref() {
+            if line.trim_end() == SEPARATOR {
+                let user: Vec<&str> = lines.collect();
+                return Ok(PromptTemplate {
+                    system: system.join("\n").trim().to_string(),
+                    user: user.join("\n").trim().to_string(),
+                });
+            }
+            system.push(line);
+        }
+        Err(format!("No '{}' line between the system and user prompts", SEPARATOR))
+    }
+
+    /// System and user prompts with each `{{name}}` replaced by its value; unknown placeholders are left as is
+    pub fn render(&self, variables: &[(&str, &str)]) -> (String, String) {
+        let fill = |text: &str| {
+            let mut text = text.to_string();
+            for (name, value) in variables {
+                text = text.replace(&format!("{{{{{}}}}}", name), value);
+            }
+            if let Some(start) = text.find("{{") {
+                let placeholder: String = text[start..].chars().take_while(|&c| c != '\n').take(40).collect();
+                eprintln!("⚠️ Prompt has an unknown placeholder: {}", placeholder);
+            }
+            text
+        };
+        (fill(&self.system), fill(&self.user))
+    }
+}
+
+/// Finds the template for a task and language, preferring the user's own files
+#[derive(Debug, Clone, Default)]
+pub struct PromptTemplates {
+    /// Where users put their templates; None for the built-in ones only
+    dir: Option<PathBuf>,
+}
+
+impl PromptTemplates {
+    pub fn new(dir: Option<PathBuf>) -> Self {
+        PromptTemplates { dir }
+    }
+
+    /// The most specific template for `task` in `language`. A regional code ("pt-BR") falls back to its
+    /// language ("pt"), then to the task's generic template; at each step a user file beats a built-in one.
+    pub fn get(&self, task: PromptTask, language: Option<&str>) -> Result<PromptTemplate, String> {
+        let language = language.map(|l| l.trim().to_lowercase().replace('_', "-"));
+        let mut stems = Vec::new();
+        if let Some(language) = language.as_deref().filter(|l| !l.is_empty()) {
+            stems.push(format!("{}.{}", task.name(), language));
+            if let Some((base, _)) = language.split_once('-') {
+                stems.push(format!("{}.{}", task.name(), base));
+            }
+        }
+        stems.push(task.name().to_string());
+
+        for stem in &stems {
+            if let Some(dir) = &self.dir {
+                let path = dir.join(format!("{}.txt", stem));
+                if path.is_file() {
+                    let text =
+                        fs::read_to_string(&path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+                    eprintln!("📄 Using prompt template {}", path.display());
+                    return PromptTemplate::parse(&text).map_err(|e| format!("{}: {}", path.display(), e));
+                }
+            }
+            if let Some((_, text)) = BUILTIN_TEMPLATES.iter().find(|(name, _)| name == stem) {
+                eprintln!("📄 Using built-in prompt template {}", stem);
+                return PromptTemplate::parse(text);
+            }
+        }
+
+        Err(format!("No prompt template for {}", task.name()))
+    }
+}
+
+/// English name of a language code, for prompts; the code itself when unknown
+pub fn language_name(code: &str) -> String {
+    let base = code.split(['-', '_']).next().unwrap_or(code).to_lowercase();
+    let name = match base.as_str() {
+        "en" => "English",
+        "es" => "Spanish",
+        "fr" => "French",
+        "de" => "German",
+        "it" => "Italian",
+        "pt" => "Portuguese",
+        "nl" => "Dutch",
+        "ca" => "Catalan",
+        "ja" => "Japanese",
+        "zh" => "Chinese",
+        "ko" => "Korean",
+        "ru" => "Russian",
+        _ => return code.to_string(),
+    };
+    name.to_string()
+}
@@ -0,0 +1,37 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Working directory in the OS temp dir, removed with everything in it when dropped.
+/// Keeps intermediate files out of the user's folders, even when a job fails or is cancelled.
+pub struct ScratchDir {
+    path: PathBuf,
+}
+
+impl ScratchDir {
+    pub fn new(label: &str) -> Result<Self, String> {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or(0);
+        let path = env::temp_dir().join(format!("autovideo-{}-{}-{}", label, process::id(), nanos));
+
+        fs::create_dir_all(&path)
+            .map_err(|e| format!("Failed to create temp dir {}: {}", path.display(), e))?;
+
+        Ok(ScratchDir { path })
+    }
+
+    /// Path of a file inside the scratch dir, as a string for FFmpeg arguments
+    pub fn file(&self, name: &str) -> String {
+        self.path.join(name).to_string_lossy().to_string()
+    }
+}
+
+impl Drop for ScratchDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
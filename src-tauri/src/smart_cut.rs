@@ -0,0 +1,418 @@
+use std::fs;
+use std::process::Command;
+use std::time::Instant;
+
+use crate::encoder::{available_encoders, preset_args, rate_control_args};
+use crate::ffmpeg::{audio_cut_filter, run_ffmpeg_command, run_ffmpeg_command_with_progress, FFmpegProgress};
+use crate::job::CancelToken;
+use crate::models::{ExportSettings, VideoCodec, VideoStreamInfo};
+use crate::probe::probe_media;
+use crate::scratch::ScratchDir;
+use crate::timeline::Timeline;
+
+/// A keyframe this close to a cut point counts as being on it (about one frame at 50fps)
+const KEYFRAME_TOLERANCE: f64 = 0.02;
+
+/// Share of the progress bar spent writing segments; the rest is the final concat + audio pass
+const SEGMENT_PROGRESS_SHARE: f64 = 0.9;
+
+/// A source video that can be cut segment by segment
+pub struct SmartCutSource {
+    input_path: String,
+    /// Encoder arguments for the re-encoded edges, matching the source stream
+    edge_args: Vec<String>,
+    /// Keyframe times in seconds from the start of the file, sorted
+    keyframes: Vec<f64>,
+    /// A range running to here ends with a whole GOP, like one ending on a keyframe
+    duration: f64,
+}
+
+/// One piece of the output video
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Piece {
+    /// Whole GOPs, from a keyframe to the next one (or the end of the file): packets are copied untouched
+    Copy { start: f64, end: f64 },
+    /// Partial GOP at either edge of a range: decoded and re-encoded
+    Reencode { start: f64, end: f64 },
+}
+
+impl Piece {
+    fn bounds(&self) -> (f64, f64) {
+        match *self {
+            Piece::Copy { start, end } | Piece::Reencode { start, end } => (start, end),
+        }
+    }
+}
+
+/// Check the input can be smart-cut, with edges encoded to match its video stream, and load its keyframe index
+pub fn probe_source(input_path: &str, export: &ExportSettings) -> Result<SmartCutSource, String> {
+    let media = probe_media(input_path)?;
+    let stream = media.video_streams.first().ok_or("no video stream")?;
+    let edge_args = edge_encoder_args(stream, export)?;
+    // Only the software encoders can be held to the source's profile, level and pixel format
+    let encoder = &edge_args[1];
+    if !available_encoders().iter().any(|e| &e.name == encoder) {
+        return Err(format!("{} is needed to match the source's edges", encoder));
+    }
+
+    // Packet times are absolute, FFmpeg's -ss is relative to the container start
+    let start_time = media.container.start_time;
+
+    let keyframes = probe_keyframes(input_path, start_time)?;
+    if keyframes.is_empty() {
+        return Err("no keyframes found".to_string());
+    }
+
+    eprintln!("🔑 Found {} keyframes in {} source", keyframes.len(), stream.codec);
+
+    Ok(SmartCutSource {
+        input_path: input_path.to_string(),
+        edge_args,
+        keyframes,
+        duration: media.container.duration.unwrap_or(f64::INFINITY),
+    })
+}
+
+/// Encoder arguments (`-c:v <encoder>` first) that reproduce the source stream's codec, profile, level,
+/// pixel format and colour tags, so re-encoded edges and copied GOPs decode as one stream. A stream
+/// x264/x265 can't reproduce falls back to a full re-encode.
+fn edge_encoder_args(stream: &VideoStreamInfo, export: &ExportSettings) -> Result<Vec<String>, String> {
+    let (codec, encoder) = match stream.codec.as_str() {
+        "h264" => (VideoCodec::H264, "libx264"),
+        "hevc" => (VideoCodec::Hevc, "libx265"),
+        other => return Err(format!("no matching encoder for {} edges", other)),
+    };
+    if stream.dolby_vision {
+        return Err("Dolby Vision metadata can't be re-created for the edges".to_string());
+    }
+
+    let source_profile = stream.profile.as_deref().unwrap_or("unknown");
+    let profile = edge_profile(codec, source_profile)
+        .ok_or_else(|| format!("no matching encoder profile for {:?} {}", codec, source_profile))?;
+    let pixel_format = stream.pixel_format.as_deref().ok_or("unknown pixel format")?;
+
+    let mut args = vec!["-c:v".to_string(), encoder.to_string()];
+    args.extend(rate_control_args(encoder, export.rate_control));
+    args.extend(preset_args(encoder, export.preset.as_deref()));
+    args.extend(["-profile:v".to_string(), profile.to_string()]);
+    match (codec, stream.level) {
+        (VideoCodec::H264, Some(level)) => {
+            args.extend(["-level:v".to_string(), format!("{}.{}", level / 10, level % 10)])
+        }
+        (VideoCodec::Hevc, Some(level)) => {
+            args.extend(["-x265-params".to_string(), format!("level-idc={}", level as f64 / 30.0)])
+        }
+        (_, None) => {}
+    }
+    args.extend(["-pix_fmt".to_string(), pixel_format.to_string()]);
+    if let Some(ref primaries) = stream.color_primaries {
+        args.extend(["-color_primaries".to_string(), primaries.clone()]);
+    }
+    if let Some(ref transfer) = stream.color_transfer {
+        args.extend(["-color_trc".to_string(), transfer.clone()]);
+    }
+
+    Ok(args)
+}
+
+/// The encoder's name for a profile as ffprobe reports it; None for profiles x264/x265 can't produce
+fn edge_profile(codec: VideoCodec, profile: &str) -> Option<&'static str> {
+    match (codec, profile) {
+        (VideoCodec::H264, "Constrained Baseline" | "Baseline") => Some("baseline"),
+        (VideoCodec::H264, "Main") => Some("main"),
+        (VideoCodec::H264, "High") => Some("high"),
+        (VideoCodec::H264, "High 10") => Some("high10"),
+        (VideoCodec::H264, "High 4:2:2") => Some("high422"),
+        (VideoCodec::H264, "High 4:4:4 Predictive") => Some("high444"),
+        (VideoCodec::Hevc, "Main") => Some("main"),
+        (VideoCodec::Hevc, "Main 10") => Some("main10"),
+        _ => None,
+    }
+}
+
+fn probe_keyframes(input_path: &str, start_time: f64) -> Result<Vec<f64>, String> {
+    // Packet flags come from the container index, so nothing has to be decoded
+    let output = Command::new("ffprobe")
+        .args([
+            "-v", "error",
+            "-select_streams", "v:0",
+            "-show_entries", "packet=pts_time,flags",
+            "-of", "csv=p=0",
+            input_path,
+        ])
+        .output()
+        .map_err(|e| format!("Failed to list keyframes: {}", e))?;
+
+    if !output.status.success() {
+        return Err(format!(
+            "ffprobe failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        ));
+    }
+
+    let mut keyframes: Vec<f64> = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| {
+            let (pts_time, flags) = line.split_once(',')?;
+            if !flags.contains('K') {
+                return None;
+            }
+            pts_time.parse::<f64>().ok().map(|t| t - start_time)
+        })
+        .collect();
+
+    // Packets are listed in decode order
+    keyframes.sort_by(|a, b| a.total_cmp(b));
+    Ok(keyframes)
+}
+
+/// Split each keep range into a re-encoded head up to its first keyframe, stream-copied GOPs up to its
+/// last keyframe and a re-encoded tail. Copied packets after the cut-out point would still be decoded
+/// from, so a copy only ends on a keyframe or at the end of the file.
+fn plan_pieces(keep_ranges: &[(f64, f64)], keyframes: &[f64], duration: f64) -> Vec<Piece> {
+    let mut pieces = Vec::new();
+
+    for &(start, end) in keep_ranges {
+        if end <= start {
+            continue;
+        }
+
+        // First keyframe at (or within a frame of) the cut-in point, and the last one before the cut-out
+        let first_index = keyframes.partition_point(|&k| k < start - KEYFRAME_TOLERANCE);
+        let last_index = keyframes.partition_point(|&k| k < end - KEYFRAME_TOLERANCE);
+        let ends_on_gop = end >= duration - KEYFRAME_TOLERANCE
+            || keyframes.get(last_index).is_some_and(|&k| k - end <= KEYFRAME_TOLERANCE);
+
+        if first_index >= last_index {
+            // No keyframe inside the range, the whole thing is a GOP fragment
+            pieces.push(Piece::Reencode { start, end });
+            continue;
+        }
+        let first_keyframe = keyframes[first_index];
+        let copy_end = if ends_on_gop { end } else { keyframes[last_index - 1] };
+        if copy_end - first_keyframe <= KEYFRAME_TOLERANCE {
+            // One keyframe and no whole GOP after it: re-encoding the range in one go is no more work
+            pieces.push(Piece::Reencode { start, end });
+            continue;
+        }
+
+        if first_keyframe - start > KEYFRAME_TOLERANCE {
+            pieces.push(Piece::Reencode { start, end: first_keyframe });
+        }
+        pieces.push(Piece::Copy {
+            start: first_keyframe.max(start),
+            end: copy_end,
+        });
+        if !ends_on_gop {
+            pieces.push(Piece::Reencode { start: copy_end, end });
+        }
+    }
+
+    pieces
+}
+
+impl SmartCutSource {
+    /// Write each piece as a video-only MPEG-TS segment, then join them with the concat demuxer
+    /// and mux in the audio, which is always cut with an aselect pass (cheap, and needed for enhancement).
+    pub fn export<F>(
+        &self,
+        timeline: &Timeline,
+        output_path: &str,
+        enhance_audio: bool,
+        cancel: &CancelToken,
+        mut on_progress: F,
+    ) -> Result<(), String>
+    where
+        F: FnMut(FFmpegProgress),
+    {
+        let pieces = plan_pieces(timeline.ranges(), &self.keyframes, self.duration);
+        let copied = pieces.iter().filter(|p| matches!(p, Piece::Copy { .. })).count();
+        eprintln!(
+            "✂️ Smart cut: {} pieces ({} stream-copied, {} re-encoded)",
+            pieces.len(),
+            copied,
+            pieces.len() - copied
+        );
+
+        let work_dir = ScratchDir::new("smartcut")?;
+        let total_duration: f64 = pieces.iter().map(|p| p.bounds().1 - p.bounds().0).sum();
+        let started = Instant::now();
+        let mut done_duration = 0.0;
+        let mut concat_list = String::new();
+
+        for (i, piece) in pieces.iter().enumerate() {
+            let (start, end) = piece.bounds();
+            let segment_path = work_dir.file(&format!("segment_{:05}.ts", i));
+            let start_str = format!("{:.6}", start);
+            let duration_str = format!("{:.6}", end - start);
+
+            // Input seeking: exact on a keyframe when copying, frame-accurate when re-encoding.
+            // Edges keep the stored orientation, as the copied packets do.
+            let mut args = vec!["-ss", &start_str];
+            if matches!(piece, Piece::Reencode { .. }) {
+                args.push("-noautorotate");
+            }
+            args.extend(["-i", &self.input_path, "-t", &duration_str, "-map", "0:v:0"]);
+            match piece {
+                Piece::Copy { .. } => args.extend(["-c:v", "copy"]),
+                Piece::Reencode { .. } => args.extend(self.edge_args.iter().map(String::as_str)),
+            }
+            // MPEG-TS keeps parameter sets in-band so copied and re-encoded parts concat cleanly
+            args.extend(["-an", "-f", "mpegts", "-y", &segment_path]);
+
+            run_ffmpeg_command(args, cancel)?;
+
+            // Explicit durations keep the video timeline locked to the audio cut
+            concat_list.push_str(&format!(
+                "file '{}'\nduration {}\n",
+                segment_path.replace('\'', "'\\''"),
+                duration_str
+            ));
+
+            done_duration += end - start;
+            on_progress(FFmpegProgress::new(
+                done_duration * SEGMENT_PROGRESS_SHARE,
+                total_duration,
+                started.elapsed(),
+            ));
+        }
+
+        let concat_path = work_dir.file("segments.txt");
+        fs::write(&concat_path, concat_list)
+            .map_err(|e| format!("Failed to write concat list: {}", e))?;
+
+        // The aselect expression grows with every range, so pass it as a script file
+        let audio_filter_path = work_dir.file("audio_filter.txt");
+        fs::write(&audio_filter_path, audio_cut_filter(timeline.ranges(), enhance_audio))
+            .map_err(|e| format!("Failed to write audio filter: {}", e))?;
+
+        let args = vec![
+            "-f", "concat",
+            "-safe", "0",
+            "-i", &concat_path,
+            "-i", &self.input_path,
+            "-map", "0:v:0",
+            "-map", "1:a:0?",
+            "-filter_script:a", &audio_filter_path,
+            "-c:v", "copy",
+            "-c:a", "aac",
+            "-b:a", "192k",
+            "-ar", "44100",
+            "-movflags", "+faststart",
+            "-y", output_path,
+        ];
+
+        run_ffmpeg_command_with_progress(args, total_duration, cancel, |p| {
+            let overall = SEGMENT_PROGRESS_SHARE + (1.0 - SEGMENT_PROGRESS_SHARE) * p.progress;
+            on_progress(FFmpegProgress::new(overall * total_duration, total_duration, started.elapsed()));
+        })?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A keyframe every 2 seconds of a 20 second video
+    fn keyframes() -> Vec<f64> {
+        (0..10).map(|n| n as f64 * 2.0).collect()
+    }
+
+    fn stream(codec: &str, profile: &str, level: i32, pixel_format: &str) -> VideoStreamInfo {
+        VideoStreamInfo {
+            index: 0,
+            codec: codec.to_string(),
+            profile: Some(profile.to_string()),
+            level: Some(level),
+            width: 1920,
+            height: 1080,
+            fps: Some(30.0),
+            rotation: 0,
+            pixel_format: Some(pixel_format.to_string()),
+            color_transfer: None,
+            color_primaries: Some("bt709".to_string()),
+            hdr: false,
+            dolby_vision: false,
+            duration: None,
+        }
+    }
+
+    fn has(args: &[String], option: &str, value: &str) -> bool {
+        args.windows(2).any(|pair| pair[0] == option && pair[1] == value)
+    }
+
+    #[test]
+    fn edges_match_the_source_stream() {
+        let settings = ExportSettings::default();
+
+        let args = edge_encoder_args(&stream("h264", "Main", 41, "yuv420p"), &settings).unwrap();
+        assert!(has(&args, "-c:v", "libx264"));
+        assert!(has(&args, "-profile:v", "main"));
+        assert!(has(&args, "-level:v", "4.1"));
+        assert!(has(&args, "-pix_fmt", "yuv420p"));
+        assert!(has(&args, "-color_primaries", "bt709"));
+
+        let args = edge_encoder_args(&stream("hevc", "Main 10", 123, "yuv420p10le"), &settings).unwrap();
+        assert!(has(&args, "-c:v", "libx265"));
+        assert!(has(&args, "-profile:v", "main10"));
+        assert!(has(&args, "-x265-params", "level-idc=4.1"));
+        assert!(has(&args, "-pix_fmt", "yuv420p10le"));
+    }
+
+    #[test]
+    fn unmatchable_sources_are_refused() {
+        let settings = ExportSettings::default();
+        assert!(edge_encoder_args(&stream("vp9", "Profile 0", 41, "yuv420p"), &settings).is_err());
+        assert!(edge_encoder_args(&stream("hevc", "Rext", 123, "yuv444p"), &settings).is_err());
+
+        let mut dolby_vision = stream("hevc", "Main 10", 153, "yuv420p10le");
+        dolby_vision.dolby_vision = true;
+        assert!(edge_encoder_args(&dolby_vision, &settings).is_err());
+    }
+
+    #[test]
+    fn both_edges_of_a_range_are_reencoded() {
+        let pieces = plan_pieces(&[(1.0, 7.5)], &keyframes(), 20.0);
+        assert_eq!(
+            pieces,
+            vec![
+                Piece::Reencode { start: 1.0, end: 2.0 },
+                Piece::Copy { start: 2.0, end: 6.0 },
+                Piece::Reencode { start: 6.0, end: 7.5 },
+            ]
+        );
+    }
+
+    #[test]
+    fn ranges_on_keyframes_are_copied_whole() {
+        let pieces = plan_pieces(&[(2.0, 6.0), (12.005, 20.0)], &keyframes(), 20.0);
+        assert_eq!(
+            pieces,
+            vec![Piece::Copy { start: 2.0, end: 6.0 }, Piece::Copy { start: 12.005, end: 20.0 }]
+        );
+    }
+
+    #[test]
+    fn copy_runs_to_the_end_of_the_file() {
+        let pieces = plan_pieces(&[(17.0, 19.5)], &keyframes(), 19.5);
+        assert_eq!(
+            pieces,
+            vec![Piece::Reencode { start: 17.0, end: 18.0 }, Piece::Copy { start: 18.0, end: 19.5 }]
+        );
+    }
+
+    #[test]
+    fn ranges_without_a_whole_gop_are_reencoded() {
+        let pieces = plan_pieces(&[(2.5, 3.5), (4.0, 5.0), (5.5, 6.5)], &keyframes(), 20.0);
+        assert_eq!(
+            pieces,
+            vec![
+                Piece::Reencode { start: 2.5, end: 3.5 },
+                Piece::Reencode { start: 4.0, end: 5.0 },
+                Piece::Reencode { start: 5.5, end: 6.5 },
+            ]
+        );
+    }
+}
@@ -0,0 +1,532 @@
+use std::fs;
+
+use crate::models::{BurnInCaptions, CaptionPosition, CaptionStyle, Segment, SubtitleFormat, SubtitleSettings};
+use crate::probe::probe_media;
+use crate::timeline::Timeline;
+
+/// A pause this long between two words always starts a new cue
+const MAX_WORD_GAP: f64 = 1.0;
+
+/// A word placed on the caption timeline
+#[derive(Debug, Clone)]
+pub struct CueWord {
+    pub text: String,
+    pub start: f64,
+    pub end: f64,
+}
+
+/// One caption on screen, as lines of timed words
+#[derive(Debug, Clone)]
+pub struct Cue {
+    pub start: f64,
+    pub end: f64,
+    pub lines: Vec<Vec<CueWord>>,
+}
+
+impl Cue {
+    fn from_lines(lines: Vec<Vec<CueWord>>) -> Self {
+        let start = lines.first().and_then(|l| l.first()).map(|w| w.start).unwrap_or(0.0);
+        let end = lines.last().and_then(|l| l.last()).map(|w| w.end).unwrap_or(start);
+        Cue { start, end, lines }
+    }
+
+    pub fn text_lines(&self) -> Vec<String> {
+        self.lines
+            .iter()
+            .map(|line| line.iter().map(|w| w.text.as_str()).collect::<Vec<_>>().join(" "))
+            .collect()
+    }
+}
+
+/// Group transcript words into cues. With a `timeline`, words that were cut are dropped
+/// and the rest are moved onto the edited output.
+pub fn build_cues(
+    segments: &[Segment],
+    timeline: Option<&Timeline>,
+    settings: &SubtitleSettings,
+) -> Vec<Cue> {
+    let mut cues = Vec::new();
+
+    // Segments are Whisper's sentence-ish units, so a cue never spans two of them
+    for segment in segments {
+        let mut words = segment_words(segment);
+        if let Some(timeline) = timeline {
+            words = remap_words(words, timeline);
+        }
+        cues.extend(group_words(words, settings));
+    }
+
+    cues.sort_by(|a, b| a.start.total_cmp(&b.start));
+    fit_durations(&mut cues, settings);
+    cues
+}
+
+/// Timed words of a segment; segments without word timings get their text spread evenly
+fn segment_words(segment: &Segment) -> Vec<CueWord> {
+    if !segment.words.is_empty() {
+        return segment
+            .words
+            .iter()
+            .filter(|w| !w.word.trim().is_empty())
+            .map(|w| CueWord {
+                text: w.word.trim().to_string(),
+                start: w.start,
+                end: w.end,
+            })
+            .collect();
+    }
+
+    let tokens: Vec<&str> = segment.text.split_whitespace().collect();
+    let step = (segment.end - segment.start).max(0.0) / tokens.len().max(1) as f64;
+    tokens
+        .iter()
+        .enumerate()
+        .map(|(i, token)| CueWord {
+            text: token.to_string(),
+            start: segment.start + step * i as f64,
+            end: segment.start + step * (i + 1) as f64,
+        })
+        .collect()
+}
+
+/// Move words onto the edited output; a word whose midpoint was cut is dropped
+fn remap_words(words: Vec<CueWord>, timeline: &Timeline) -> Vec<CueWord> {
+    words
+        .into_iter()
+        .filter_map(|word| {
+            let (start, end) = timeline.map_span(word.start, word.end)?;
+            Some(CueWord {
+                text: word.text,
+                start,
+                end,
+            })
+        })
+        .collect()
+}
+
+/// Fill lines greedily, starting a new cue when the lines are full, the cue gets too long
+/// or the speaker pauses
+fn group_words(words: Vec<CueWord>, settings: &SubtitleSettings) -> Vec<Cue> {
+    let mut cues = Vec::new();
+    let mut lines: Vec<Vec<CueWord>> = Vec::new();
+    let mut line_chars = 0;
+
+    for word in words {
+        let word_chars = word.text.chars().count();
+
+        let cue_start = lines.first().and_then(|l| l.first()).map(|w| w.start);
+        let last_end = lines.last().and_then(|l| l.last()).map(|w| w.end);
+
+        if let (Some(cue_start), Some(last_end)) = (cue_start, last_end) {
+            let fits_line = line_chars + 1 + word_chars <= settings.max_chars_per_line;
+            let too_long = word.end - cue_start > settings.max_cue_duration;
+            let paused = word.start - last_end > MAX_WORD_GAP;
+
+            if too_long || paused || (!fits_line && lines.len() >= settings.max_lines.max(1)) {
+                cues.push(Cue::from_lines(std::mem::take(&mut lines)));
+            } else if !fits_line {
+                lines.push(Vec::new());
+                line_chars = 0;
+            }
+        }
+
+        if lines.is_empty() {
+            lines.push(Vec::new());
+            line_chars = 0;
+        }
+
+        if let Some(line) = lines.last_mut() {
+            line_chars += if line.is_empty() { word_chars } else { word_chars + 1 };
+            line.push(word);
+        }
+    }
+
+    if !lines.is_empty() {
+        cues.push(Cue::from_lines(lines));
+    }
+
+    cues
+}
+
+/// Clamp cues to the max duration and hold short ones up to the min duration,
+/// without ever overlapping the next cue
+fn fit_durations(cues: &mut [Cue], settings: &SubtitleSettings) {
+    for i in 0..cues.len() {
+        let next_start = cues.get(i + 1).map(|c| c.start).unwrap_or(f64::INFINITY);
+        let cue = &mut cues[i];
+
+        cue.end = cue.end.min(cue.start + settings.max_cue_duration);
+        if cue.end - cue.start < settings.min_cue_duration {
+            cue.end = cue.end.max(cue.start + settings.min_cue_duration);
+        }
+        if next_start > cue.start {
+            cue.end = cue.end.min(next_start);
+        }
+    }
+}
+
+pub fn to_srt(cues: &[Cue]) -> String {
+    let mut output = String::new();
+
+    for (i, cue) in cues.iter().enumerate() {
+        output.push_str(&format!(
+            "{}\n{} --> {}\n{}\n\n",
+            i + 1,
+            format_timestamp(cue.start, ','),
+            format_timestamp(cue.end, ','),
+            cue.text_lines().join("\n")
+        ));
+    }
+
+    output
+}
+
+pub fn to_vtt(cues: &[Cue]) -> String {
+    let mut output = String::from("WEBVTT\n\n");
+
+    for cue in cues {
+        let text: Vec<String> = cue.text_lines().iter().map(|l| escape_vtt(l)).collect();
+        output.push_str(&format!(
+            "{} --> {}\n{}\n\n",
+            format_timestamp(cue.start, '.'),
+            format_timestamp(cue.end, '.'),
+            text.join("\n")
+        ));
+    }
+
+    output
+}
+
+/// Advanced SubStation script with `\k` karaoke timing: each word switches from the text color
+/// to the highlight color as it is spoken. `width`/`height` are the video size, so style sizes are pixels.
+pub fn to_ass_karaoke(cues: &[Cue], style: &CaptionStyle, width: u32, height: u32) -> Result<String, String> {
+    // Numpad-style alignment, always horizontally centered
+    let alignment = match style.position {
+        CaptionPosition::Bottom => 2,
+        CaptionPosition::Middle => 5,
+        CaptionPosition::Top => 8,
+    };
+
+    // \k fills from SecondaryColour to PrimaryColour, so the highlight is the primary
+    let mut output = format!(
+        "[Script Info]\n\
+         ScriptType: v4.00+\n\
+         PlayResX: {}\n\
+         PlayResY: {}\n\
+         WrapStyle: 2\n\
+         ScaledBorderAndShadow: yes\n\
+         \n\
+         [V4+ Styles]\n\
+         Format: Name, Fontname, Fontsize, PrimaryColour, SecondaryColour, OutlineColour, BackColour, \
+         Bold, Italic, Underline, StrikeOut, ScaleX, ScaleY, Spacing, Angle, BorderStyle, Outline, Shadow, \
+         Alignment, MarginL, MarginR, MarginV, Encoding\n\
+         Style: Caption,{},{},{},{},{},&H80000000,{},0,0,0,100,100,0,0,1,{},0,{},40,40,{},1\n\
+         \n\
+         [Events]\n\
+         Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\n",
+        width,
+        height,
+        style.font.replace(',', " "),
+        style.font_size,
+        ass_color(&style.highlight_color)?,
+        ass_color(&style.text_color)?,
+        ass_color(&style.outline_color)?,
+        if style.bold { -1 } else { 0 },
+        style.outline_width,
+        alignment,
+        style.margin
+    );
+
+    for cue in cues {
+        output.push_str(&format!(
+            "Dialogue: 0,{},{},Caption,,0,0,0,,{}\n",
+            format_ass_timestamp(cue.start),
+            format_ass_timestamp(cue.end),
+            karaoke_text(cue)
+        ));
+    }
+
+    Ok(output)
+}
+
+/// Cue text with a `{\kN}` tag (centiseconds) before every word. Each word lasts until the next
+/// one starts, so pauses stay on the word just spoken.
+fn karaoke_text(cue: &Cue) -> String {
+    let words: Vec<&CueWord> = cue.lines.iter().flatten().collect();
+    let cue_start_cs = (cue.start * 100.0).round() as i64;
+    // Durations are taken between rounded absolute times so they never drift
+    let mut elapsed_cs = 0;
+    let mut text = String::new();
+
+    let first_start_cs = words
+        .first()
+        .map(|w| (w.start * 100.0).round() as i64 - cue_start_cs)
+        .unwrap_or(0);
+    if first_start_cs > 0 {
+        text.push_str(&format!("{{\\k{}}}", first_start_cs));
+        elapsed_cs = first_start_cs;
+    }
+
+    let mut word_index = 0;
+    for (line_index, line) in cue.lines.iter().enumerate() {
+        if line_index > 0 {
+            text.push_str("\\N");
+        }
+
+        for (i, word) in line.iter().enumerate() {
+            let boundary = words.get(word_index + 1).map(|w| w.start).unwrap_or(cue.end);
+            let boundary_cs = ((boundary * 100.0).round() as i64 - cue_start_cs).max(elapsed_cs);
+
+            text.push_str(&format!("{{\\k{}}}{}", boundary_cs - elapsed_cs, escape_ass(&word.text)));
+            if i + 1 < line.len() {
+                text.push(' ');
+            }
+
+            elapsed_cs = boundary_cs;
+            word_index += 1;
+        }
+    }
+
+    text
+}
+
+/// Build karaoke cues on the edited output of `timeline` and write them as an ASS script
+/// sized to the input video
+pub fn write_karaoke_captions(
+    input_path: &str,
+    segments: &[Segment],
+    timeline: &Timeline,
+    captions: &BurnInCaptions,
+    output_path: &str,
+) -> Result<(), String> {
+    let media = probe_media(input_path)?;
+    let video = media
+        .video_streams
+        .first()
+        .ok_or_else(|| format!("{} has no video track to caption", input_path))?;
+
+    // FFmpeg autorotates, so phone footage is rendered at its upright size
+    let (width, height) = if video.rotation % 180 == 90 {
+        (video.height, video.width)
+    } else {
+        (video.width, video.height)
+    };
+
+    let cues = build_cues(segments, Some(timeline), &captions.layout);
+    let script = to_ass_karaoke(&cues, &captions.style, width, height)?;
+
+    fs::write(output_path, script)
+        .map_err(|e| format!("Failed to write captions to {}: {}", output_path, e))?;
+
+    eprintln!("💬 Wrote {} karaoke cues to {}", cues.len(), output_path);
+    Ok(())
+}
+
+/// Build cues and write them in the requested format
+pub fn export_subtitles(
+    segments: &[Segment],
+    timeline: Option<&Timeline>,
+    format: SubtitleFormat,
+    settings: &SubtitleSettings,
+    output_path: &str,
+) -> Result<(), String> {
+    let cues = build_cues(segments, timeline, settings);
+
+    let contents = match format {
+        SubtitleFormat::Srt => to_srt(&cues),
+        SubtitleFormat::Vtt => to_vtt(&cues),
+    };
+
+    fs::write(output_path, contents)
+        .map_err(|e| format!("Failed to write subtitles to {}: {}", output_path, e))?;
+
+    eprintln!("💬 Wrote {} cues to {}", cues.len(), output_path);
+    Ok(())
+}
+
+/// `HH:MM:SS,mmm` for SRT, `HH:MM:SS.mmm` for WebVTT
+fn format_timestamp(seconds: f64, separator: char) -> String {
+    let millis = (seconds.max(0.0) * 1000.0).round() as u64;
+    format!(
+        "{:02}:{:02}:{:02}{}{:03}",
+        millis / 3_600_000,
+        millis / 60_000 % 60,
+        millis / 1000 % 60,
+        separator,
+        millis % 1000
+    )
+}
+
+/// `H:MM:SS.cc`
+fn format_ass_timestamp(seconds: f64) -> String {
+    let centis = (seconds.max(0.0) * 100.0).round() as u64;
+    format!(
+        "{}:{:02}:{:02}.{:02}",
+        centis / 360_000,
+        centis / 6000 % 60,
+        centis / 100 % 60,
+        centis % 100
+    )
+}
+
+/// `#RRGGBB` to ASS `&HAABBGGRR` (fully opaque)
+fn ass_color(hex: &str) -> Result<String, String> {
+    let digits = hex.trim_start_matches('#');
+    if digits.len() != 6 || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(format!("Invalid caption color {}, expected #RRGGBB", hex));
+    }
+
+    Ok(format!("&H00{}{}{}", &digits[4..6], &digits[2..4], &digits[0..2]).to_uppercase())
+}
+
+/// Braces and backslashes would start override tags
+fn escape_ass(text: &str) -> String {
+    text.replace('\\', "/").replace('{', "(").replace('}', ")")
+}
+
+fn escape_vtt(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::Word;
+
+    /// One segment with a word every `step` seconds, each lasting `step * 0.8`
+    fn segment(start: f64, step: f64, words: &[&str]) -> Segment {
+        let words: Vec<Word> = words
+            .iter()
+            .enumerate()
+            .map(|(i, word)| Word {
+                id: format!("w{}", i),
+                word: word.to_string(),
+                start: start + step * i as f64,
+                end: start + step * i as f64 + step * 0.8,
+                confidence: None,
+            })
+            .collect();
+        Segment {
+            id: 0,
+            start,
+            end: words.last().map_or(start, |w| w.end),
+            text: words.iter().map(|w| w.word.as_str()).collect::<Vec<_>>().join(" "),
+            words,
+            confidence: None,
+            avg_logprob: None,
+        }
+    }
+
+    fn cue(start: f64, end: f64, text: &str) -> Cue {
+        Cue {
+            start,
+            end,
+            lines: vec![vec![CueWord {
+                text: text.to_string(),
+                start,
+                end,
+            }]],
+        }
+    }
+
+    #[test]
+    fn timestamps_are_formatted_per_format() {
+        assert_eq!(format_timestamp(3725.5, ','), "01:02:05,500");
+        assert_eq!(format_timestamp(0.9996, '.'), "00:00:01.000");
+        assert_eq!(format_timestamp(-1.0, ','), "00:00:00,000");
+        assert_eq!(format_ass_timestamp(3725.456), "1:02:05.46");
+    }
+
+    #[test]
+    fn words_fill_lines_then_cues() {
+        let settings = SubtitleSettings {
+            max_chars_per_line: 11,
+            max_lines: 2,
+            min_cue_duration: 0.0,
+            max_cue_duration: 100.0,
+        };
+        let words = ["uno", "dos", "tres", "cuatro", "cinco", "seis", "siete"];
+        let cues = build_cues(&[segment(0.0, 0.5, &words)], None, &settings);
+
+        let texts: Vec<Vec<String>> = cues.iter().map(Cue::text_lines).collect();
+        assert_eq!(texts, vec![vec!["uno dos", "tres cuatro"], vec!["cinco seis", "siete"]]);
+        assert_eq!(cues[1].start, 2.0);
+    }
+
+    #[test]
+    fn a_pause_or_a_long_cue_starts_a_new_one() {
+        let settings = SubtitleSettings {
+            max_chars_per_line: 42,
+            max_lines: 2,
+            min_cue_duration: 0.0,
+            max_cue_duration: 2.0,
+        };
+        let segments = [segment(0.0, 0.5, &["a", "b", "c", "d", "e", "f"])];
+        let cues = build_cues(&segments, None, &settings);
+        assert_eq!(cues.len(), 2);
+        assert!(cues.iter().all(|c| c.end - c.start <= 2.0));
+
+        let mut paused = segment(0.0, 0.5, &["antes", "de", "pausa"]);
+        paused.words[2].start = 3.0;
+        paused.words[2].end = 3.4;
+        let cues = build_cues(&[paused], None, &SubtitleSettings::default());
+        assert_eq!(cues.len(), 2);
+    }
+
+    #[test]
+    fn durations_are_fitted_without_overlapping() {
+        let settings = SubtitleSettings::default();
+        let mut cues = vec![cue(0.0, 0.2, "corto"), cue(1.0, 1.2, "hasta"), cue(1.5, 20.0, "largo")];
+        fit_durations(&mut cues, &settings);
+
+        let spans: Vec<(f64, f64)> = cues.iter().map(|c| (c.start, c.end)).collect();
+        // Held to the minimum, cut short by the next cue, clamped to the maximum
+        assert_eq!(spans, vec![(0.0, 1.0), (1.0, 1.5), (1.5, 8.5)]);
+    }
+
+    #[test]
+    fn cut_words_are_dropped_and_the_rest_moved() {
+        let timeline = Timeline::new(&[(0.0, 2.0), (5.0, 10.0)], 10.0).unwrap();
+        let segments = [segment(1.0, 2.0, &["antes", "cortada", "después"])];
+        let cues = build_cues(&segments, Some(&timeline), &SubtitleSettings::default());
+
+        let words: Vec<(&str, f64)> = cues
+            .iter()
+            .flat_map(|c| c.lines.iter().flatten())
+            .map(|w| (w.text.as_str(), w.start))
+            .collect();
+        assert_eq!(words, vec![("antes", 1.0), ("después", 2.0)]);
+    }
+
+    #[test]
+    fn srt_and_vtt_are_written() {
+        let cues = [cue(1.0, 2.5, "<hola>"), cue(61.0, 62.0, "adiós")];
+
+        assert_eq!(
+            to_srt(&cues),
+            "1\n00:00:01,000 --> 00:00:02,500\n<hola>\n\n2\n00:01:01,000 --> 00:01:02,000\nadiós\n\n"
+        );
+        assert_eq!(
+            to_vtt(&cues),
+            "WEBVTT\n\n00:00:01.000 --> 00:00:02.500\n&lt;hola&gt;\n\n00:01:01.000 --> 00:01:02.000\nadiós\n\n"
+        );
+    }
+
+    #[test]
+    fn karaoke_words_last_until_the_next_one() {
+        let cues = build_cues(&[segment(0.5, 0.5, &["uno", "dos", "{tres}"])], None, &SubtitleSettings::default());
+        let script = to_ass_karaoke(&cues, &CaptionStyle::default(), 1920, 1080).unwrap();
+
+        assert!(script.contains("Style: Caption,Arial,64,&H0000D4FF,&H00FFFFFF,"), "{}", script);
+        assert!(
+            script.contains("Dialogue: 0,0:00:00.50,0:00:01.90,Caption,,0,0,0,,{\\k50}uno {\\k50}dos {\\k40}(tres)\n"),
+            "{}",
+            script
+        );
+        let unnamed_color = CaptionStyle {
+            text_color: "white".to_string(),
+            ..CaptionStyle::default()
+        };
+        assert!(to_ass_karaoke(&cues, &unnamed_color, 1920, 1080).is_err());
+    }
+}
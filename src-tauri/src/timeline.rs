@@ -0,0 +1,264 @@
+/// Ranges closer than this are treated as touching and merged
+const MERGE_EPSILON: f64 = 0.001;
+
+/// Mapping between a source video and an export made of its kept ranges.
+/// The ranges are always sorted, non-overlapping and inside the source.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Timeline {
+    ranges: Vec<(f64, f64)>,
+    /// Output time at which each range starts
+    offsets: Vec<f64>,
+    output_duration: f64,
+}
+
+impl Timeline {
+    /// Validate and normalize keep ranges (in source seconds) for a source of `source_duration`
+    pub fn new(keep_ranges: &[(f64, f64)], source_duration: f64) -> Result<Self, String> {
+        validate(keep_ranges)?;
+
+        let ranges = normalize(keep_ranges, source_duration);
+        if ranges.is_empty() {
+            return Err("Nothing to export: no kept range overlaps the video".to_string());
+        }
+
+        let mut offsets = Vec::with_capacity(ranges.len());
+        let mut output_duration = 0.0;
+        for (start, end) in &ranges {
+            offsets.push(output_duration);
+            output_duration += end - start;
+        }
+
+        Ok(Timeline {
+            ranges,
+            offsets,
+            output_duration,
+        })
+    }
+
+    /// Keep everything except the detected silences, leaving `margin` seconds of padding around speech
+    pub fn from_silences(silences: &[(f64, f64)], margin: f64, source_duration: f64) -> Result<Self, String> {
+        let mut keep_ranges = Vec::new();
+        let mut last_end = 0.0;
+
+        for &(silence_start, silence_end) in silences {
+            let keep_end = (silence_start + margin).min(source_duration);
+            if keep_end > last_end {
+                keep_ranges.push((last_end, keep_end));
+            }
+
+            // The next range starts before the silence ends, but never before this one ends
+            let next_start = (silence_end - margin).max(0.0);
+            last_end = next_start.max(keep_end);
+        }
+
+        if last_end < source_duration {
+            keep_ranges.push((last_end, source_duration));
+        }
+
+        Timeline::new(&keep_ranges, source_duration)
+    }
+
+    pub fn ranges(&self) -> &[(f64, f64)] {
+        &self.ranges
+    }
+
+    pub fn output_duration(&self) -> f64 {
+        self.output_duration
+    }
+
+    /// Total length removed from a source of `source_duration`
+    pub fn removed_duration(&self, source_duration: f64) -> f64 {
+        (source_duration - self.output_duration).max(0.0)
+    }
+
+    /// Output time of a source time, or None if that moment was cut
+    pub fn source_to_output(&self, source_time: f64) -> Option<f64> {
+        let index = self.range_at_source(source_time)?;
+        Some(self.offsets[index] + source_time - self.ranges[index].0)
+    }
+
+    /// Like `source_to_output`, but a cut moment maps to where playback resumes after the cut
+    pub fn source_to_output_clamped(&self, source_time: f64) -> f64 {
+        if let Some(output_time) = self.source_to_output(source_time) {
+            return output_time;
+        }
+
+        // First range starting after the cut
+        let next = self.ranges.partition_point(|&(start, _)| start <= source_time);
+        self.offsets.get(next).copied().unwrap_or(self.output_duration)
+    }
+
+    /// Source time shown at an output time, or None past either end of the export
+    pub fn output_to_source(&self, output_time: f64) -> Option<f64> {
+        if !(0.0..=self.output_duration).contains(&output_time) {
+            return None;
+        }
+
+        // On a boundary the next range is what gets shown
+        let index = self
+            .offsets
+            .partition_point(|&offset| offset <= output_time)
+            .saturating_sub(1);
+        let (start, end) = self.ranges[index];
+        Some((start + output_time - self.offsets[index]).min(end))
+    }
+
+    /// Map a source span (e.g. a word) to the output, using the range that holds its midpoint
+    /// and clamping to that range. None if the midpoint was cut.
+    pub fn map_span(&self, start: f64, end: f64) -> Option<(f64, f64)> {
+        let index = self.range_at_source((start + end) / 2.0)?;
+        let (range_start, range_end) = self.ranges[index];
+        let offset = self.offsets[index];
+
+        Some((
+            offset + start.max(range_start) - range_start,
+            offset + end.min(range_end) - range_start,
+        ))
+    }
+
+    /// Index of the range containing a source time; ranges are half-open except the last
+    fn range_at_source(&self, source_time: f64) -> Option<usize> {
+        let index = self
+            .ranges
+            .partition_point(|&(start, _)| start <= source_time)
+            .checked_sub(1)?;
+        let (_, end) = self.ranges[index];
+
+        if source_time < end || (index == self.ranges.len() - 1 && source_time == end) {
+            Some(index)
+        } else {
+            None
+        }
+    }
+}
+
+/// Reject ranges that can't be right: non-finite values, negative times or an end before the start
+pub fn validate(keep_ranges: &[(f64, f64)]) -> Result<(), String> {
+    for &(start, end) in keep_ranges {
+        if !start.is_finite() || !end.is_finite() {
+            return Err(format!("Invalid keep range {}..{}: not a number", start, end));
+        }
+        if end < start {
+            return Err(format!("Invalid keep range {}..{}: ends before it starts", start, end));
+        }
+        if end < 0.0 {
+            return Err(format!("Invalid keep range {}..{}: before the start of the video", start, end));
+        }
+    }
+
+    Ok(())
+}
+
+/// Sort, clamp to the source and merge overlapping or touching ranges; empty ranges are dropped
+pub fn normalize(keep_ranges: &[(f64, f64)], source_duration: f64) -> Vec<(f64, f64)> {
+    let mut clamped: Vec<(f64, f64)> = keep_ranges
+        .iter()
+        .map(|&(start, end)| (start.clamp(0.0, source_duration), end.clamp(0.0, source_duration)))
+        .filter(|(start, end)| end > start)
+        .collect();
+    clamped.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    let mut merged: Vec<(f64, f64)> = Vec::with_capacity(clamped.len());
+    for (start, end) in clamped {
+        match merged.last_mut() {
+            Some(last) if start <= last.1 + MERGE_EPSILON => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-9, "expected {}, got {}", expected, actual);
+    }
+
+    #[test]
+    fn normalize_sorts_clamps_and_merges() {
+        let ranges = normalize(&[(8.0, 12.0), (-1.0, 2.0), (1.5, 3.0), (5.0, 5.0), (3.0005, 4.0)], 10.0);
+        assert_eq!(ranges, vec![(0.0, 4.0), (8.0, 10.0)]);
+    }
+
+    #[test]
+    fn validate_rejects_bad_ranges() {
+        assert!(validate(&[(0.0, 1.0), (2.0, 3.0)]).is_ok());
+        assert!(validate(&[(2.0, 1.0)]).is_err());
+        assert!(validate(&[(f64::NAN, 1.0)]).is_err());
+        assert!(validate(&[(0.0, f64::INFINITY)]).is_err());
+        assert!(validate(&[(-2.0, -1.0)]).is_err());
+    }
+
+    #[test]
+    fn new_fails_when_nothing_is_kept() {
+        assert!(Timeline::new(&[], 10.0).is_err());
+        assert!(Timeline::new(&[(12.0, 15.0)], 10.0).is_err());
+    }
+
+    #[test]
+    fn source_to_output_skips_cuts() {
+        let timeline = Timeline::new(&[(0.0, 2.0), (5.0, 8.0)], 10.0).unwrap();
+        assert_close(timeline.output_duration(), 5.0);
+        assert_close(timeline.removed_duration(10.0), 5.0);
+
+        assert_close(timeline.source_to_output(1.0).unwrap(), 1.0);
+        assert_close(timeline.source_to_output(5.0).unwrap(), 2.0);
+        assert_close(timeline.source_to_output(7.5).unwrap(), 4.5);
+        assert_close(timeline.source_to_output(8.0).unwrap(), 5.0);
+        assert_eq!(timeline.source_to_output(2.0), None);
+        assert_eq!(timeline.source_to_output(3.0), None);
+        assert_eq!(timeline.source_to_output(9.0), None);
+    }
+
+    #[test]
+    fn clamped_mapping_resumes_after_cut() {
+        let timeline = Timeline::new(&[(1.0, 2.0), (5.0, 8.0)], 10.0).unwrap();
+        assert_close(timeline.source_to_output_clamped(0.5), 0.0);
+        assert_close(timeline.source_to_output_clamped(3.0), 1.0);
+        assert_close(timeline.source_to_output_clamped(9.0), 4.0);
+    }
+
+    #[test]
+    fn output_to_source_round_trips() {
+        let timeline = Timeline::new(&[(0.0, 2.0), (5.0, 8.0)], 10.0).unwrap();
+        assert_close(timeline.output_to_source(0.0).unwrap(), 0.0);
+        assert_close(timeline.output_to_source(2.0).unwrap(), 5.0);
+        assert_close(timeline.output_to_source(5.0).unwrap(), 8.0);
+        assert_eq!(timeline.output_to_source(5.5), None);
+        assert_eq!(timeline.output_to_source(-0.1), None);
+
+        for output_time in [0.3, 1.9, 2.4, 4.99] {
+            let source_time = timeline.output_to_source(output_time).unwrap();
+            assert_close(timeline.source_to_output(source_time).unwrap(), output_time);
+        }
+    }
+
+    #[test]
+    fn map_span_uses_midpoint_range() {
+        let timeline = Timeline::new(&[(0.0, 2.0), (5.0, 8.0)], 10.0).unwrap();
+        let (start, end) = timeline.map_span(1.8, 2.1).unwrap();
+        assert_close(start, 1.8);
+        assert_close(end, 2.0);
+
+        let (start, end) = timeline.map_span(4.8, 5.4).unwrap();
+        assert_close(start, 2.0);
+        assert_close(end, 2.4);
+
+        assert_eq!(timeline.map_span(2.5, 3.0), None);
+    }
+
+    #[test]
+    fn from_silences_keeps_margin_around_speech() {
+        let timeline = Timeline::from_silences(&[(2.0, 5.0), (7.0, 9.5)], 0.2, 10.0).unwrap();
+        let ranges = timeline.ranges();
+        assert_eq!(ranges.len(), 3);
+        assert_close(ranges[0].1, 2.2);
+        assert_close(ranges[1].0, 4.8);
+        assert_close(ranges[1].1, 7.2);
+        assert_close(ranges[2].0, 9.3);
+        assert_close(ranges[2].1, 10.0);
+    }
+}
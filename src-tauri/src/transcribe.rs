@@ -1,84 +1,229 @@
-use whisper_rs::{FullParams, SamplingStrategy, WhisperContext, WhisperContextParameters, WhisperState};
-
-use crate::ffmpeg::{extract_audio, get_video_duration};
-use crate::models::{PipelineConfig, Segment, Transcript, TranscriptResult, Word};
-
-pub fn get_model_path() -> String {
-    // Try multiple possible locations for the model
-    let possible_paths = vec![
-        "models/ggml-base.bin",
-        "../models/ggml-base.bin",
-        "src-tauri/models/ggml-base.bin",
-    ];
-
-    for path in possible_paths {
-        if std::path::Path::new(path).exists() {
-            eprintln!("✅ Found Whisper model at: {}", path);
-            return path.to_string();
-        } else {
-            eprintln!("⚠️ Model not found at: {}", path);
+use std::ffi::{c_int, c_void, CStr};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use whisper_rs::whisper_rs_sys::{self, whisper_context, whisper_state};
+use whisper_rs::{
+    get_lang_str, FullParams, SamplingStrategy, WhisperContext, WhisperContextParameters, WhisperState,
+};
+
+use crate::chunks::{chunk_read, cut_chunk, place_segments, renumber, stitch};
+use crate::ffmpeg::decode_audio;
+use crate::glossary::{initial_prompt, snap_to_glossary};
+use crate::job::{CancelToken, CANCELLED_ERROR};
+use crate::models::{
+    LlmProviderConfig, PipelineConfig, PipelineEvent, Segment, Transcript, TranscriptResult, TranscriptionTask,
+    WhisperParams, Word,
+};
+use crate::pipeline;
+use crate::probe::{get_duration, probe_media};
+use crate::prompts::PromptTemplates;
+use crate::transcript_cache::{TranscriptCache, TranscriptCheckpoint};
+use crate::whisper_models::{ModelManager, DEFAULT_MODEL};
+use crate::words::{confidence, group_tokens, is_text_token, timestamp_seconds, token_timing, TextToken};
+
+/// Whisper expects 16 kHz mono
+const SAMPLE_RATE: u32 = 16000;
+
+/// What a transcription reports while it runs
+#[derive(Debug, Clone)]
+pub enum TranscribeEvent {
+    /// Overall progress (0-1) and an estimate of the seconds left
+    Progress { progress: f64, eta_seconds: Option<f64> },
+    /// Segments Whisper just decoded, with text and timing only; words come with the final transcript
+    Segments(Vec<Segment>),
+}
+
+/// Where one Whisper run sits in the whole recording
+#[derive(Debug, Clone, Copy)]
+struct ChunkWindow {
+    /// Recording time (seconds) of the first sample Whisper gets
+    offset: f64,
+    /// Segments centred in `[owned_from, owned_to)` belong to this chunk; the rest is overlap
+    owned_from: f64,
+    owned_to: f64,
+    /// Overall progress (0-1) when this chunk starts and ends
+    progress_from: f64,
+    progress_to: f64,
+    /// Id of the first segment this chunk adds, for partial transcripts
+    first_segment_id: usize,
+}
+
+/// Rust side of Whisper's C callbacks during one `full` call.
+/// whisper-rs' closure-based callbacks are avoided: the progress one keeps a dangling pointer.
+struct DecodeCallbacks<'a> {
+    cancel: &'a CancelToken,
+    on_event: &'a dyn Fn(TranscribeEvent) -> Result<(), String>,
+    window: ChunkWindow,
+    /// When this transcription (not this chunk) started, and its progress then, for the ETA
+    started: Instant,
+    started_progress: f64,
+    /// Set once `on_event` fails, which aborts decoding like a cancel
+    failed: AtomicBool,
+    error: Mutex<Option<String>>,
+}
+
+impl<'a> DecodeCallbacks<'a> {
+    fn new(
+        cancel: &'a CancelToken,
+        on_event: &'a dyn Fn(TranscribeEvent) -> Result<(), String>,
+        window: ChunkWindow,
+        started: Instant,
+        started_progress: f64,
+    ) -> Self {
+        DecodeCallbacks {
+            cancel,
+            on_event,
+            window,
+            started,
+            started_progress,
+            failed: AtomicBool::new(false),
+            error: Mutex::new(None),
         }
     }
 
-    // Default to the standard location even if not found
-    eprintln!("❌ Model not found in any standard location, using default path");
-    "models/ggml-base.bin".to_string()
+    /// Point the params' progress, new-segment and abort callbacks at `self`.
+    /// `self` must outlive the `full` call that uses these params.
+    fn install(&self, params: &mut FullParams) {
+        let user_data = self as *const DecodeCallbacks as *mut c_void;
+        unsafe {
+            params.set_progress_callback(Some(progress_trampoline));
+            params.set_progress_callback_user_data(user_data);
+            params.set_new_segment_callback(Some(new_segment_trampoline));
+            params.set_new_segment_callback_user_data(user_data);
+            params.set_abort_callback(Some(abort_trampoline));
+            params.set_abort_callback_user_data(user_data);
+        }
+    }
+
+    /// Pass an event on; the first failure (or panic, which must not cross into C) is kept for later
+    fn emit(&self, event: TranscribeEvent) {
+        if self.failed.load(Ordering::SeqCst) {
+            return;
+        }
+
+        let outcome = panic::catch_unwind(AssertUnwindSafe(|| (self.on_event)(event)))
+            .unwrap_or_else(|_| Err("Transcription progress handler panicked".to_string()));
+
+        if let Err(e) = outcome {
+            *self.error.lock().unwrap_or_else(|e| e.into_inner()) = Some(e);
+            self.failed.store(true, Ordering::SeqCst);
+        }
+    }
+
+    fn take_error(&self) -> Option<String> {
+        self.error.lock().unwrap_or_else(|e| e.into_inner()).take()
+    }
+}
+
+/// `progress` is Whisper's percentage of the chunk decoded so far
+unsafe extern "C" fn progress_trampoline(
+    _ctx: *mut whisper_context,
+    _state: *mut whisper_state,
+    progress: c_int,
+    user_data: *mut c_void,
+) {
+    let callbacks = &*(user_data as *const DecodeCallbacks);
+    let window = callbacks.window;
+
+    let fraction = (progress as f64 / 100.0).clamp(0.0, 1.0);
+    let overall = window.progress_from + (window.progress_to - window.progress_from) * fraction;
+
+    // Chunks resumed from a checkpoint took no time in this run
+    let done = overall - callbacks.started_progress;
+    let elapsed = callbacks.started.elapsed().as_secs_f64();
+    let eta_seconds = (done > 0.0).then(|| elapsed * (1.0 - overall) / done);
+
+    callbacks.emit(TranscribeEvent::Progress {
+        progress: overall,
+        eta_seconds,
+    });
 }
 
-/// Extract word-level timestamps from a segment's tokens
-fn extract_words_from_segment(
+/// Called with the number of segments appended to the state since the last call
+unsafe extern "C" fn new_segment_trampoline(
+    _ctx: *mut whisper_context,
+    state: *mut whisper_state,
+    n_new: c_int,
+    user_data: *mut c_void,
+) {
+    let callbacks = &*(user_data as *const DecodeCallbacks);
+    let window = callbacks.window;
+
+    let total = whisper_rs_sys::whisper_full_n_segments_from_state(state);
+    let segments: Vec<Segment> = ((total - n_new).max(0)..total)
+        .filter_map(|i| {
+            let text = whisper_rs_sys::whisper_full_get_segment_text_from_state(state, i);
+            if text.is_null() {
+                return None;
+            }
+
+            Some(Segment {
+                id: window.first_segment_id + i as usize,
+                start: timestamp_seconds(whisper_rs_sys::whisper_full_get_segment_t0_from_state(state, i)),
+                end: timestamp_seconds(whisper_rs_sys::whisper_full_get_segment_t1_from_state(state, i)),
+                text: CStr::from_ptr(text).to_string_lossy().into_owned(),
+                words: Vec::new(),
+                confidence: None,
+                avg_logprob: None,
+            })
+        })
+        .collect();
+
+    // Segments from the overlap come from the neighbouring chunk instead
+    let segments = place_segments(segments, window.offset, window.owned_from, window.owned_to);
+    if !segments.is_empty() {
+        callbacks.emit(TranscribeEvent::Segments(segments));
+    }
+}
+
+/// Also called from ggml's worker threads, so only atomics are touched here
+unsafe extern "C" fn abort_trampoline(user_data: *mut c_void) -> bool {
+    let callbacks = &*(user_data as *const DecodeCallbacks);
+    callbacks.cancel.is_cancelled() || callbacks.failed.load(Ordering::SeqCst)
+}
+
+/// Text tokens of a segment, without timestamp and control tokens
+fn extract_segment_tokens(
+    context: &WhisperContext,
     state: &WhisperState,
     segment_index: i32,
-    global_word_index: &mut usize,
-) -> Result<Vec<Word>, String> {
-    let mut words = Vec::new();
-
+) -> Result<Vec<TextToken>, String> {
     let num_tokens = state
         .full_n_tokens(segment_index)
         .map_err(|e| format!("Failed to get token count for segment {}: {}", segment_index, e))?;
 
+    let mut tokens = Vec::new();
     for t in 0..num_tokens {
-        let token_text = state
-            .full_get_token_text(segment_index, t)
-            .map_err(|e| format!("Failed to get token text: {}", e))?;
-
-        // Skip special tokens (timestamps, control tokens)
-        if token_text.starts_with('[') || token_text.starts_with('<') {
-            continue;
-        }
-
         let token_data = state
             .full_get_token_data(segment_index, t)
             .map_err(|e| format!("Failed to get token data: {}", e))?;
 
-        // t0 and t1 are in centiseconds (100ths of a second)
-        let word_start = token_data.t0 as f64 / 100.0;
-        let word_end = token_data.t1 as f64 / 100.0;
-
-        // Skip tokens with invalid timestamps
-        if word_start < 0.0 || word_end <= word_start {
+        if !is_text_token(token_data.id, context.token_eot()) {
             continue;
         }
 
-        let trimmed_word = token_text.trim().to_string();
-
-        // Skip empty words
-        if !trimmed_word.is_empty() {
-            words.push(Word {
-                id: format!("w{}", *global_word_index),
-                word: trimmed_word,
-                start: word_start,
-                end: word_end,
-            });
-            *global_word_index += 1;
-        }
+        // Raw bytes, since one character may be split across two tokens
+        let bytes = context
+            .token_to_cstr(token_data.id)
+            .map_err(|e| format!("Failed to get token text: {}", e))?
+            .to_bytes()
+            .to_vec();
+
+        tokens.push(TextToken {
+            bytes,
+            timing: token_timing(token_data.t0, token_data.t1),
+            probability: token_data.p as f64,
+        });
     }
 
-    Ok(words)
+    Ok(tokens)
 }
 
 /// Extract segments with word-level timestamps from whisper state
-fn extract_segments_with_words(state: &WhisperState) -> Result<Vec<Segment>, String> {
+fn extract_segments_with_words(context: &WhisperContext, state: &WhisperState) -> Result<Vec<Segment>, String> {
     let num_segments = state
         .full_n_segments()
         .map_err(|e| format!("Failed to get segment count: {}", e))?;
@@ -87,212 +232,518 @@ fn extract_segments_with_words(state: &WhisperState) -> Result<Vec<Segment>, Str
     let mut global_word_index = 0usize;
 
     for i in 0..num_segments {
-        let start_ms = state
+        let t0 = state
             .full_get_segment_t0(i)
             .map_err(|e| format!("Failed to get segment start for segment {}: {}", i, e))?;
-        let end_ms = state
+        let t1 = state
             .full_get_segment_t1(i)
             .map_err(|e| format!("Failed to get segment end for segment {}: {}", i, e))?;
         let text = state
             .full_get_segment_text(i)
             .map_err(|e| format!("Failed to get segment text for segment {}: {}", i, e))?;
 
-        let words = extract_words_from_segment(state, i, &mut global_word_index)?;
+        let start = timestamp_seconds(t0);
+        let tokens = extract_segment_tokens(context, state, i)?;
+
+        let words = group_tokens(&tokens, start)
+            .into_iter()
+            .map(|word| {
+                let id = format!("w{}", global_word_index);
+                global_word_index += 1;
+                Word {
+                    id,
+                    word: word.text,
+                    start: word.start,
+                    end: word.end,
+                    confidence: word.confidence,
+                }
+            })
+            .collect();
+
+        let probabilities: Vec<f64> = tokens.iter().map(|token| token.probability).collect();
+        let avg_logprob = (!probabilities.is_empty()).then(|| {
+            probabilities.iter().map(|p| p.max(f64::MIN_POSITIVE).ln()).sum::<f64>() / probabilities.len() as f64
+        });
 
         result_segments.push(Segment {
             id: i as usize,
-            start: start_ms as f64 / 1000.0,
-            end: end_ms as f64 / 1000.0,
+            start,
+            end: timestamp_seconds(t1),
             text,
             words,
+            confidence: confidence(&probabilities),
+            avg_logprob,
         });
     }
 
     Ok(result_segments)
 }
 
-pub fn transcribe_audio(
-    input_path: &str,
-    output_pcm_path: &str,
-    model_path: &str,
-    config: &PipelineConfig,
-) -> Result<Transcript, String> {
-    extract_audio(input_path, output_pcm_path, 16000, 1)?;
-
-    let context = WhisperContext::new_with_params(model_path, WhisperContextParameters::default())
-        .map_err(|e| format!("Failed to load Whisper model: {}", e))?;
-
-    let pcm_data_bytes =
-        std::fs::read(output_pcm_path).map_err(|e| format!("Failed to read PCM data: {}", e))?;
-
-    let pcm_data: Vec<f32> = pcm_data_bytes
-        .chunks_exact(4)
-        .map(|chunk| {
-            let mut bytes = [0u8; 4];
-            bytes.copy_from_slice(chunk);
-            f32::from_le_bytes(bytes)
-        })
-        .collect();
+/// How one chunk is decoded
+struct DecodeSettings<'a> {
+    /// None detects the language
+    language: Option<&'a str>,
+    prompt: Option<&'a str>,
+    params: &'a WhisperParams,
+    translate: bool,
+}
 
-    let sampling_strategy = SamplingStrategy::Greedy { best_of: 1 };
-    let mut params = FullParams::new(sampling_strategy);
+/// Run Whisper over one chunk; returns its segments in chunk time and the language it ran with
+fn run_whisper(
+    context: &WhisperContext,
+    samples: &[f32],
+    settings: &DecodeSettings,
+    callbacks: &DecodeCallbacks,
+    cancel: &CancelToken,
+) -> Result<(Vec<Segment>, Option<String>), String> {
+    let whisper_params = settings.params;
+    let strategy = match whisper_params.beam_size {
+        Some(beam_size) => SamplingStrategy::BeamSearch {
+            beam_size: beam_size.max(1) as c_int,
+            patience: whisper_params.patience,
+        },
+        None => SamplingStrategy::Greedy {
+            best_of: whisper_params.best_of.max(1) as c_int,
+        },
+    };
+
+    let mut params = FullParams::new(strategy);
     params.set_no_timestamps(false);
     params.set_token_timestamps(true);
-    params.set_language(if let Some(ref lang) = config.language {
-        Some(lang.as_str())
-    } else {
-        None
-    });
+    params.set_language(settings.language);
+    params.set_translate(settings.translate);
+    params.set_temperature(whisper_params.temperature);
+    params.set_temperature_inc(whisper_params.temperature_increment);
+    params.set_entropy_thold(whisper_params.entropy_threshold);
+    params.set_logprob_thold(whisper_params.logprob_threshold);
+    if let Some(threads) = whisper_params.threads {
+        params.set_n_threads(threads.max(1) as c_int);
+    }
+    if let Some(max_len) = whisper_params.max_segment_length {
+        params.set_max_len(max_len as c_int);
+        params.set_split_on_word(whisper_params.split_on_word);
+    }
+    if let Some(prompt) = settings.prompt {
+        params.set_initial_prompt(prompt);
+    }
+    callbacks.install(&mut params);
 
     let mut state = context
         .create_state()
         .map_err(|e| format!("Failed to create state: {}", e))?;
-    state
-        .full(params, &pcm_data)
-        .map_err(|e| format!("Failed to transcribe: {}", e))?;
+    let outcome = state.full(params, samples);
 
-    let segments = extract_segments_with_words(&state)?;
+    if let Some(error) = callbacks.take_error() {
+        return Err(error);
+    }
+    outcome.map_err(|e| {
+        if cancel.is_cancelled() {
+            CANCELLED_ERROR.to_string()
+        } else {
+            format!("Failed to transcribe: {}", e)
+        }
+    })?;
 
-    Ok(Transcript {
-        segments,
-        language: None,
-    })
+    let mut segments = extract_segments_with_words(context, &state)?;
+    if settings.translate {
+        for segment in &mut segments {
+            segment.words.clear();
+        }
+    }
+
+    // The requested language, or the one Whisper detected
+    let language = state
+        .full_lang_id_from_state()
+        .ok()
+        .and_then(get_lang_str)
+        .map(str::to_string)
+        .or_else(|| settings.language.map(str::to_string));
+
+    Ok((segments, language))
 }
 
-pub fn transcribe_with_progress<F>(
-    input_path: &str,
-    output_pcm_path: &str,
-    model_path: &str,
-    config: &PipelineConfig,
-    progress_callback: F,
-) -> Result<Transcript, String>
-where
-    F: Fn(f64) -> Result<(), String>,
-{
-    extract_audio(input_path, output_pcm_path, 16000, 1)?;
-
-    progress_callback(0.5)?;
-
-    let context = WhisperContext::new_with_params(model_path, WhisperContextParameters::default())
-        .map_err(|e| format!("Failed to load Whisper model: {}", e))?;
-
-    let pcm_data_bytes =
-        std::fs::read(output_pcm_path).map_err(|e| format!("Failed to read PCM data: {}", e))?;
-
-    let pcm_data: Vec<f32> = pcm_data_bytes
-        .chunks_exact(4)
-        .map(|chunk| {
-            let mut bytes = [0u8; 4];
-            bytes.copy_from_slice(chunk);
-            f32::from_le_bytes(bytes)
-        })
-        .collect();
+/// Identifies everything that changes Whisper's output, for transcript cache keys.
+/// The thread count doesn't, so it's left out. Patience is kept although the bundled whisper.cpp
+/// ignores it, so cached transcripts stay right once it reads it.
+fn params_id(options: &TranscribeOptions, task: TranscriptionTask, prompt: Option<&str>) -> String {
+    let params = &options.params;
+    let strategy = match params.beam_size {
+        Some(beam_size) => format!("beam:size={},patience={}", beam_size, params.patience),
+        None => format!("greedy:best_of={}", params.best_of),
+    };
+
+    format!(
+        "{:?};{},token_timestamps;temperature={}+{};thresholds={},{};max_len={:?},split_on_word={};prompt={};glossary={}",
+        task,
+        strategy,
+        params.temperature,
+        params.temperature_increment,
+        params.entropy_threshold,
+        params.logprob_threshold,
+        params.max_segment_length,
+        params.split_on_word,
+        prompt.unwrap_or_default(),
+        options.glossary.join("\n")
+    )
+}
 
-    let sampling_strategy = SamplingStrategy::Greedy { best_of: 1 };
-    let mut params = FullParams::new(sampling_strategy);
-    params.set_no_timestamps(false);
-    params.set_token_timestamps(true);
-    params.set_language(if let Some(ref lang) = config.language {
-        Some(lang.as_str())
+/// Overall progress once the recording is transcribed up to `seconds`
+fn progress_at(seconds: f64, duration_seconds: f64) -> f64 {
+    if duration_seconds > 0.0 {
+        (seconds / duration_seconds).clamp(0.0, 1.0)
     } else {
-        None
-    });
+        0.0
+    }
+}
 
-    let mut state = context
-        .create_state()
-        .map_err(|e| format!("Failed to create state: {}", e))?;
-    state
-        .full(params, &pcm_data)
-        .map_err(|e| format!("Failed to transcribe: {}", e))?;
+/// Settings for one transcription
+#[derive(Debug, Clone, Default)]
+pub struct TranscribeOptions {
+    /// Model name from the models directory; None uses the default model
+    pub model: Option<String>,
+    /// Language code; None lets Whisper detect it
+    pub language: Option<String>,
+    /// `Translate` makes callers also ask for `Transcriber::translate`
+    pub task: TranscriptionTask,
+    pub initial_prompt: Option<String>,
+    pub glossary: Vec<String>,
+    pub params: WhisperParams,
+    /// Run Whisper even if a cached transcript exists
+    pub force: bool,
+}
 
-    progress_callback(1.0)?;
+impl TranscribeOptions {
+    /// Options for `Transcriber::translate` once the transcript is known; pinning the
+    /// spoken language spares Whisper detecting it again
+    pub fn for_translation(&self, spoken_language: Option<&str>) -> Self {
+        TranscribeOptions {
+            language: spoken_language.map(str::to_string).or_else(|| self.language.clone()),
+            ..self.clone()
+        }
+    }
 
-    let segments = extract_segments_with_words(&state)?;
+    pub fn from_config(config: &PipelineConfig) -> Self {
+        TranscribeOptions {
+            model: config.model.clone(),
+            language: config.language.clone(),
+            task: config.task,
+            initial_prompt: config.initial_prompt.clone(),
+            glossary: config.glossary.clone(),
+            params: config.whisper.clone(),
+            force: config.force_transcribe,
+        }
+    }
+}
 
-    Ok(Transcript {
-        segments,
-        language: None,
-    })
+/// Runs Whisper for every job. The last model used stays loaded, so back-to-back jobs
+/// skip the multi-second model load.
+pub struct Transcriber {
+    models: ModelManager,
+    cache: Option<TranscriptCache>,
+    /// Model path and its context
+    loaded: Mutex<Option<(String, Arc<WhisperContext>)>>,
 }
 
-/// Transcribe video and return TranscriptResult with word-level timestamps
-/// This is used by the text-based editor flow
-pub async fn transcribe_video_for_editor(
-    input_path: &str,
-    language: Option<&str>,
-    llm_api_key: Option<&str>,
-) -> Result<TranscriptResult, String> {
-    let pcm_path = format!("{}.pcm", input_path);
-    let model_path = get_model_path();
+impl Transcriber {
+    pub fn new(models: ModelManager, cache: Option<TranscriptCache>) -> Self {
+        Transcriber {
+            models,
+            cache,
+            loaded: Mutex::new(None),
+        }
+    }
 
-    // Get video duration
-    let duration_seconds = get_video_duration(input_path)?;
+    pub fn models(&self) -> &ModelManager {
+        &self.models
+    }
 
-    eprintln!("🎬 Transcribing video for editor: {}", input_path);
-    eprintln!("📏 Video duration: {:.2}s", duration_seconds);
+    pub fn cache(&self) -> Option<&TranscriptCache> {
+        self.cache.as_ref()
+    }
 
-    extract_audio(input_path, &pcm_path, 16000, 1)?;
+    /// Transcribe a media file with word timings, using the cache unless `options.force` is set.
+    /// Long recordings are transcribed in chunks; with a cache, finished chunks are checkpointed
+    /// so a cancelled or crashed job picks up where it stopped. An error from `on_event` aborts it.
+    pub fn transcribe(
+        &self,
+        input_path: &str,
+        options: &TranscribeOptions,
+        cancel: &CancelToken,
+        on_event: impl Fn(TranscribeEvent) -> Result<(), String>,
+    ) -> Result<Transcript, String> {
+        self.run(input_path, options, TranscriptionTask::Transcribe, cancel, on_event)
+    }
 
-    let context = WhisperContext::new_with_params(&model_path, WhisperContextParameters::default())
-        .map_err(|e| format!("Failed to load Whisper model: {}", e))?;
+    /// Whisper's English translation of a media file, like `transcribe` but with segment timing only;
+    /// Whisper's word timings don't line up with translated text
+    pub fn translate(
+        &self,
+        input_path: &str,
+        options: &TranscribeOptions,
+        cancel: &CancelToken,
+        on_event: impl Fn(TranscribeEvent) -> Result<(), String>,
+    ) -> Result<Transcript, String> {
+        self.run(input_path, options, TranscriptionTask::Translate, cancel, on_event)
+    }
 
-    let pcm_data_bytes =
-        std::fs::read(&pcm_path).map_err(|e| format!("Failed to read PCM data: {}", e))?;
+    fn run(
+        &self,
+        input_path: &str,
+        options: &TranscribeOptions,
+        task: TranscriptionTask,
+        cancel: &CancelToken,
+        on_event: impl Fn(TranscribeEvent) -> Result<(), String>,
+    ) -> Result<Transcript, String> {
+        let translate = task == TranscriptionTask::Translate;
+        if translate {
+            let model = options.model.as_deref().unwrap_or(DEFAULT_MODEL);
+            if model.ends_with(".en") {
+                return Err(format!("Whisper model \"{}\" is English-only and can't translate", model));
+            }
+            if model.contains("turbo") {
+                eprintln!("⚠️ Whisper model {} was not trained to translate; expect poor results", model);
+            }
+        }
 
-    let pcm_data: Vec<f32> = pcm_data_bytes
-        .chunks_exact(4)
-        .map(|chunk| {
-            let mut bytes = [0u8; 4];
-            bytes.copy_from_slice(chunk);
-            f32::from_le_bytes(bytes)
-        })
-        .collect();
+        let model_path = self.models.resolve(options.model.as_deref())?;
+        let language = options.language.as_deref();
+        let prompt = initial_prompt(options.initial_prompt.as_deref(), &options.glossary);
+
+        let cache_key = self
+            .cache
+            .as_ref()
+            .and_then(|c| c.key_for(input_path, &model_path, language, &params_id(options, task, prompt.as_deref())));
+
+        if let (Some(cache), Some(key)) = (&self.cache, &cache_key) {
+            if !options.force {
+                if let Some(cached) = cache.load(key, input_path) {
+                    on_event(TranscribeEvent::Progress {
+                        progress: 1.0,
+                        eta_seconds: None,
+                    })?;
+                    return Ok(Transcript {
+                        segments: cached.segments,
+                        language: cached.language,
+                    });
+                }
+            }
+        }
 
-    let sampling_strategy = SamplingStrategy::Greedy { best_of: 1 };
-    let mut params = FullParams::new(sampling_strategy);
-    params.set_no_timestamps(false);
-    params.set_token_timestamps(true);
-    params.set_language(language);
+        let duration_seconds = get_duration(input_path)?;
+        let context = self.context(&model_path)?;
 
-    let mut state = context
-        .create_state()
-        .map_err(|e| format!("Failed to create state: {}", e))?;
-    state
-        .full(params, &pcm_data)
-        .map_err(|e| format!("Failed to transcribe: {}", e))?;
-
-    let mut segments = extract_segments_with_words(&state)?;
-
-    // Clean up transcript with LLM if API key provided
-    if let Some(api_key) = llm_api_key {
-        eprintln!("🧹 Cleaning transcript with LLM...");
-        match crate::llm::clean_transcript_with_llm(&segments, api_key).await {
-            Ok(cleaned_segments) => {
-                eprintln!("✨ LLM cleanup successful");
-                segments = cleaned_segments;
+        let mut checkpoint = match (&self.cache, &cache_key) {
+            (Some(cache), Some(key)) if options.force => {
+                cache.remove_checkpoint(key);
+                TranscriptCheckpoint::default()
+            }
+            (Some(cache), Some(key)) => cache.load_checkpoint(key).unwrap_or_default(),
+            _ => TranscriptCheckpoint::default(),
+        };
+        if checkpoint.transcribed_until > 0.0 {
+            eprintln!("⏩ Resuming transcription at {:.1}s", checkpoint.transcribed_until);
+        }
+        if checkpoint.language.is_none() {
+            checkpoint.language = options.language.clone();
+        }
+
+        let started = Instant::now();
+        let started_progress = progress_at(checkpoint.transcribed_until, duration_seconds);
+
+        on_event(TranscribeEvent::Progress {
+            progress: started_progress,
+            eta_seconds: None,
+        })?;
+
+        while checkpoint.transcribed_until < duration_seconds {
+            let owned_from = checkpoint.transcribed_until;
+            let (offset, read_seconds) = chunk_read(owned_from, duration_seconds);
+            let mut samples = decode_audio(input_path, offset, read_seconds, SAMPLE_RATE, 1, cancel)?;
+            let decoded_until = offset + samples.len() as f64 / SAMPLE_RATE as f64;
+
+            // The probed duration may be a little off, so also trust the audio running out
+            let is_last = read_seconds.is_none_or(|s| samples.len() < (s * SAMPLE_RATE as f64) as usize);
+            let owned_to = if is_last {
+                f64::INFINITY
+            } else {
+                cut_chunk(&mut samples, SAMPLE_RATE, owned_from, offset)
+            };
+
+            eprintln!(
+                "🎙️ Transcribing {:.1}s-{:.1}s of {:.1}s",
+                owned_from,
+                owned_to.min(decoded_until),
+                duration_seconds
+            );
+
+            let window = ChunkWindow {
+                offset,
+                owned_from,
+                owned_to,
+                progress_from: progress_at(owned_from, duration_seconds),
+                progress_to: progress_at(owned_to, duration_seconds),
+                first_segment_id: checkpoint.segments.len(),
+            };
+            let callbacks = DecodeCallbacks::new(cancel, &on_event, window, started, started_progress);
+            let settings = DecodeSettings {
+                language: checkpoint.language.as_deref(),
+                prompt: prompt.as_deref(),
+                params: &options.params,
+                translate,
+            };
+            let (segments, language) = run_whisper(&context, &samples, &settings, &callbacks, cancel)?;
+            drop(samples);
+
+            // Detected in the first chunk, then kept so every chunk is in the same language
+            if checkpoint.language.is_none() {
+                eprintln!("🌐 Detected language: {}", language.as_deref().unwrap_or("unknown"));
+                checkpoint.language = language;
             }
-            Err(e) => {
-                eprintln!("⚠️ LLM cleanup failed, using original: {}", e);
-                // Continue with original segments if LLM fails
+
+            stitch(
+                &mut checkpoint.segments,
+                place_segments(segments, offset, owned_from, owned_to),
+            );
+
+            if is_last {
+                break;
+            }
+
+            checkpoint.transcribed_until = owned_to;
+            if let (Some(cache), Some(key)) = (&self.cache, &cache_key) {
+                cache.store_checkpoint(key, &checkpoint);
             }
         }
+
+        on_event(TranscribeEvent::Progress {
+            progress: 1.0,
+            eta_seconds: None,
+        })?;
+
+        let snapped = snap_to_glossary(&mut checkpoint.segments, &options.glossary);
+        if snapped > 0 {
+            eprintln!("📖 Corrected {} words to glossary terms", snapped);
+        }
+        renumber(&mut checkpoint.segments);
+        let transcript = Transcript {
+            segments: checkpoint.segments,
+            // `checkpoint.language` is the spoken language either way
+            language: if translate { Some("en".to_string()) } else { checkpoint.language },
+        };
+
+        if let (Some(cache), Some(key)) = (&self.cache, &cache_key) {
+            cache.store(key, &transcript_result(input_path, transcript.clone(), duration_seconds));
+            cache.remove_checkpoint(key);
+        }
+
+        Ok(transcript)
     }
 
-    // Flatten all words from all segments
-    let all_words: Vec<Word> = segments
-        .iter()
-        .flat_map(|s| s.words.clone())
-        .collect();
+    /// The loaded context for a model, loading it (and dropping any other) if needed
+    fn context(&self, model_path: &str) -> Result<Arc<WhisperContext>, String> {
+        let mut loaded = self.loaded.lock().unwrap_or_else(|e| e.into_inner());
+
+        if let Some((path, context)) = loaded.as_ref() {
+            if path == model_path {
+                return Ok(context.clone());
+            }
+        }
+
+        // Free the previous model before loading the next one
+        *loaded = None;
 
-    eprintln!("✅ Transcription complete: {} segments, {} words", segments.len(), all_words.len());
+        eprintln!("🧠 Loading Whisper model {}", model_path);
+        let context = WhisperContext::new_with_params(model_path, WhisperContextParameters::default())
+            .map_err(|e| format!("Failed to load Whisper model: {}", e))?;
+        let context = Arc::new(context);
+        *loaded = Some((model_path.to_string(), context.clone()));
 
-    // Clean up temp file
-    let _ = std::fs::remove_file(&pcm_path);
+        Ok(context)
+    }
+}
 
-    Ok(TranscriptResult {
-        segments,
-        words: all_words,
+/// Transcribe video and return TranscriptResult with word-level timestamps
+/// This is used by the text-based editor flow
+pub fn transcribe_video_for_editor(
+    transcriber: &Transcriber,
+    input_path: &str,
+    options: &TranscribeOptions,
+    llm: Option<&LlmProviderConfig>,
+    templates: &PromptTemplates,
+    cancel: &CancelToken,
+    progress_callback: impl Fn(PipelineEvent) -> Result<(), String>,
+) -> Result<TranscriptResult, String> {
+    let media = probe_media(input_path)?;
+    media.validate_for_transcription()?;
+    let duration_seconds = media.duration().unwrap_or_default();
+
+    eprintln!("🎬 Transcribing video for editor: {}", input_path);
+    eprintln!("📏 Video duration: {:.2}s", duration_seconds);
+
+    progress_callback(PipelineEvent::StageStarted {
+        stage: "transcribe".to_string(),
+    })?;
+
+    // The cache holds raw Whisper output; LLM cleanup below always runs fresh
+    let mut transcript = transcriber.transcribe(input_path, options, cancel, |event| match event {
+        TranscribeEvent::Progress { progress, eta_seconds } => progress_callback(PipelineEvent::StageProgress {
+            stage: "transcribe".to_string(),
+            progress,
+            eta_seconds,
+        }),
+        TranscribeEvent::Segments(_) => Ok(()),
+    })?;
+
+    progress_callback(PipelineEvent::StageCompleted {
+        stage: "transcribe".to_string(),
+    })?;
+
+    // Clean up transcript with LLM if a provider is configured
+    let correction = match llm {
+        Some(llm) => {
+            cancel.check()?;
+            pipeline::block_on(pipeline::run_cleanup(
+                &mut transcript,
+                llm,
+                templates,
+                &options.glossary,
+                cancel,
+                &progress_callback,
+            ))??
+        }
+        None => None,
+    };
+
+    let mut result = transcript_result(input_path, transcript, duration_seconds);
+    result.correction = correction;
+
+    if options.task == TranscriptionTask::Translate {
+        cancel.check()?;
+        eprintln!("🌍 Translating to English...");
+        result.translation = Some(transcriber.translate(
+            input_path,
+            &options.for_translation(result.language.as_deref()),
+            cancel,
+            |_| Ok(()),
+        )?);
+    }
+
+    eprintln!("✅ Transcription complete: {} segments, {} words", result.segments.len(), result.words.len());
+
+    Ok(result)
+}
+
+/// Editor-facing result, with the words of all segments flattened
+fn transcript_result(input_path: &str, transcript: Transcript, duration_seconds: f64) -> TranscriptResult {
+    let words: Vec<Word> = transcript.segments.iter().flat_map(|s| s.words.clone()).collect();
+
+    TranscriptResult {
+        segments: transcript.segments,
+        words,
         duration_seconds,
         input_path: input_path.to_string(),
-    })
+        language: transcript.language,
+        translation: None,
+        correction: None,
+    }
 }
@@ -0,0 +1,365 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::models::{Segment, TranscriptResult};
+use crate::whisper_models::file_sha256;
+
+/// Bump when the transcript format or word extraction changes, so stale entries stop matching
+const CACHE_VERSION: u32 = 4;
+
+/// Bytes hashed from the start, middle and end of a recording.
+/// Hashing a whole multi-GB recording would take longer than a cache hit is meant to, so its size and
+/// modification time go into the key too.
+const HASH_SAMPLE_BYTES: u64 = 4 * 1024 * 1024;
+
+/// Chunks of a long transcription finished so far, so an interrupted job resumes where it stopped
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct TranscriptCheckpoint {
+    /// Recording time (seconds) up to which `segments` are final
+    pub transcribed_until: f64,
+    pub segments: Vec<Segment>,
+    /// Detected in the first chunk and used for the rest
+    pub language: Option<String>,
+}
+
+/// A model file as last hashed: its size and modification time
+type FileStamp = (u64, Option<SystemTime>);
+
+/// Whisper results stored as `TranscriptResult` JSON, one file per
+/// (media content, model, language, transcription params)
+#[derive(Debug, Clone)]
+pub struct TranscriptCache {
+    dir: PathBuf,
+    /// Full SHA-256 of each model file, rehashed only when the file changes
+    model_hashes: Arc<Mutex<HashMap<PathBuf, (FileStamp, String)>>>,
+}
+
+impl TranscriptCache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        TranscriptCache {
+            dir: dir.into(),
+            model_hashes: Arc::default(),
+        }
+    }
+
+    /// Cache key for a transcription. None (with a warning) if the files can't be read,
+    /// in which case the caller just transcribes without caching.
+    pub fn key_for(&self, input_path: &str, model_path: &str, language: Option<&str>, params: &str) -> Option<String> {
+        self.versioned_key(CACHE_VERSION, input_path, model_path, language, params)
+    }
+
+    fn versioned_key(
+        &self,
+        version: u32,
+        input_path: &str,
+        model_path: &str,
+        language: Option<&str>,
+        params: &str,
+    ) -> Option<String> {
+        let hashes = media_hash(Path::new(input_path))
+            .and_then(|media| Ok((media, self.model_hash(Path::new(model_path))?)));
+
+        match hashes {
+            Ok((media_hash, model_hash)) => {
+                let mut hasher = Sha256::new();
+                hasher.update(format!(
+                    "v{}\nmedia={}\nmodel={}\nlanguage={}\nparams={}\n",
+                    version,
+                    media_hash,
+                    model_hash,
+                    language.unwrap_or("auto"),
+                    params
+                ));
+                Some(hex(&hasher.finalize()))
+            }
+            Err(e) => {
+                eprintln!("⚠️ Transcript cache disabled for {}: {}", input_path, e);
+                None
+            }
+        }
+    }
+
+    /// Cached transcript for a key, with `input_path` updated in case the file was moved
+    pub fn load(&self, key: &str, input_path: &str) -> Option<TranscriptResult> {
+        let path = self.entry_path(key);
+        let contents = fs::read(&path).ok()?;
+
+        match serde_json::from_slice::<TranscriptResult>(&contents) {
+            Ok(mut result) => {
+                eprintln!("⚡ Using cached transcript for {}", input_path);
+                result.input_path = input_path.to_string();
+                Some(result)
+            }
+            Err(e) => {
+                eprintln!("⚠️ Ignoring unreadable cache entry {}: {}", path.display(), e);
+                let _ = fs::remove_file(&path);
+                None
+            }
+        }
+    }
+
+    /// Save a transcript; failures are only logged since the cache is an optimization
+    pub fn store(&self, key: &str, result: &TranscriptResult) {
+        if let Err(e) = self.try_store(key, result) {
+            eprintln!("⚠️ Failed to cache transcript: {}", e);
+        }
+    }
+
+    fn try_store(&self, key: &str, result: &TranscriptResult) -> Result<(), String> {
+        let json = serde_json::to_vec(result).map_err(|e| format!("Failed to serialize transcript: {}", e))?;
+        let path = self.entry_path(key);
+        self.write_atomically(&path, &json)?;
+
+        eprintln!("💾 Cached transcript as {}", path.display());
+        Ok(())
+    }
+
+    /// Write then rename, so a crash never leaves a half-written entry behind
+    fn write_atomically(&self, path: &Path, contents: &[u8]) -> Result<(), String> {
+        fs::create_dir_all(&self.dir)
+            .map_err(|e| format!("Failed to create {}: {}", self.dir.display(), e))?;
+
+        let temp_path = path.with_extension("json.tmp");
+        fs::write(&temp_path, contents).map_err(|e| format!("Failed to write {}: {}", temp_path.display(), e))?;
+        fs::rename(&temp_path, path).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+    }
+
+    /// Checkpoint left by an unfinished transcription with this key
+    pub fn load_checkpoint(&self, key: &str) -> Option<TranscriptCheckpoint> {
+        let path = self.checkpoint_path(key);
+        let contents = fs::read(&path).ok()?;
+
+        match serde_json::from_slice::<TranscriptCheckpoint>(&contents) {
+            Ok(checkpoint) => Some(checkpoint),
+            Err(e) => {
+                eprintln!("⚠️ Ignoring unreadable checkpoint {}: {}", path.display(), e);
+                let _ = fs::remove_file(&path);
+                None
+            }
+        }
+    }
+
+    /// Save progress after a chunk; like `store`, failures are only logged
+    pub fn store_checkpoint(&self, key: &str, checkpoint: &TranscriptCheckpoint) {
+        let outcome = serde_json::to_vec(checkpoint)
+            .map_err(|e| format!("Failed to serialize checkpoint: {}", e))
+            .and_then(|json| self.write_atomically(&self.checkpoint_path(key), &json));
+
+        if let Err(e) = outcome {
+            eprintln!("⚠️ Failed to save transcription checkpoint: {}", e);
+        }
+    }
+
+    pub fn remove_checkpoint(&self, key: &str) {
+        let _ = fs::remove_file(self.checkpoint_path(key));
+    }
+
+    /// Delete every cached transcript and checkpoint; returns how many were removed
+    pub fn clear(&self) -> Result<usize, String> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(format!("Failed to read {}: {}", self.dir.display(), e)),
+        };
+
+        let mut removed = 0;
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.extension().is_some_and(|ext| ext == "json" || ext == "tmp") {
+                fs::remove_file(&path).map_err(|e| format!("Failed to remove {}: {}", path.display(), e))?;
+                removed += 1;
+            }
+        }
+
+        eprintln!("🧹 Cleared {} cached transcripts", removed);
+        Ok(removed)
+    }
+
+    /// A model is hashed whole, since a re-quantized model can have the same size; the hash is reused
+    /// until the file's size or modification time changes
+    fn model_hash(&self, path: &Path) -> Result<String, String> {
+        let stamp = file_stamp(path)?;
+        if let Some((known_stamp, sha256)) = self.model_hashes.lock().unwrap_or_else(|e| e.into_inner()).get(path) {
+            if *known_stamp == stamp {
+                return Ok(sha256.clone());
+            }
+        }
+
+        let sha256 = file_sha256(path)?;
+        self.model_hashes
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(path.to_path_buf(), (stamp, sha256.clone()));
+        Ok(sha256)
+    }
+
+    fn entry_path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", key))
+    }
+
+    fn checkpoint_path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.partial.json", key))
+    }
+}
+
+fn file_stamp(path: &Path) -> Result<FileStamp, String> {
+    let metadata = fs::metadata(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    Ok((metadata.len(), metadata.modified().ok()))
+}
+
+/// SHA-256 over the file's size, modification time and samples from its start, middle and end
+fn media_hash(path: &Path) -> Result<String, String> {
+    let (size, modified) = file_stamp(path)?;
+    let mut file = File::open(path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+
+    let mut hasher = Sha256::new();
+    hasher.update(size.to_le_bytes());
+    let modified_nanos = modified
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |since| since.as_nanos());
+    hasher.update(modified_nanos.to_le_bytes());
+
+    // Small files are hashed whole
+    let samples = if size <= 3 * HASH_SAMPLE_BYTES {
+        vec![(0, size)]
+    } else {
+        vec![
+            (0, HASH_SAMPLE_BYTES),
+            (size / 2 - HASH_SAMPLE_BYTES / 2, HASH_SAMPLE_BYTES),
+            (size - HASH_SAMPLE_BYTES, HASH_SAMPLE_BYTES),
+        ]
+    };
+
+    let mut buffer = Vec::new();
+    for (offset, length) in samples {
+        buffer.clear();
+        file.seek(SeekFrom::Start(offset))
+            .and_then(|_| (&mut file).take(length).read_to_end(&mut buffer))
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        hasher.update(&buffer);
+    }
+
+    Ok(hex(&hasher.finalize()))
+}
+
+pub(crate) fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scratch::ScratchDir;
+    use std::time::Duration;
+
+    struct Fixture {
+        _dir: ScratchDir,
+        cache: TranscriptCache,
+        media: String,
+        model: String,
+    }
+
+    /// A cache with a recording big enough to be sampled and a small model
+    fn fixture() -> Fixture {
+        let dir = ScratchDir::new("cache-test").unwrap();
+        let media = dir.file("video.mp4");
+        fs::write(&media, vec![7u8; (4 * HASH_SAMPLE_BYTES) as usize]).unwrap();
+        let model = dir.file("ggml-tiny.bin");
+        fs::write(&model, b"weights").unwrap();
+        Fixture {
+            cache: TranscriptCache::new(dir.file("transcripts")),
+            _dir: dir,
+            media,
+            model,
+        }
+    }
+
+    fn transcript(input_path: &str) -> TranscriptResult {
+        TranscriptResult {
+            segments: Vec::new(),
+            words: Vec::new(),
+            duration_seconds: 12.0,
+            input_path: input_path.to_string(),
+            language: Some("es".to_string()),
+            translation: None,
+            correction: None,
+        }
+    }
+
+    /// Overwrite `path` with `contents`, dated an hour from now so the change is always visible
+    fn rewrite(path: &str, contents: &[u8]) {
+        fs::write(path, contents).unwrap();
+        File::options()
+            .write(true)
+            .open(path)
+            .and_then(|file| file.set_modified(SystemTime::now() + Duration::from_secs(3600)))
+            .unwrap();
+    }
+
+    #[test]
+    fn same_inputs_hit_the_cache() {
+        let f = fixture();
+        let key = f.cache.key_for(&f.media, &f.model, Some("es"), "greedy").unwrap();
+        f.cache.store(&key, &transcript(&f.media));
+
+        let again = f.cache.key_for(&f.media, &f.model, Some("es"), "greedy").unwrap();
+        assert_eq!(again, key);
+        let cached = f.cache.load(&again, "/moved/video.mp4").unwrap();
+        assert_eq!(cached.duration_seconds, 12.0);
+        assert_eq!(cached.input_path, "/moved/video.mp4");
+    }
+
+    #[test]
+    fn changed_params_or_language_miss() {
+        let f = fixture();
+        let key = f.cache.key_for(&f.media, &f.model, Some("es"), "greedy").unwrap();
+        f.cache.store(&key, &transcript(&f.media));
+
+        let beam = f.cache.key_for(&f.media, &f.model, Some("es"), "beam").unwrap();
+        let english = f.cache.key_for(&f.media, &f.model, Some("en"), "greedy").unwrap();
+        assert_ne!(beam, key);
+        assert_ne!(english, key);
+        assert!(f.cache.load(&beam, &f.media).is_none());
+    }
+
+    #[test]
+    fn a_new_cache_version_misses() {
+        let f = fixture();
+        let current = f.cache.versioned_key(CACHE_VERSION, &f.media, &f.model, None, "greedy").unwrap();
+        let next = f.cache.versioned_key(CACHE_VERSION + 1, &f.media, &f.model, None, "greedy").unwrap();
+        assert_ne!(current, next);
+    }
+
+    #[test]
+    fn edits_outside_the_samples_miss() {
+        let f = fixture();
+        let key = f.cache.key_for(&f.media, &f.model, None, "greedy").unwrap();
+
+        // Between the start and middle samples, and the same size
+        let mut contents = fs::read(&f.media).unwrap();
+        contents[(HASH_SAMPLE_BYTES + 10) as usize] = 8;
+        rewrite(&f.media, &contents);
+
+        assert_ne!(f.cache.key_for(&f.media, &f.model, None, "greedy").unwrap(), key);
+    }
+
+    #[test]
+    fn a_same_size_model_misses() {
+        let f = fixture();
+        let key = f.cache.key_for(&f.media, &f.model, None, "greedy").unwrap();
+
+        rewrite(&f.model, b"weightz");
+        assert_ne!(f.cache.key_for(&f.media, &f.model, None, "greedy").unwrap(), key);
+        assert_eq!(
+            f.cache.model_hash(Path::new(&f.model)).unwrap(),
+            file_sha256(Path::new(&f.model)).unwrap()
+        );
+    }
+}
@@ -0,0 +1,429 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+use sha2::{Digest, Sha256};
+
+use crate::models::{ModelVerification, WhisperModelInfo};
+use crate::transcript_cache::hex;
+
+/// Used when a job doesn't pick a model
+pub const DEFAULT_MODEL: &str = "base";
+
+/// First four bytes of every whisper.cpp GGML model ("ggml" as a little-endian u32)
+const GGML_MAGIC: u32 = 0x6767_6d6c;
+
+/// Optional `{"ggml-base.bin": "<sha256>", ...}` in the models directory,
+/// for checksums of models not in `KNOWN_MODELS` or to override them
+const CHECKSUMS_FILE: &str = "checksums.json";
+
+/// Where models were looked up before the models directory existed; still honored for the default model
+const LEGACY_MODEL_DIRS: [&str; 3] = ["models", "../models", "src-tauri/models"];
+
+const MB: u64 = 1024 * 1024;
+
+#[derive(Debug)]
+pub struct KnownModel {
+    pub name: &'static str,
+    /// Approximate download size
+    pub size_bytes: u64,
+    pub multilingual: bool,
+    pub quantized: bool,
+    /// SHA-256 of the file published in the whisper.cpp model repository, where we have it
+    pub sha256: Option<&'static str>,
+}
+
+const fn known(
+    name: &'static str,
+    size_mb: u64,
+    multilingual: bool,
+    quantized: bool,
+    sha256: Option<&'static str>,
+) -> KnownModel {
+    KnownModel {
+        name,
+        size_bytes: size_mb * MB,
+        multilingual,
+        quantized,
+        sha256,
+    }
+}
+
+/// GGML models from the whisper.cpp model repository, smallest first. A checksum here is the SHA-256 of
+/// the repository's file (its Git LFS pointer's `oid`); models without one verify as `Unknown` unless
+/// `checksums.json` lists them.
+pub const KNOWN_MODELS: &[KnownModel] = &[
+    known("tiny", 75, true, false, None),
+    known("tiny.en", 75, false, false, None),
+    known("tiny-q5_1", 31, true, true, None),
+    known("base", 142, true, false, None),
+    known("base.en", 142, false, false, None),
+    known("base-q5_1", 57, true, true, None),
+    known("small", 466, true, false, None),
+    known("small.en", 466, false, false, None),
+    known("small-q5_1", 181, true, true, None),
+    known("medium", 1500, true, false, None),
+    known("medium.en", 1500, false, false, None),
+    known("medium-q5_0", 514, true, true, None),
+    known("large-v3", 2900, true, false, None),
+    known("large-v3-q5_0", 1080, true, true, None),
+    known("large-v3-turbo", 1500, true, false, None),
+    known("large-v3-turbo-q5_0", 547, true, true, None),
+];
+
+/// GGML Whisper models stored as `ggml-<name>.bin` in one directory
+#[derive(Debug, Clone)]
+pub struct ModelManager {
+    dir: PathBuf,
+    /// Models offered for download and their checksums; `KNOWN_MODELS` outside of tests
+    registry: &'static [KnownModel],
+}
+
+impl ModelManager {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        ModelManager {
+            dir: dir.into(),
+            registry: KNOWN_MODELS,
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Every registry model (installed or not) followed by custom models found in the directory
+    pub fn list(&self) -> Vec<WhisperModelInfo> {
+        let mut models: Vec<WhisperModelInfo> = self.registry.iter().map(|known| self.info(known.name)).collect();
+
+        let mut custom: Vec<WhisperModelInfo> = fs::read_dir(&self.dir)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .filter_map(|entry| model_name(&entry.file_name().to_string_lossy()))
+            .filter(|name| self.find_known(name).is_none())
+            .map(|name| self.info(&name))
+            .collect();
+        custom.sort_by(|a, b| a.name.cmp(&b.name));
+
+        models.extend(custom);
+        models
+    }
+
+    /// Path of the model a job should use. None means the default model, which may also
+    /// come from the legacy `models/` directories; a named model must be installed.
+    pub fn resolve(&self, model: Option<&str>) -> Result<String, String> {
+        let name = model.unwrap_or(DEFAULT_MODEL);
+        validate_name(name)?;
+
+        let path = self.model_path(name);
+        if path.is_file() {
+            eprintln!("✅ Using Whisper model {} at {}", name, path.display());
+            return Ok(path.to_string_lossy().into_owned());
+        }
+
+        if model.is_none() {
+            for dir in LEGACY_MODEL_DIRS {
+                let legacy = Path::new(dir).join(file_name(name));
+                if legacy.is_file() {
+                    eprintln!("✅ Using Whisper model at legacy location {}", legacy.display());
+                    return Ok(legacy.to_string_lossy().into_owned());
+                }
+            }
+        }
+
+        Err(format!(
+            "Whisper model \"{}\" is not installed. Import {} or place it in {}",
+            name,
+            file_name(name),
+            self.dir.display()
+        ))
+    }
+
+    /// Hash an installed model and compare it with its expected checksum
+    pub fn verify(&self, name: &str) -> Result<ModelVerification, String> {
+        validate_name(name)?;
+        let path = self.model_path(name);
+        if !path.is_file() {
+            return Err(format!("Whisper model \"{}\" is not installed", name));
+        }
+
+        eprintln!("🔍 Verifying Whisper model {}...", name);
+        let sha256 = file_sha256(&path)?;
+        let verification = self.check(name, sha256);
+        log_verification(name, &verification);
+        Ok(verification)
+    }
+
+    /// Copy a model file into the directory, named after the source file
+    /// (`ggml-small.bin` → "small"). Registry models must match their checksum.
+    pub fn import(&self, source_path: &str) -> Result<WhisperModelInfo, String> {
+        let source = Path::new(source_path);
+        let source_name = source
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .ok_or_else(|| format!("Not a file: {}", source_path))?;
+        let name = model_name(&source_name).unwrap_or_else(|| {
+            let stem = source_name.rsplit_once('.').map_or(source_name.as_str(), |(stem, _)| stem);
+            stem.trim_start_matches("ggml-").to_string()
+        });
+        validate_name(&name)?;
+
+        check_ggml_magic(source)?;
+
+        let destination = self.model_path(&name);
+        if destination.exists() {
+            return Err(format!("Whisper model \"{}\" is already installed", name));
+        }
+        fs::create_dir_all(&self.dir).map_err(|e| format!("Failed to create {}: {}", self.dir.display(), e))?;
+
+        eprintln!("📥 Importing Whisper model {} from {}", name, source_path);
+
+        // Copy under a temporary name and hash on the way, so a bad or partial copy never shows up as installed
+        let temp_path = destination.with_extension("bin.tmp");
+        let sha256 = match copy_and_hash(source, &temp_path) {
+            Ok(sha256) => sha256,
+            Err(e) => {
+                let _ = fs::remove_file(&temp_path);
+                return Err(e);
+            }
+        };
+
+        let verification = self.check(&name, sha256);
+        log_verification(&name, &verification);
+        if let ModelVerification::Mismatch { expected, actual } = verification {
+            let _ = fs::remove_file(&temp_path);
+            return Err(format!(
+                "{} is not a valid {} model: SHA-256 {} does not match {}",
+                source_path,
+                file_name(&name),
+                actual,
+                expected
+            ));
+        }
+
+        fs::rename(&temp_path, &destination)
+            .map_err(|e| format!("Failed to write {}: {}", destination.display(), e))?;
+
+        Ok(self.info(&name))
+    }
+
+    fn info(&self, name: &str) -> WhisperModelInfo {
+        let path = self.model_path(name);
+        let known = self.find_known(name);
+        let installed_size = fs::metadata(&path).ok().filter(|m| m.is_file()).map(|m| m.len());
+
+        WhisperModelInfo {
+            name: name.to_string(),
+            file_name: file_name(name),
+            path: path.to_string_lossy().into_owned(),
+            installed: installed_size.is_some(),
+            size_bytes: installed_size.or(known.map(|k| k.size_bytes)).unwrap_or(0),
+            known: known.is_some(),
+            quantized: known.map_or(name.contains("-q"), |k| k.quantized),
+            multilingual: known.map_or(!name.ends_with(".en"), |k| k.multilingual),
+        }
+    }
+
+    fn check(&self, name: &str, sha256: String) -> ModelVerification {
+        match self.expected_sha256(name) {
+            Some(expected) if expected.eq_ignore_ascii_case(&sha256) => ModelVerification::Verified { sha256 },
+            Some(expected) => ModelVerification::Mismatch { expected, actual: sha256 },
+            None => ModelVerification::Unknown { sha256 },
+        }
+    }
+
+    /// `checksums.json` takes precedence over the built-in registry
+    fn expected_sha256(&self, name: &str) -> Option<String> {
+        let overrides = fs::read(self.dir.join(CHECKSUMS_FILE))
+            .ok()
+            .and_then(|contents| match serde_json::from_slice::<HashMap<String, String>>(&contents) {
+                Ok(checksums) => Some(checksums),
+                Err(e) => {
+                    eprintln!("⚠️ Ignoring unreadable {}: {}", CHECKSUMS_FILE, e);
+                    None
+                }
+            });
+
+        overrides
+            .and_then(|mut checksums| checksums.remove(&file_name(name)))
+            .or_else(|| self.find_known(name).and_then(|k| k.sha256).map(str::to_string))
+    }
+
+    fn model_path(&self, name: &str) -> PathBuf {
+        self.dir.join(file_name(name))
+    }
+
+    fn find_known(&self, name: &str) -> Option<&'static KnownModel> {
+        self.registry.iter().find(|known| known.name == name)
+    }
+}
+
+fn file_name(name: &str) -> String {
+    format!("ggml-{}.bin", name)
+}
+
+/// "ggml-base.en.bin" → "base.en"
+fn model_name(file_name: &str) -> Option<String> {
+    file_name
+        .strip_prefix("ggml-")?
+        .strip_suffix(".bin")
+        .filter(|name| !name.is_empty())
+        .map(str::to_string)
+}
+
+/// Names become file names, so keep them to a safe character set
+fn validate_name(name: &str) -> Result<(), String> {
+    let valid = !name.is_empty()
+        && !name.starts_with('.')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+
+    if valid {
+        Ok(())
+    } else {
+        Err(format!("Invalid Whisper model name: \"{}\"", name))
+    }
+}
+
+fn check_ggml_magic(path: &Path) -> Result<(), String> {
+    let mut magic = [0u8; 4];
+    File::open(path)
+        .and_then(|mut file| file.read_exact(&mut magic))
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+
+    if u32::from_le_bytes(magic) == GGML_MAGIC {
+        Ok(())
+    } else {
+        Err(format!("{} is not a GGML Whisper model", path.display()))
+    }
+}
+
+pub(crate) fn file_sha256(path: &Path) -> Result<String, String> {
+    let mut file = File::open(path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file, &mut hasher).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    Ok(hex(&hasher.finalize()))
+}
+
+fn copy_and_hash(source: &Path, destination: &Path) -> Result<String, String> {
+    let mut reader = File::open(source).map_err(|e| format!("Failed to open {}: {}", source.display(), e))?;
+    let mut writer =
+        File::create(destination).map_err(|e| format!("Failed to create {}: {}", destination.display(), e))?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0u8; MB as usize];
+
+    loop {
+        let read = reader
+            .read(&mut buffer)
+            .map_err(|e| format!("Failed to read {}: {}", source.display(), e))?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+        writer
+            .write_all(&buffer[..read])
+            .map_err(|e| format!("Failed to write {}: {}", destination.display(), e))?;
+    }
+
+    writer
+        .sync_all()
+        .map_err(|e| format!("Failed to write {}: {}", destination.display(), e))?;
+    Ok(hex(&hasher.finalize()))
+}
+
+fn log_verification(name: &str, verification: &ModelVerification) {
+    match verification {
+        ModelVerification::Verified { .. } => eprintln!("✅ Whisper model {} checksum verified", name),
+        ModelVerification::Mismatch { .. } => eprintln!("❌ Whisper model {} checksum mismatch", name),
+        ModelVerification::Unknown { sha256 } => {
+            eprintln!("⚠️ No known checksum for Whisper model {} (SHA-256 {})", name, sha256)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scratch::ScratchDir;
+
+    /// A file that passes the GGML magic check, with `body` after the magic
+    fn fake_model(path: &str, body: &[u8]) {
+        let mut contents = GGML_MAGIC.to_le_bytes().to_vec();
+        contents.extend_from_slice(body);
+        fs::write(path, contents).unwrap();
+    }
+
+    /// A model manager whose `checksums.json` expects `ggml-tiny.bin` to hold `body`
+    fn manager_expecting(dir: &ScratchDir, body: &[u8]) -> ModelManager {
+        let expected = dir.file("expected.bin");
+        fake_model(&expected, body);
+        let sha256 = file_sha256(Path::new(&expected)).unwrap();
+
+        let models = ModelManager::new(dir.file("models"));
+        fs::create_dir_all(models.dir()).unwrap();
+        let checksums = serde_json::json!({ "ggml-tiny.bin": sha256 });
+        fs::write(models.dir().join(CHECKSUMS_FILE), checksums.to_string()).unwrap();
+        models
+    }
+
+    /// SHA-256 of a `fake_model` holding "the real weights"
+    const REAL_WEIGHTS_SHA256: &str = "ac8e66f75a2a98fe199b9b0247c905691c7e1a63e9282075ff363383b4472f44";
+
+    const TEST_REGISTRY: &[KnownModel] = &[known("tiny", 1, true, false, Some(REAL_WEIGHTS_SHA256))];
+
+    #[test]
+    fn verify_checks_installed_models_against_the_registry() {
+        let dir = ScratchDir::new("models-test").unwrap();
+        let models = ModelManager {
+            dir: dir.file("models").into(),
+            registry: TEST_REGISTRY,
+        };
+        fs::create_dir_all(models.dir()).unwrap();
+        assert!(!models.dir().join(CHECKSUMS_FILE).exists());
+
+        fake_model(&models.model_path("tiny").to_string_lossy(), b"the real weights");
+        assert!(matches!(models.verify("tiny").unwrap(), ModelVerification::Verified { .. }));
+
+        fake_model(&models.model_path("tiny").to_string_lossy(), b"the real weightz");
+        match models.verify("tiny").unwrap() {
+            ModelVerification::Mismatch { expected, .. } => assert_eq!(expected, REAL_WEIGHTS_SHA256),
+            other => panic!("corrupted model verified as {:?}", other),
+        }
+    }
+
+    #[test]
+    fn import_rejects_a_tampered_model() {
+        let dir = ScratchDir::new("models-test").unwrap();
+        let models = manager_expecting(&dir, b"the real weights");
+        let source = dir.file("ggml-tiny.bin");
+        fake_model(&source, b"the real weightz");
+
+        let error = models.import(&source).unwrap_err();
+        assert!(error.contains("does not match"), "{}", error);
+        assert!(!models.model_path("tiny").exists());
+        assert!(!models.model_path("tiny").with_extension("bin.tmp").exists());
+    }
+
+    #[test]
+    fn import_rejects_a_truncated_model() {
+        let dir = ScratchDir::new("models-test").unwrap();
+        let models = manager_expecting(&dir, b"the real weights");
+        let source = dir.file("ggml-tiny.bin");
+        fake_model(&source, b"the real");
+
+        assert!(models.import(&source).is_err());
+        assert!(!models.model_path("tiny").exists());
+    }
+
+    #[test]
+    fn import_accepts_a_matching_model() {
+        let dir = ScratchDir::new("models-test").unwrap();
+        let models = manager_expecting(&dir, b"the real weights");
+        let source = dir.file("ggml-tiny.bin");
+        fake_model(&source, b"the real weights");
+
+        let info = models.import(&source).unwrap();
+        assert!(info.installed);
+        assert!(matches!(models.verify("tiny").unwrap(), ModelVerification::Verified { .. }));
+    }
+}
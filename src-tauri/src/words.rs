@@ -0,0 +1,310 @@
+//! Grouping of Whisper's sub-word tokens into the words shown in the editor.
+//!
+//! Whisper's BPE tokens carry a leading space when they start a new word ("▁dis", "pos", "itivo"),
+//! and a multi-byte character can be split across two tokens, so grouping works on raw bytes.
+
+use crate::models::{Confidence, Word};
+
+/// Words whose mean token probability is below this are worth a second look
+pub const LOW_CONFIDENCE_THRESHOLD: f64 = 0.6;
+
+/// Whisper's segment and token timestamps count centiseconds (100ths of a second), not milliseconds
+pub fn timestamp_seconds(centiseconds: i64) -> f64 {
+    centiseconds as f64 / 100.0
+}
+
+/// Timestamp and control tokens come after end-of-text (`eot`) in the vocabulary
+pub fn is_text_token(id: i32, eot: i32) -> bool {
+    id < eot
+}
+
+/// A token's span in seconds from its centisecond timestamps; None when Whisper gave it no usable one
+pub fn token_timing(t0: i64, t1: i64) -> Option<(f64, f64)> {
+    let (start, end) = (timestamp_seconds(t0), timestamp_seconds(t1));
+    (start >= 0.0 && end > start).then_some((start, end))
+}
+
+/// One text token of a segment, with its timing in seconds if Whisper gave it a usable one
+#[derive(Debug, Clone)]
+pub struct TextToken {
+    pub bytes: Vec<u8>,
+    pub timing: Option<(f64, f64)>,
+    pub probability: f64,
+}
+
+/// A whole word: its tokens' text joined, spanning from the first to the last timed token
+#[derive(Debug, Clone, PartialEq)]
+pub struct GroupedWord {
+    pub text: String,
+    pub start: f64,
+    pub end: f64,
+    /// Over the word's own tokens; attached punctuation doesn't count
+    pub confidence: Option<Confidence>,
+}
+
+/// Tokens of one word while grouping
+#[derive(Default)]
+struct Pending {
+    bytes: Vec<u8>,
+    timing: Option<(f64, f64)>,
+    probabilities: Vec<f64>,
+}
+
+impl Pending {
+    fn push(&mut self, token: &TextToken) {
+        self.bytes.extend_from_slice(&token.bytes);
+        self.extend_timing(token.timing);
+
+        let text = String::from_utf8_lossy(&token.bytes);
+        if !text.trim().chars().all(is_punctuation) {
+            self.probabilities.push(token.probability);
+        }
+    }
+
+    fn append(&mut self, other: Pending) {
+        self.bytes.extend(other.bytes);
+        self.extend_timing(other.timing);
+        self.probabilities.extend(other.probabilities);
+    }
+
+    fn extend_timing(&mut self, timing: Option<(f64, f64)>) {
+        if let Some((start, end)) = timing {
+            self.timing = Some(match self.timing {
+                Some((first, last)) => (first.min(start), last.max(end)),
+                None => (start, end),
+            });
+        }
+    }
+
+    fn text(&self) -> String {
+        String::from_utf8_lossy(&self.bytes).trim().to_string()
+    }
+}
+
+/// Merge a segment's tokens into words. A token starting with a space (or with a character
+/// of a script written without spaces, like Chinese or Japanese) starts a new word.
+/// Punctuation sticks to the word it belongs to: opening marks (¿, «, ") to the next word,
+/// everything else to the previous one. Untimed words get a zero-length span where the previous word ends.
+pub fn group_tokens(tokens: &[TextToken], segment_start: f64) -> Vec<GroupedWord> {
+    let mut words: Vec<Pending> = Vec::new();
+    let mut current = Pending::default();
+    // Opening punctuation waiting for the word it belongs to
+    let mut prefix = Pending::default();
+
+    for token in tokens {
+        let text = String::from_utf8_lossy(&token.bytes);
+        let trimmed = text.trim();
+        if trimmed.is_empty() {
+            current.push(token);
+            continue;
+        }
+
+        let starts_word = text.starts_with(char::is_whitespace) || starts_unspaced_script(trimmed);
+
+        if trimmed.chars().all(is_punctuation) {
+            if starts_word && trimmed.chars().all(is_opening_punctuation) {
+                flush(&mut words, &mut current);
+                prefix.push(token);
+            } else {
+                current.push(token);
+            }
+            continue;
+        }
+
+        if starts_word {
+            flush(&mut words, &mut current);
+        }
+        current.append(std::mem::take(&mut prefix));
+        current.push(token);
+    }
+
+    // Opening punctuation at the very end has no word to attach to
+    current.append(prefix);
+    flush(&mut words, &mut current);
+
+    let mut previous_end = segment_start;
+    words
+        .iter()
+        .filter_map(|word| {
+            let text = word.text();
+            if text.is_empty() {
+                return None;
+            }
+            let (start, end) = word.timing.unwrap_or((previous_end, previous_end));
+            previous_end = end;
+            Some(GroupedWord {
+                text,
+                start,
+                end,
+                confidence: confidence(&word.probabilities),
+            })
+        })
+        .collect()
+}
+
+/// Min and mean of token probabilities; None without any tokens
+pub fn confidence(probabilities: &[f64]) -> Option<Confidence> {
+    if probabilities.is_empty() {
+        return None;
+    }
+
+    Some(Confidence {
+        min: probabilities.iter().copied().fold(f64::INFINITY, f64::min),
+        mean: probabilities.iter().sum::<f64>() / probabilities.len() as f64,
+    })
+}
+
+/// Words Whisper was unsure about (mean token probability below `threshold`), for review in the editor.
+/// Words without a confidence are never flagged.
+pub fn low_confidence_words(words: &[Word], threshold: f64) -> Vec<&Word> {
+    words
+        .iter()
+        .filter(|word| word.confidence.is_some_and(|c| c.mean < threshold))
+        .collect()
+}
+
+fn flush(words: &mut Vec<Pending>, current: &mut Pending) {
+    if !current.bytes.is_empty() {
+        words.push(std::mem::take(current));
+    }
+}
+
+fn is_punctuation(c: char) -> bool {
+    c.is_ascii_punctuation() || "¿¡«»“”‘’„…–—。、，．！？：；「」『』（）".contains(c)
+}
+
+fn is_opening_punctuation(c: char) -> bool {
+    "¿¡«“‘„\"'([{「『（".contains(c)
+}
+
+/// Whether text begins with a complete CJK ideograph or kana, where every token is its own word
+fn starts_unspaced_script(text: &str) -> bool {
+    text.chars().next().is_some_and(|c| {
+        matches!(c as u32,
+            0x3040..=0x30FF | 0x3400..=0x4DBF | 0x4E00..=0x9FFF | 0xF900..=0xFAFF | 0xFF66..=0xFF9F)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn token(text: &[u8], timing: Option<(f64, f64)>, probability: f64) -> TextToken {
+        TextToken {
+            bytes: text.to_vec(),
+            timing,
+            probability,
+        }
+    }
+
+    fn texts(words: &[GroupedWord]) -> Vec<&str> {
+        words.iter().map(|w| w.text.as_str()).collect()
+    }
+
+    fn word(text: &str, mean: Option<f64>) -> Word {
+        Word {
+            id: text.to_string(),
+            word: text.to_string(),
+            start: 0.0,
+            end: 0.0,
+            confidence: mean.map(|mean| Confidence { min: mean, mean }),
+        }
+    }
+
+    #[test]
+    fn a_leading_space_starts_a_word() {
+        let tokens = [
+            token(b" dis", Some((1.0, 1.2)), 0.9),
+            token(b"pos", Some((1.2, 1.4)), 0.5),
+            token(b"itivo", Some((1.4, 1.7)), 0.7),
+            token(b" nuevo", Some((1.8, 2.1)), 0.8),
+        ];
+        let words = group_tokens(&tokens, 1.0);
+
+        assert_eq!(texts(&words), vec!["dispositivo", "nuevo"]);
+        assert_eq!((words[0].start, words[0].end), (1.0, 1.7));
+        let confidence = words[0].confidence.unwrap();
+        assert_eq!(confidence.min, 0.5);
+        assert!((confidence.mean - 0.7).abs() < 1e-9);
+    }
+
+    #[test]
+    fn characters_split_across_tokens_are_joined() {
+        // "é" is 0xC3 0xA9 in UTF-8
+        let tokens = [
+            token(b" caf", Some((0.0, 0.2)), 0.9),
+            token(&[0xC3], None, 0.9),
+            token(&[0xA9], Some((0.2, 0.3)), 0.9),
+            token(" 你".as_bytes(), Some((0.4, 0.5)), 0.9),
+            token("好".as_bytes(), Some((0.5, 0.6)), 0.9),
+        ];
+        assert_eq!(texts(&group_tokens(&tokens, 0.0)), vec!["café", "你", "好"]);
+    }
+
+    #[test]
+    fn punctuation_sticks_to_its_word() {
+        let tokens = [
+            token(" ¿".as_bytes(), Some((0.0, 0.1)), 0.2),
+            token(b"Qu", Some((0.1, 0.2)), 0.9),
+            token("é".as_bytes(), Some((0.2, 0.3)), 0.7),
+            token(b"?", Some((0.3, 0.4)), 0.1),
+            token(b" Hola", Some((0.5, 0.8)), 0.9),
+            token(b",", Some((0.8, 0.9)), 0.1),
+        ];
+        let words = group_tokens(&tokens, 0.0);
+
+        assert_eq!(texts(&words), vec!["¿Qué?", "Hola,"]);
+        assert_eq!((words[0].start, words[0].end), (0.0, 0.4));
+        // Punctuation's probabilities don't count
+        assert_eq!(words[0].confidence.unwrap().min, 0.7);
+        assert_eq!(words[1].confidence.unwrap().mean, 0.9);
+    }
+
+    #[test]
+    fn untimed_words_sit_where_the_previous_word_ends() {
+        let tokens = [
+            token(b" uno", None, 0.9),
+            token(b" dos", Some((2.0, 2.5)), 0.9),
+            token(b" tres", None, 0.9),
+        ];
+        let words = group_tokens(&tokens, 1.5);
+
+        let spans: Vec<(f64, f64)> = words.iter().map(|w| (w.start, w.end)).collect();
+        assert_eq!(spans, vec![(1.5, 1.5), (2.0, 2.5), (2.5, 2.5)]);
+    }
+
+    #[test]
+    fn timestamp_and_control_tokens_are_not_text() {
+        let eot = 50257;
+        assert!(is_text_token(0, eot));
+        assert!(is_text_token(eot - 1, eot));
+        assert!(!is_text_token(eot, eot));
+        // Timestamp tokens start after the control tokens
+        assert!(!is_text_token(50364, eot));
+    }
+
+    #[test]
+    fn token_timestamps_are_centiseconds() {
+        assert_eq!(timestamp_seconds(150), 1.5);
+        assert_eq!(token_timing(150, 230), Some((1.5, 2.3)));
+        assert_eq!(token_timing(-1, 230), None);
+        assert_eq!(token_timing(230, 230), None);
+    }
+
+    #[test]
+    fn only_unsure_words_are_flagged() {
+        let words = [
+            word("claro", Some(0.95)),
+            word("Kubernetes", Some(LOW_CONFIDENCE_THRESHOLD - 0.01)),
+            word("justo", Some(LOW_CONFIDENCE_THRESHOLD)),
+            word("insertada", None),
+        ];
+        let flagged: Vec<&str> = low_confidence_words(&words, LOW_CONFIDENCE_THRESHOLD)
+            .iter()
+            .map(|w| w.word.as_str())
+            .collect();
+
+        assert_eq!(flagged, vec!["Kubernetes"]);
+        assert_eq!(confidence(&[]), None);
+    }
+}
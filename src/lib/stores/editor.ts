@@ -1,11 +1,18 @@
-import { writable, derived, type Writable, type Readable } from 'svelte/store';
-import type { TranscriptResult, Word } from '$lib/types/pipeline';
+import { writable, derived, get, type Writable, type Readable } from 'svelte/store';
+import type { TranscriptResult, VideoMetadata, Word } from '$lib/types/pipeline';
+import { applyTranscriptChanges, lowConfidenceWords } from '$lib/utils/tauri';
 
 // Individual stores for editor state
 export const transcript: Writable<TranscriptResult | null> = writable(null);
 export const deletedWordIds: Writable<Set<string>> = writable(new Set());
 export const currentTime: Writable<number> = writable(0);
 export const inputPath: Writable<string | null> = writable(null);
+// Words Whisper was unsure about, flagged for review
+export const lowConfidenceWordIds: Writable<Set<string>> = writable(new Set());
+// LLM corrections kept in the transcript; all of them to start with
+export const acceptedChangeIds: Writable<Set<number>> = writable(new Set());
+// Titles, description, tags and chapters from the LLM, once generated
+export const videoMetadata: Writable<VideoMetadata | null> = writable(null);
 
 // Derived store: compute keep ranges from non-deleted words
 export const keepRanges: Readable<[number, number][]> = derived(
@@ -65,6 +72,13 @@ export function setTranscript(result: TranscriptResult): void {
   inputPath.set(result.input_path);
   deletedWordIds.set(new Set());
   currentTime.set(0);
+  lowConfidenceWordIds.set(new Set());
+  acceptedChangeIds.set(new Set(result.correction?.changes.map((c) => c.id) ?? []));
+  videoMetadata.set(null);
+
+  lowConfidenceWords(result.words)
+    .then((ids) => lowConfidenceWordIds.set(new Set(ids)))
+    .catch((err) => console.error('⚠️ Failed to find low-confidence words:', err));
 }
 
 export function toggleWord(wordId: string): void {
@@ -102,6 +116,31 @@ export function restoreAll(): void {
   deletedWordIds.set(new Set());
 }
 
+// Rebuild the transcript from Whisper's segments with only the accepted corrections
+export async function setChangesAccepted(changeIds: number[], accepted: boolean): Promise<void> {
+  const current = get(transcript);
+  if (!current?.correction) return;
+
+  const ids = new Set(get(acceptedChangeIds));
+  for (const id of changeIds) {
+    if (accepted) {
+      ids.add(id);
+    } else {
+      ids.delete(id);
+    }
+  }
+
+  const { original_segments, changes } = current.correction;
+  const segments = await applyTranscriptChanges(
+    original_segments,
+    changes.filter((c) => ids.has(c.id))
+  );
+  console.log(`📝 ${ids.size} of ${changes.length} corrections accepted`);
+
+  acceptedChangeIds.set(ids);
+  transcript.set({ ...current, segments, words: segments.flatMap((s) => s.words) });
+}
+
 export function setCurrentTime(time: number): void {
   currentTime.set(time);
 }
@@ -111,4 +150,7 @@ export function reset(): void {
   deletedWordIds.set(new Set());
   currentTime.set(0);
   inputPath.set(null);
+  lowConfidenceWordIds.set(new Set());
+  acceptedChangeIds.set(new Set());
+  videoMetadata.set(null);
 }
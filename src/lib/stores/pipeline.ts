@@ -1,5 +1,5 @@
 import { writable, get } from 'svelte/store';
-import type { PipelineConfig, PipelineEvent, PipelineResult } from '$lib/types/pipeline';
+import type { PipelineConfig, PipelineEvent, PipelineResult, Segment } from '$lib/types/pipeline';
 import type { Screen, StepperStage } from '$lib/types/ui';
 
 interface PipelineStore {
@@ -7,6 +7,8 @@ interface PipelineStore {
   selectedFile: string | null;
   config: PipelineConfig;
   stages: StepperStage[];
+  // Text decoded so far while transcribing
+  partialSegments: Segment[];
   result: PipelineResult | null;
   error: string | null;
   isProcessing: boolean;
@@ -18,22 +20,61 @@ const defaultConfig: PipelineConfig = {
   silence_threshold_db: -30.0,
   silence_min_duration: 0.5,
   cut_margin: 0.2,
-  language: null
+  language: null,
+  model: null,
+  task: 'Transcribe',
+  initial_prompt: null,
+  glossary: [],
+  // Matches WhisperPreset::Balanced
+  whisper: {
+    beam_size: null,
+    patience: -1.0,
+    best_of: 5,
+    temperature: 0.0,
+    temperature_increment: 0.2,
+    entropy_threshold: 2.4,
+    logprob_threshold: -1.0,
+    threads: null,
+    max_segment_length: null,
+    split_on_word: false
+  },
+  llm: null,
+  export: {
+    codec: 'H264',
+    encoder: null,
+    rate_control: { Bitrate: { kbps: 8000 } },
+    preset: null,
+    profile: null,
+    mode: 'Reencode',
+    captions: null
+  },
+  force_transcribe: false
 };
 
 const initialStages: StepperStage[] = [
   { id: 'transcribe', label: 'Transcribing Audio', status: 'pending' },
+  { id: 'cleanup', label: 'Cleaning Up Transcript', status: 'pending' },
+  { id: 'translate', label: 'Translating to English', status: 'pending' },
   { id: 'detect_silences', label: 'Detecting Silences', status: 'pending' },
   { id: 'cut_silences', label: 'Cutting Silences', status: 'pending' },
   { id: 'enhance_audio', label: 'Enhancing Audio', status: 'pending' },
   { id: 'export', label: 'Exporting Video', status: 'pending' }
 ];
 
+// The cleanup stage only runs with an LLM configured, the translate stage only when translation is on
+function stagesFor(config: PipelineConfig): StepperStage[] {
+  return initialStages
+    .filter(s => s.id !== 'cleanup' || config.llm !== null)
+    .filter(s => s.id !== 'translate' || config.task === 'Translate')
+    .map(s => ({ ...s }));
+}
+
 const initialState: PipelineStore = {
   screen: 'home',
   selectedFile: null,
   config: { ...defaultConfig },
-  stages: initialStages.map(s => ({ ...s })),
+  stages: stagesFor(defaultConfig),
+  partialSegments: [],
   result: null,
   error: null,
   isProcessing: false
@@ -69,7 +110,8 @@ function createPipelineStore() {
         screen: 'processing',
         isProcessing: true,
         error: null,
-        stages: initialStages.map(s => ({ ...s })),
+        stages: stagesFor(state.config),
+        partialSegments: [],
         result: null
       }));
     },
@@ -87,11 +129,17 @@ function createPipelineStore() {
             s.id === stage ? { ...s, status: 'active' as const, progress: 0 } : s
           );
         } else if ('StageProgress' in event) {
-          const { stage, progress } = event.StageProgress;
+          const { stage, progress, eta_seconds } = event.StageProgress;
           console.log(`⏳ Stage progress: ${stage} - ${(progress * 100).toFixed(1)}%`);
           newState.stages = state.stages.map(s =>
-            s.id === stage ? { ...s, status: 'active' as const, progress } : s
+            s.id === stage
+              ? { ...s, status: 'active' as const, progress, etaSeconds: eta_seconds }
+              : s
           );
+        } else if ('PartialTranscript' in event) {
+          const { segments } = event.PartialTranscript;
+          console.log(`📝 Partial transcript: ${segments.length} new segments`);
+          newState.partialSegments = [...state.partialSegments, ...segments];
         } else if ('StageCompleted' in event) {
           const { stage } = event.StageCompleted;
           console.log(`✅ Stage completed: ${stage}`);
@@ -118,6 +166,11 @@ function createPipelineStore() {
           newState.error = `Pipeline failed: ${error}`;
           newState.screen = 'home';
           newState.isProcessing = false;
+        } else if ('PipelineCancelled' in event) {
+          const { job_id } = event.PipelineCancelled;
+          console.log(`🛑 Pipeline cancelled: ${job_id}`);
+          newState.screen = 'home';
+          newState.isProcessing = false;
         }
 
         return newState;
@@ -128,7 +181,8 @@ function createPipelineStore() {
       set({
         ...initialState,
         config: { ...defaultConfig },
-        stages: initialStages.map(s => ({ ...s }))
+        stages: stagesFor(defaultConfig),
+        partialSegments: []
       });
     }
   };
@@ -4,11 +4,13 @@
 
 export type PipelineEvent =
   | { StageStarted: { stage: string } }
-  | { StageProgress: { stage: string; progress: number } }
+  | { StageProgress: { stage: string; progress: number; eta_seconds: number | null } }
   | { StageCompleted: { stage: string } }
   | { StageFailed: { stage: string; error: string } }
+  | { PartialTranscript: { segments: Segment[] } }
   | { PipelineCompleted: { result: PipelineResult } }
-  | { PipelineFailed: { error: string } };
+  | { PipelineFailed: { error: string } }
+  | { PipelineCancelled: { job_id: string } };
 
 export interface PipelineConfig {
   enhance_audio: boolean;
@@ -17,11 +19,150 @@ export interface PipelineConfig {
   silence_min_duration: number;
   cut_margin: number;
   language: string | null;
+  model: string | null;
+  task: TranscriptionTask;
+  initial_prompt: string | null;
+  glossary: string[];
+  whisper: WhisperParams;
+  // Model for transcript cleanup; null skips it
+  llm: LlmProviderConfig | null;
+  export: ExportSettings;
+  force_transcribe: boolean;
+}
+
+export type VideoCodec = 'H264' | 'Hevc';
+
+export type ExportMode = 'Reencode' | 'SmartCut';
+
+export type RateControl = { Crf: { crf: number } } | { Bitrate: { kbps: number } };
+
+export interface ExportSettings {
+  codec: VideoCodec;
+  encoder: string | null;
+  rate_control: RateControl;
+  preset: string | null;
+  profile: string | null;
+  mode: ExportMode;
+  captions: BurnInCaptions | null;
+}
+
+export type CaptionPosition = 'Top' | 'Middle' | 'Bottom';
+
+export interface CaptionStyle {
+  font: string;
+  font_size: number;
+  bold: boolean;
+  text_color: string;
+  highlight_color: string;
+  outline_color: string;
+  outline_width: number;
+  position: CaptionPosition;
+  margin: number;
+}
+
+export interface BurnInCaptions {
+  style: CaptionStyle;
+  layout: SubtitleSettings;
+}
+
+export interface VideoEncoderInfo {
+  name: string;
+  codec: VideoCodec;
+  hardware: boolean;
+}
+
+export type TranscriptionTask = 'Transcribe' | 'Translate';
+
+export interface WhisperParams {
+  beam_size: number | null;
+  patience: number;
+  best_of: number;
+  temperature: number;
+  temperature_increment: number;
+  entropy_threshold: number;
+  logprob_threshold: number;
+  threads: number | null;
+  max_segment_length: number | null;
+  split_on_word: boolean;
+}
+
+export type WhisperPreset = 'Fast' | 'Balanced' | 'Accurate';
+
+export type LlmProviderConfig =
+  | {
+      OpenAiCompatible: {
+        base_url: string;
+        model: string;
+        api_key: string | null;
+        headers: Record<string, string>;
+      };
+    }
+  | { Ollama: { base_url: string; model: string } }
+  | { Anthropic: { base_url: string; model: string; api_key: string } };
+
+export interface WhisperModelInfo {
+  name: string;
+  file_name: string;
+  path: string;
+  installed: boolean;
+  size_bytes: number;
+  known: boolean;
+  quantized: boolean;
+  multilingual: boolean;
+}
+
+export type ModelVerification =
+  | { Verified: { sha256: string } }
+  | { Mismatch: { expected: string; actual: string } }
+  | { Unknown: { sha256: string } };
+
+export interface ContainerInfo {
+  format_name: string;
+  format_long_name: string | null;
+  duration: number | null;
+  start_time: number;
+  size_bytes: number | null;
+  bit_rate: number | null;
+}
+
+export interface VideoStreamInfo {
+  index: number;
+  codec: string;
+  profile: string | null;
+  level: number | null;
+  width: number;
+  height: number;
+  fps: number | null;
+  rotation: number;
+  pixel_format: string | null;
+  color_transfer: string | null;
+  color_primaries: string | null;
+  hdr: boolean;
+  dolby_vision: boolean;
+  duration: number | null;
+}
+
+export interface AudioStreamInfo {
+  index: number;
+  codec: string;
+  channels: number;
+  channel_layout: string | null;
+  sample_rate: number;
+  language: string | null;
+  duration: number | null;
+}
+
+export interface MediaInfo {
+  path: string;
+  container: ContainerInfo;
+  video_streams: VideoStreamInfo[];
+  audio_streams: AudioStreamInfo[];
 }
 
 export interface PipelineResult {
   output_path: string;
   transcript: Transcript;
+  translation: Transcript | null;
   stats: TranscriptStats;
 }
 
@@ -44,6 +185,8 @@ export interface Segment {
   end: number;
   text: string;
   words: Word[] | null;
+  confidence: Confidence | null;
+  avg_logprob: number | null;
 }
 
 export interface Word {
@@ -51,6 +194,37 @@ export interface Word {
   word: string;
   start: number;
   end: number;
+  confidence: Confidence | null;
+}
+
+// Whisper token probabilities (0-1)
+export interface Confidence {
+  min: number;
+  mean: number;
+}
+
+export type SubtitleFormat = 'Srt' | 'Vtt';
+
+// Written by the LLM for publishing; chapter starts are in source seconds
+export interface VideoMetadata {
+  titles: string[];
+  description: string;
+  tags: string[];
+  chapters: Chapter[];
+}
+
+export interface Chapter {
+  title: string;
+  start: number;
+}
+
+export type ChapterFormat = 'YouTube' | 'Mp4';
+
+export interface SubtitleSettings {
+  max_chars_per_line: number;
+  max_lines: number;
+  min_cue_duration: number;
+  max_cue_duration: number;
 }
 
 export interface TranscriptResult {
@@ -58,4 +232,24 @@ export interface TranscriptResult {
   words: Word[];
   duration_seconds: number;
   input_path: string;
+  language: string | null;
+  translation: Transcript | null;
+  // LLM corrections, already applied to segments
+  correction: TranscriptCorrection | null;
+}
+
+export type ChangeKind = 'Replace' | 'Merge' | 'Split' | 'Insert' | 'Delete';
+
+export interface TranscriptChange {
+  id: number;
+  kind: ChangeKind;
+  segment_id: number;
+  position: number;
+  original: Word[];
+  corrected: Word[];
+}
+
+export interface TranscriptCorrection {
+  original_segments: Segment[];
+  changes: TranscriptChange[];
 }
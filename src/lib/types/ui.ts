@@ -5,4 +5,5 @@ export interface StepperStage {
   label: string;
   status: 'pending' | 'active' | 'completed' | 'failed';
   progress?: number;
+  etaSeconds?: number | null;
 }
@@ -1,6 +1,26 @@
 import { invoke } from '@tauri-apps/api/core';
 import { listen, type UnlistenFn } from '@tauri-apps/api/event';
-import type { PipelineConfig, PipelineEvent, TranscriptResult } from '$lib/types/pipeline';
+import type {
+  Chapter,
+  ChapterFormat,
+  ExportSettings,
+  LlmProviderConfig,
+  MediaInfo,
+  ModelVerification,
+  PipelineConfig,
+  PipelineEvent,
+  Segment,
+  SubtitleFormat,
+  SubtitleSettings,
+  TranscriptChange,
+  TranscriptResult,
+  VideoEncoderInfo,
+  VideoMetadata,
+  WhisperModelInfo,
+  WhisperParams,
+  WhisperPreset,
+  Word
+} from '$lib/types/pipeline';
 import { pipelineStore } from '$lib/stores/pipeline';
 
 let unlistenFn: UnlistenFn | null = null;
@@ -30,20 +50,32 @@ export function stopListening(): void {
 export async function processVideo(
   inputPath: string,
   config: PipelineConfig
-): Promise<void> {
+): Promise<string> {
   console.log('📞 Invoking process_video command');
   console.log('   Input path:', inputPath);
   console.log('   Config:', JSON.stringify(config, null, 2));
 
   try {
-    await invoke('process_video', { inputPath, config });
-    console.log('✅ process_video command invoked successfully');
+    const jobId = await invoke<string>('process_video', { inputPath, config });
+    console.log('✅ process_video command invoked successfully, job:', jobId);
+    return jobId;
   } catch (err) {
     console.error('❌ process_video command failed:', err);
     throw err;
   }
 }
 
+export async function cancelJob(jobId: string): Promise<void> {
+  console.log('📞 Invoking cancel_job command for', jobId);
+  try {
+    await invoke('cancel_job', { jobId });
+    console.log('✅ cancel_job command invoked successfully');
+  } catch (err) {
+    console.error('❌ cancel_job command failed:', err);
+    throw err;
+  }
+}
+
 export async function getFFmpegVersion(): Promise<string> {
   console.log('📞 Invoking get_ffmpeg_version command');
   try {
@@ -56,21 +88,25 @@ export async function getFFmpegVersion(): Promise<string> {
   }
 }
 
+/** `jobId` is ours to pick, so the transcription can be cancelled with `cancelJob` while it runs */
 export async function transcribeVideo(
   inputPath: string,
-  language: string | null = null,
-  llmApiKey: string | null = null
+  config: PipelineConfig,
+  jobId: string
 ): Promise<TranscriptResult> {
-  console.log('📞 Invoking transcribe_video command');
+  console.log('📞 Invoking transcribe_video command, job:', jobId);
   console.log('   Input path:', inputPath);
-  console.log('   Language:', language ?? 'auto-detect');
-  console.log('   LLM cleanup:', llmApiKey ? 'enabled' : 'disabled');
+  console.log('   Language:', config.language ?? 'auto-detect');
+  console.log('   LLM cleanup:', config.llm ? Object.keys(config.llm)[0] : 'disabled');
+  console.log('   Force re-transcribe:', config.force_transcribe);
+  console.log('   Model:', config.model ?? 'default');
+  console.log('   Glossary terms:', config.glossary.length);
 
   try {
     const result = await invoke<TranscriptResult>('transcribe_video', {
       inputPath,
-      language,
-      llmApiKey
+      config,
+      jobId
     });
     console.log('✅ transcribe_video completed');
     console.log('   Segments:', result.segments.length);
@@ -83,21 +119,113 @@ export async function transcribeVideo(
   }
 }
 
+export async function clearTranscriptCache(): Promise<number> {
+  console.log('📞 Invoking clear_transcript_cache command');
+  try {
+    const removed = await invoke<number>('clear_transcript_cache');
+    console.log('✅ Cleared cached transcripts:', removed);
+    return removed;
+  } catch (err) {
+    console.error('❌ clear_transcript_cache command failed:', err);
+    throw err;
+  }
+}
+
+export async function lowConfidenceWords(words: Word[], threshold: number | null = null): Promise<string[]> {
+  console.log('📞 Invoking low_confidence_words command');
+  try {
+    const ids = await invoke<string[]>('low_confidence_words', { words, threshold });
+    console.log('✅ Low-confidence words:', ids.length);
+    return ids;
+  } catch (err) {
+    console.error('❌ low_confidence_words command failed:', err);
+    throw err;
+  }
+}
+
+export async function applyTranscriptChanges(
+  originalSegments: Segment[],
+  changes: TranscriptChange[]
+): Promise<Segment[]> {
+  console.log('📞 Invoking apply_transcript_changes command:', changes.length, 'changes');
+  try {
+    const segments = await invoke<Segment[]>('apply_transcript_changes', { originalSegments, changes });
+    console.log('✅ Applied transcript changes');
+    return segments;
+  } catch (err) {
+    console.error('❌ apply_transcript_changes command failed:', err);
+    throw err;
+  }
+}
+
+export async function whisperPreset(preset: WhisperPreset): Promise<WhisperParams> {
+  console.log('📞 Invoking whisper_preset command:', preset);
+  try {
+    const params = await invoke<WhisperParams>('whisper_preset', { preset });
+    console.log('✅ Whisper preset params:', params);
+    return params;
+  } catch (err) {
+    console.error('❌ whisper_preset command failed:', err);
+    throw err;
+  }
+}
+
+export async function listWhisperModels(): Promise<WhisperModelInfo[]> {
+  console.log('📞 Invoking list_whisper_models command');
+  try {
+    const models = await invoke<WhisperModelInfo[]>('list_whisper_models');
+    console.log('✅ Whisper models installed:', models.filter((m) => m.installed).map((m) => m.name));
+    return models;
+  } catch (err) {
+    console.error('❌ list_whisper_models command failed:', err);
+    throw err;
+  }
+}
+
+export async function importWhisperModel(sourcePath: string): Promise<WhisperModelInfo> {
+  console.log('📞 Invoking import_whisper_model command:', sourcePath);
+  try {
+    const model = await invoke<WhisperModelInfo>('import_whisper_model', { sourcePath });
+    console.log('✅ Imported Whisper model:', model.name);
+    return model;
+  } catch (err) {
+    console.error('❌ import_whisper_model command failed:', err);
+    throw err;
+  }
+}
+
+export async function verifyWhisperModel(name: string): Promise<ModelVerification> {
+  console.log('📞 Invoking verify_whisper_model command:', name);
+  try {
+    const verification = await invoke<ModelVerification>('verify_whisper_model', { name });
+    console.log('✅ Verified Whisper model:', verification);
+    return verification;
+  } catch (err) {
+    console.error('❌ verify_whisper_model command failed:', err);
+    throw err;
+  }
+}
+
 export async function exportEditedVideo(
   inputPath: string,
   keepRanges: [number, number][],
-  enhanceAudio: boolean
+  enhanceAudio: boolean,
+  exportSettings: ExportSettings | null = null,
+  segments: Segment[] | null = null
 ): Promise<string> {
   console.log('📞 Invoking export_edited_video command');
   console.log('   Input path:', inputPath);
   console.log('   Keep ranges:', keepRanges.length);
   console.log('   Enhance audio:', enhanceAudio);
+  console.log('   Export settings:', exportSettings ?? 'default');
 
   try {
     const outputPath = await invoke<string>('export_edited_video', {
       inputPath,
       keepRanges,
-      enhanceAudio
+      enhanceAudio,
+      export: exportSettings,
+      segments
     });
     console.log('✅ export_edited_video completed');
     console.log('   Output path:', outputPath);
@@ -107,3 +235,93 @@ export async function exportEditedVideo(
     throw err;
   }
 }
+
+export async function exportSubtitles(
+  inputPath: string,
+  segments: Segment[],
+  format: SubtitleFormat,
+  keepRanges: [number, number][] | null = null,
+  settings: SubtitleSettings | null = null,
+  language: string | null = null
+): Promise<string> {
+  console.log('📞 Invoking export_subtitles command');
+  console.log('   Format:', format);
+  console.log('   Keep ranges:', keepRanges?.length ?? 'none (original timing)');
+  console.log('   Language tag:', language ?? 'none');
+
+  try {
+    const outputPath = await invoke<string>('export_subtitles', {
+      inputPath,
+      segments,
+      format,
+      settings,
+      keepRanges,
+      language
+    });
+    console.log('✅ Subtitles written to', outputPath);
+    return outputPath;
+  } catch (err) {
+    console.error('❌ export_subtitles command failed:', err);
+    throw err;
+  }
+}
+
+export async function generateMetadata(
+  transcript: TranscriptResult,
+  llm: LlmProviderConfig
+): Promise<VideoMetadata> {
+  console.log('📞 Invoking generate_metadata command with', Object.keys(llm)[0]);
+  try {
+    const metadata = await invoke<VideoMetadata>('generate_metadata', { transcript, llm });
+    console.log('✅ Metadata:', metadata.titles.length, 'titles,', metadata.chapters.length, 'chapters');
+    return metadata;
+  } catch (err) {
+    console.error('❌ generate_metadata command failed:', err);
+    throw err;
+  }
+}
+
+export async function exportChapters(
+  inputPath: string,
+  chapters: Chapter[],
+  format: ChapterFormat,
+  keepRanges: [number, number][] | null = null
+): Promise<string> {
+  console.log('📞 Invoking export_chapters command');
+  console.log('   Format:', format);
+  console.log('   Chapters:', chapters.length);
+  console.log('   Keep ranges:', keepRanges?.length ?? 'none (original timing)');
+
+  try {
+    const outputPath = await invoke<string>('export_chapters', { inputPath, chapters, format, keepRanges });
+    console.log('✅ Chapters written to', outputPath);
+    return outputPath;
+  } catch (err) {
+    console.error('❌ export_chapters command failed:', err);
+    throw err;
+  }
+}
+
+export async function listVideoEncoders(): Promise<VideoEncoderInfo[]> {
+  console.log('📞 Invoking list_video_encoders command');
+  try {
+    const encoders = await invoke<VideoEncoderInfo[]>('list_video_encoders');
+    console.log('✅ Video encoders:', encoders.map((e) => e.name).join(', '));
+    return encoders;
+  } catch (err) {
+    console.error('❌ list_video_encoders command failed:', err);
+    throw err;
+  }
+}
+
+export async function probeMedia(inputPath: string): Promise<MediaInfo> {
+  console.log('📞 Invoking probe_media command:', inputPath);
+  try {
+    const media = await invoke<MediaInfo>('probe_media', { inputPath });
+    console.log('✅ Probed media:', media.container.format_name, media.container.duration);
+    return media;
+  } catch (err) {
+    console.error('❌ probe_media command failed:', err);
+    throw err;
+  }
+}